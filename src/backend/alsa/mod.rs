@@ -3,37 +3,107 @@ extern crate alsa;
 extern crate nix;
 
 use std::mem;
-use std::thread::{Builder, JoinHandle};
-use std::io::{stderr, Write};
+use std::num::{NonZeroU16, NonZeroU32};
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+use std::thread::{self, Builder, JoinHandle, sleep};
+use std::panic;
+use std::fs::File;
+use std::io::{self, stderr, Read, Write};
 use std::ffi::{CString, CStr};
+use std::os::unix::io::RawFd;
+use std::os::unix::thread::JoinHandleExt;
+use std::time::{Duration, Instant, SystemTime};
 
 use self::alsa::{Seq, Direction};
-use self::alsa::seq::{PortInfo, PortSubscribe, Addr, QueueTempo, EventType, PortCap, PortType};
+use self::alsa::seq::{PortInfo, PortSubscribe, Addr, QueueTempo, EventType, PortCap, PortType, Event};
 
-use ::{MidiMessage, Ignore};
+use ::{MidiMessage, Ignore, ManufacturerId};
 use ::errors::*;
+use ::os::linux::{PortCapabilities, OutputMode, CallbackStats, EagainPolicy, NoteOffPolicy, QueueTimerInfo, TimestampSample, SysexChunk, PartialMessagePolicy, PortEvent, ThreadConfig, PortFilter, MessageCallback, SysexChunkCallback};
+#[cfg(feature = "diagnostics")]
+use ::os::linux::{RawEvent, RawEventHook};
+
+/// Reports a problem in the input handler, using the `log` crate if the
+/// `logging` feature is enabled, and writing to stderr otherwise.
+macro_rules! log_warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "logging")]
+        warn!($($arg)*);
+        #[cfg(not(feature = "logging"))]
+        { let _ = writeln!(stderr(), $($arg)*); }
+    }}
+}
+
+/// Reports details of the input handler that are only interesting when
+/// debugging, using the `log` crate if the `logging` feature is enabled.
+macro_rules! log_debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "logging")]
+        debug!($($arg)*);
+        #[cfg(not(feature = "logging"))]
+        { if cfg!(debug_assertions) { let _ = writeln!(stderr(), $($arg)*); } }
+    }}
+}
+
+mod rawmidi;
+pub use self::rawmidi::{RawMidiPort, RawMidiInputConnection, RawMidiOutputConnection, raw_input_ports, raw_output_ports};
 
 mod helpers {
+    use std::ffi::CStr;
+    #[cfg(not(feature = "logging"))]
+    use std::io::{stderr, Write};
+    use super::alsa::{Direction, Error};
     use super::alsa::seq::{Seq, Addr, ClientIter, PortIter, PortInfo, PortCap, MidiEvent, PortType};
     use ::errors::PortInfoError;
+    use ::os::linux::{PortCapabilities, PortFilter};
+
+    /// Opens the sequencer. Opening the `default` device can fail when the ALSA
+    /// configuration is incomplete (e.g. in minimal containers without a default
+    /// sound card), so the `hw` device is tried next, which does not depend on it.
+    /// If both fail, the error of the last attempt is returned.
+    pub fn open_seq(dir: Option<Direction>) -> Result<Seq, Error> {
+        let default = CStr::from_bytes_with_nul(b"default\0").unwrap();
+        if let Ok(seq) = Seq::open(Some(default), dir, true) {
+            return Ok(seq);
+        }
+        let hw = CStr::from_bytes_with_nul(b"hw\0").unwrap();
+        let seq = Seq::open(Some(hw), dir, true)?;
+        log_warn!("could not open the default ALSA sequencer, using {} instead", hw.to_string_lossy());
+        Ok(seq)
+    }
 
     pub fn poll(fds: &mut [super::libc::pollfd], timeout: i32) -> i32 {
         unsafe { super::libc::poll(fds.as_mut_ptr(), fds.len() as super::libc::nfds_t, timeout) }
     }
 
+    fn port_matches(port_type: PortType, filter: &PortFilter) -> bool {
+        let mut enabled = PortType::empty();
+        let mut disabled = PortType::empty();
+        for &(flag, on) in &[(PortType::MIDI_GENERIC, filter.midi_generic),
+                             (PortType::SYNTH, filter.synth),
+                             (PortType::APPLICATION, filter.application)] {
+            if on { enabled |= flag } else { disabled |= flag }
+        }
+        port_type.intersects(enabled) && !port_type.intersects(disabled)
+            && (!filter.hardware_only || port_type.intersects(PortType::HARDWARE | PortType::PORT))
+    }
+
     #[inline]
-    pub fn get_ports<F, T>(s: &Seq, capability: PortCap, f: F) -> Vec<T> where F: Fn(PortInfo) -> T {
+    pub fn get_ports<F, T>(s: &Seq, capability: PortCap, filter: &PortFilter, f: F) -> Vec<T> where F: Fn(PortInfo) -> T {
         ClientIter::new(s).flat_map(|c| PortIter::new(s, c.get_client()))
-                          .filter(|p| p.get_type().intersects(PortType::MIDI_GENERIC | PortType::SYNTH | PortType::APPLICATION))
+                          .filter(|p| port_matches(p.get_type(), filter))
                           .filter(|p| p.get_capability().intersects(capability))
                           .map(f)
                           .collect()
     }
 
     #[inline]
-    pub fn get_port_count(s: &Seq, capability: PortCap) -> usize {
+    pub fn get_port_count(s: &Seq, capability: PortCap, filter: &PortFilter) -> usize {
         ClientIter::new(s).flat_map(|c| PortIter::new(s, c.get_client()))
-                          .filter(|p| p.get_type().intersects(PortType::MIDI_GENERIC | PortType::SYNTH | PortType::APPLICATION))
+                          .filter(|p| port_matches(p.get_type(), filter))
                           .filter(|p| p.get_capability().intersects(capability))
                           .count()
     }
@@ -58,15 +128,44 @@ mod helpers {
         Ok(output)
     }
 
+    /// Gets the name of the client of the sequencer handle itself, as stored by ALSA.
+    pub fn get_own_client_name(s: &Seq) -> Result<String, PortInfoError> {
+        let client_id = s.client_id().map_err(|_| PortInfoError::CannotRetrievePortName)?;
+        let cinfo = s.get_any_client_info(client_id).map_err(|_| PortInfoError::CannotRetrievePortName)?;
+        cinfo.get_name().map(|name| name.to_string()).map_err(|_| PortInfoError::CannotRetrievePortName)
+    }
+
+    #[inline]
+    pub fn get_client_name(s: &Seq, addr: Addr) -> Option<String> {
+        let cinfo = s.get_any_client_info(addr.client).ok()?;
+        cinfo.get_name().ok().map(|name| name.to_string())
+    }
+
+    #[inline]
+    pub fn get_port_capabilities(s: &Seq, addr: Addr) -> Result<PortCapabilities, PortInfoError> {
+        let caps = match s.get_any_port_info(addr) {
+            Ok(p) => p.get_capability(),
+            Err(_) => return Err(PortInfoError::InvalidPort)
+        };
+        Ok(PortCapabilities {
+            read: caps.contains(PortCap::READ),
+            write: caps.contains(PortCap::WRITE),
+            subs_read: caps.contains(PortCap::SUBS_READ),
+            subs_write: caps.contains(PortCap::SUBS_WRITE),
+        })
+    }
+
     pub struct EventDecoder {
         ev: MidiEvent
     }
 
+    unsafe impl Send for EventDecoder {}
+
     impl EventDecoder {
-        pub fn new(merge_commands: bool) -> EventDecoder {
-            let coder = MidiEvent::new(0).unwrap();
+        pub fn new(merge_commands: bool) -> Result<EventDecoder, Error> {
+            let coder = MidiEvent::new(0)?;
             coder.enable_running_status(merge_commands);
-            EventDecoder { ev: coder }
+            Ok(EventDecoder { ev: coder })
         }
 
         #[inline]
@@ -84,11 +183,11 @@ mod helpers {
 
     impl EventEncoder {
         #[inline]
-        pub fn new(buffer_size: u32) -> EventEncoder {
-            EventEncoder {
-                ev: MidiEvent::new(buffer_size).unwrap(),
-                buffer_size: buffer_size
-            }
+        pub fn new(buffer_size: u32) -> Result<EventEncoder, Error> {
+            Ok(EventEncoder {
+                ev: MidiEvent::new(buffer_size)?,
+                buffer_size
+            })
         }
 
         #[inline]
@@ -116,44 +215,647 @@ mod helpers {
 
 const INITIAL_CODER_BUFFER_SIZE: usize = 32;
 
+/// Tempo of the input queue (in microseconds per quarter note) when tick timestamps
+/// are used. This is the default tempo of a Standard MIDI File (120 BPM).
+const TICK_TEMPO: u64 = 500_000;
+
+/// Size of `snd_seq_event_t`, which is also the size of a cell in the kernel event pool.
+const SEQ_EVENT_SIZE: usize = 28;
+/// Default size of the output buffer of a client in `libasound` (which midir does not change).
+const SEQ_OUTPUT_BUFFER_SIZE: usize = 16 * 1024;
+/// Default number of cells in the output pool of a client in the kernel (which midir does not change).
+const SEQ_OUTPUT_POOL_CELLS: usize = 500;
+/// Maximum number of cells in the input pool (FIFO) of a client in the kernel (`SNDRV_SEQ_MAX_CLIENT_EVENTS`).
+/// Larger values are silently ignored by the kernel. The default is 200.
+const SEQ_INPUT_POOL_CELLS_MAX: usize = 2000;
+
+/// Size of the buffer that incoming events are decoded into. The ALSA documentation
+/// says that the decoded form of a sequencer event takes at most 12 bytes, except
+/// for System Exclusive events (which are copied directly). If an event ever needs
+/// more, ALSA refuses to decode it instead of truncating it, which is reported.
+const DECODE_BUFFER_SIZE: usize = 12;
+
+/// Default for the maximum size of a SysEx message that is assembled from its chunks.
+const DEFAULT_MAX_SYSEX_SIZE: usize = 1024 * 1024;
+
+/// Sequencer client that reads the time of input queues (see `timestamp_epochs`),
+/// because the clients of the connections belong to their handler threads. It is
+/// opened on first use and kept until the process exits, so that other applications
+/// do not see a new client appear for every read.
+static QUEUE_READER: Mutex<Option<Seq>> = Mutex::new(None);
+
+/// Reads the real time of the given input queues one after another, against a single
+/// sample of the monotonic clock, and returns the point in time at which each of
+/// them was zero (`None` for queues that can not be read).
+pub fn timestamp_epochs(queue_ids: &[Option<i32>]) -> Vec<Option<Instant>> {
+    let mut reader = QUEUE_READER.lock().unwrap_or_else(|e| e.into_inner());
+    if reader.is_none() {
+        *reader = helpers::open_seq(None).ok();
+        if let Some(ref seq) = *reader {
+            let _ = seq.set_client_name(&CString::new("midir queue reader").unwrap());
+        }
+    }
+    let seq = match *reader {
+        Some(ref seq) => seq,
+        None => return vec![None; queue_ids.len()]
+    };
+    // Bracket the reads with two samples of the monotonic clock and assume that all of them happened halfway
+    let before = Instant::now();
+    let times: Vec<Option<Duration>> = queue_ids.iter().map(|id| {
+        id.and_then(|id| seq.get_queue_status(id).ok()).map(|status| status.get_real_time())
+    }).collect();
+    let after = Instant::now();
+    let now = before + (after - before) / 2;
+    times.into_iter().map(|time| time.and_then(|time| now.checked_sub(time))).collect()
+}
+
+/// Finds the timer of the given queue in the contents of `/proc/asound/seq/timer`,
+/// which has one entry per running queue in the following form:
+///
+/// ```text
+/// Timer for queue 0 : system timer
+///   Period time : 0.004000000
+///   Skew : 65536 / 65536
+/// ```
+fn parse_queue_timer(info: &str, queue_id: i32) -> Option<QueueTimerInfo> {
+    let header = format!("Timer for queue {} : ", queue_id);
+    let mut lines = info.lines().skip_while(|line| !line.starts_with(&header));
+    let name = lines.next()?[header.len()..].trim().to_string();
+    let period = lines.take_while(|line| !line.starts_with("Timer for queue"))
+                      .filter_map(|line| line.trim().split_once("Period time :").map(|(_, period)| period))
+                      .next()?
+                      .trim();
+
+    let mut parts = period.splitn(2, '.');
+    let secs = parts.next()?.parse::<u64>().ok()?;
+    let nanos = match parts.next() {
+        // Pad or cut the fractional part to nanoseconds
+        Some(frac) => format!("{:0<9.9}", frac).parse::<u32>().ok()?,
+        None => 0
+    };
+    Some(QueueTimerInfo {
+        name,
+        resolution: Duration::new(secs, nanos)
+    })
+}
+
+/// Whether a SysEx event contains a complete message that can be delivered
+/// without assembling it (see `MidiInputExt::set_zero_copy_sysex`).
+fn is_single_event_sysex(chunk: &[u8], max_sysex_size: usize) -> bool {
+    chunk.len() >= 2 && chunk[0] == 0xF0 && chunk[chunk.len() - 1] == 0xF7 && chunk.len() <= max_sysex_size
+}
+
+/// Whether the message has as many bytes as its status byte requires
+/// (SysEx messages are always considered to be complete).
+fn is_complete_message(bytes: &[u8]) -> bool {
+    let expected = match bytes[0] {
+        0xF0 => return true,
+        0x00..=0x7F => return false, // the status byte is missing
+        0xC0..=0xDF | 0xF1 | 0xF3 => 2,
+        0x80..=0xEF | 0xF2 => 3,
+        _ => 1
+    };
+    bytes.len() >= expected
+}
+
+/// Run the given bytes through the same encoder and decoder that are used
+/// for sending and receiving messages, and return the bytes that a receiving
+/// midir application would get (concatenated, if the bytes contain several
+/// messages). This shows how the sequencer transforms messages, e.g. running
+/// status is expanded into full messages, and System Exclusive messages are
+/// passed through unchanged. No ALSA ports are opened and no I/O takes place.
+///
+/// An error is returned if the encoder or the decoder reject the bytes.
+/// Bytes that do not form a complete message at the end are not returned.
+pub fn encode_decode_roundtrip(bytes: &[u8]) -> Result<Vec<u8>, SendError> {
+    let mut encoder = helpers::EventEncoder::new(cmp::max(bytes.len(), INITIAL_CODER_BUFFER_SIZE) as u32)
+        .map_err(|ref e| SendError::Backend(backend_error("could not create ALSA event encoder", e)))?;
+    let mut decoder = helpers::EventDecoder::new(false)
+        .map_err(|ref e| SendError::Backend(backend_error("could not create ALSA event decoder", e)))?;
+    let mut decoded = [0; DECODE_BUFFER_SIZE];
+    let mut result = Vec::with_capacity(bytes.len());
+
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let (consumed, ev) = match encoder.get_wrapped().encode(&bytes[pos..]) {
+            Ok(r) => r,
+            Err(_) => return Err(SendError::InvalidData("ALSA encoder reported invalid data"))
+        };
+        pos += consumed;
+        let mut ev = match ev {
+            Some(ev) => ev,
+            None if consumed == 0 => break,
+            None => continue
+        };
+        if ev.get_type() == EventType::Sysex {
+            // SysEx data is passed on directly, like in `handle_input`
+            result.extend_from_slice(ev.get_ext().unwrap_or(&[]));
+        } else {
+            match decoder.get_wrapped().decode(&mut decoded, &mut ev) {
+                Ok(nbytes) => result.extend_from_slice(&decoded[..nbytes]),
+                Err(_) => return Err(SendError::InvalidData("ALSA decoder reported invalid data"))
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Counts the MIDI messages in the bytes of a decoded event, by counting
+/// the status bytes (a System Exclusive message extends to the end).
+fn message_count(bytes: &[u8]) -> usize {
+    let mut count = 0;
+    for &byte in bytes {
+        if byte & 0x80 != 0 {
+            count += 1;
+            if byte == 0xF0 { break; }
+        }
+    }
+    count
+}
+
+/// Returns the length of the first message in bytes that may contain several
+/// messages (see `message_count`).
+fn first_message_len(bytes: &[u8]) -> usize {
+    if bytes[0] == 0xF0 {
+        return bytes.len();
+    }
+    bytes[1..].iter().position(|&byte| byte & 0x80 != 0).map_or(bytes.len(), |pos| pos + 1)
+}
+
+/// Converts an ALSA error into a `BackendError` with the given description.
+fn backend_error(msg: &'static str, err: &alsa::Error) -> BackendError {
+    BackendError::new(msg, err.errno().map(|e| e as i32))
+}
+
+#[inline]
+fn duration_to_micros(d: Duration) -> u64 {
+    (d.as_secs() * 1_000_000) + (d.subsec_nanos() as u64 / 1_000)
+}
+
+fn duration_to_nanos(d: Duration) -> u64 {
+    (d.as_secs() * 1_000_000_000) + d.subsec_nanos() as u64
+}
+
 pub struct MidiInput {
     ignore_flags: Ignore,
     seq: Option<Seq>,
+    config: Box<InputConfig>, // boxed, so that errors that return the `MidiInput` stay small
+}
+
+/// Configuration of a `MidiInput` that is passed on to the handler thread.
+#[derive(Default)]
+struct InputConfig {
+    reorder_delay: Duration,
+    max_retained_capacity: Option<usize>,
+    measure_callback_time: bool,
+    sysex_filter: Option<Vec<ManufacturerId>>,
+    eagain_policy: EagainPolicy,
+    #[cfg(feature = "diagnostics")]
+    raw_event_hook: Option<RawEventHook>,
+    external_polling: bool,
+    system_common_callback: Option<MessageCallback>,
+    connection_callback: Option<Box<dyn FnMut(PortEvent) + Send>>,
+    timestamp_log_capacity: usize,
+    sysex_chunk_callback: Option<SysexChunkCallback>,
+    partial_message_policy: PartialMessagePolicy,
+    partial_message_callback: Option<MessageCallback>,
+    split_merged_messages: bool,
+    timestamp_offset: Duration,
+    nanosecond_timestamps: bool,
+    private_ports: bool,
+    tick_ppq: Option<u16>,
+    queue_tempo: Option<(u32, u16)>,
+    detach_on_drop: bool,
+    max_sysex_size: Option<usize>,
+    zero_copy_sysex: bool,
+    input_pool_cells: Option<usize>,
+    thread_config: ThreadConfig,
+    unsubscribe_timeout: Option<Duration>,
+    port_filter: PortFilter,
+    timestamping: Option<bool>,
 }
 
-#[derive(Clone, PartialEq)]
+impl InputConfig {
+    /// Whether the input queue is used to timestamp events. This defaults to
+    /// the opposite of the `avoid_timestamping` feature.
+    fn timestamping(&self) -> bool {
+        self.timestamping.unwrap_or(!cfg!(feature = "avoid_timestamping"))
+    }
+
+    /// The maximum size of a SysEx message that is assembled from its chunks.
+    fn max_sysex_size(&self) -> usize {
+        self.max_sysex_size.unwrap_or(DEFAULT_MAX_SYSEX_SIZE)
+    }
+
+    /// The tempo of the input queue in microseconds per quarter note, which
+    /// determines the duration of a tick when tick timestamps are used.
+    fn tick_tempo(&self) -> u64 {
+        self.queue_tempo.map_or(TICK_TEMPO, |(tempo, _)| tempo as u64)
+    }
+
+    /// Converts a duration into the unit of the timestamps that are passed to the callbacks.
+    fn to_timestamp(&self, d: Duration) -> u64 {
+        match self.tick_ppq {
+            Some(ppq) => duration_to_micros(d) * ppq as u64 / self.tick_tempo(),
+            None if self.nanosecond_timestamps => duration_to_nanos(d),
+            None => duration_to_micros(d)
+        }
+    }
+
+    /// Converts a difference of timestamps into milliseconds (rounding up).
+    fn to_millis(&self, t: u64) -> u64 {
+        match self.tick_ppq {
+            Some(ppq) => (t * self.tick_tempo()).div_ceil(ppq as u64 * 1000),
+            None => {
+                let per_ms = self.to_timestamp(Duration::from_millis(1));
+                t.div_ceil(per_ms)
+            }
+        }
+    }
+
+    /// Calculates the timestamp of an event from its time (real time or ticks,
+    /// depending on how the port is stamped), compensating for the latency of the device.
+    fn event_timestamp(&self, ev: &Event) -> u64 {
+        if !self.timestamping() {
+            return 0;
+        }
+        match self.tick_ppq {
+            Some(_) => (ev.get_tick().unwrap_or(0) as u64).saturating_sub(self.to_timestamp(self.timestamp_offset)),
+            None => {
+                let time = ev.get_time().unwrap();
+                self.to_timestamp(time.checked_sub(self.timestamp_offset).unwrap_or(Duration::from_secs(0)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiInputPort {
     addr: Addr
 }
 
 pub struct MidiInputConnection<T: 'static> {
-    subscription: Option<PortSubscribe>,
-    thread: Option<JoinHandle<(HandlerData<T>, T)>>,
+    subscription: Option<(Addr, Addr)>, // sender and destination
+    handler: Option<InputHandler<T>>,
     vport: i32, // TODO: probably port numbers are only u8, therefore could use Option<u8>
+    client_id: i32, // the sequencer is owned by the handler, so the client id is kept here
     trigger_send_fd: i32,
+    trigger_rcv_fd: i32, // owned by the handler, only closed here if the handler thread panicked
+    shared: Arc<SharedState>,
+    source_timing: Option<bool>,
+    queue_id: i32,
+    timestamping: bool,
+    detach_on_drop: bool,
 }
 
 struct HandlerData<T: 'static> {
-    ignore_flags: Ignore,
     seq: Seq,
     trigger_rcv_fd: i32,
     callback: Box<dyn FnMut(u64, &[u8], &mut T) + Send>,
     queue_id: i32, // an input queue is needed to get timestamped events
+    shared: Arc<SharedState>,
+    config: Box<InputConfig>,
+    subscription: Option<(Addr, Addr)>, // sender and destination of the subscription made by `connect` or `rewire`
+}
+
+/// Where the input of a connection is processed.
+enum InputHandler<T: 'static> {
+    /// In a handler thread, which returns the handler data and the user data when it is stopped
+    Thread(JoinHandle<(HandlerData<T>, T)>),
+    /// In `process_ready_events`, which is called by an external event loop
+    External(HandlerData<T>, T, InputProcessor),
+}
+
+impl<T> HandlerData<T> {
+    /// The descriptors that need to be polled for input: the trigger pipe,
+    /// followed by those of the sequencer.
+    fn poll_fds(&self) -> Result<Vec<self::libc::pollfd>, BackendError> {
+        use self::alsa::PollDescriptors;
+
+        let poll_desc_info = (&self.seq, Some(Direction::Capture));
+        let trigger = self::libc::pollfd { fd: self.trigger_rcv_fd, events: self::libc::POLLIN, revents: 0 };
+        let mut poll_fds = vec![trigger; poll_desc_info.count() + 1];
+        // The number of descriptors may have changed since `count`, so only keep those that were filled in
+        let filled = poll_desc_info.fill(&mut poll_fds[1..])
+            .map_err(|ref e| backend_error("could not get ALSA poll descriptors", e))?;
+        poll_fds.truncate(filled + 1);
+        Ok(poll_fds)
+    }
+
+    /// Keeps the source port of the connection (see `MidiInputConnection::source_name`)
+    /// up to date when a subscription to the port of the connection is made or removed.
+    /// For a connection made by `connect` (or `rewire`), the name of its source is
+    /// resolved again when it subscribes, e.g. after it has been renamed. For a virtual
+    /// port, the source is the port that subscribed most recently, until it unsubscribes.
+    fn update_source(&self, sender: Addr, dest: Addr, subscribed: bool) {
+        if dest.client != self.seq.client_id().unwrap_or(-1) {
+            return;
+        }
+        let mut source = self.shared.source.lock().unwrap();
+        match self.subscription {
+            Some((own_sender, _)) if subscribed && own_sender == sender => {
+                *source = Some((sender, helpers::get_port_name(&self.seq, sender).ok()));
+            },
+            Some(_) => {},
+            None if subscribed => {
+                *source = Some((sender, helpers::get_port_name(&self.seq, sender).ok()));
+            },
+            None => {
+                if source.as_ref().is_some_and(|&(addr, _)| addr == sender) {
+                    *source = None;
+                }
+            }
+        }
+    }
+
+    /// Moves the subscription of the connection to another source port (see
+    /// `MidiInputConnection::rewire`). The new subscription is made before the
+    /// old one is removed, so that the connection stays as it is if that fails.
+    /// This uses the sequencer handle of the handler, because other clients may
+    /// not subscribe private ports (see `MidiInputExt::set_private_ports`).
+    fn rewire(&mut self, sender: Addr, dest: Addr) -> Result<SourceInfo, ConnectError<()>> {
+        let src_pinfo = match self.seq.get_any_port_info(sender) {
+            Ok(p) => p,
+            Err(_) => return Err(ConnectError::new(ConnectErrorKind::InvalidPort, ()))
+        };
+        let source = SourceInfo {
+            name: helpers::get_port_name(&self.seq, sender).ok(),
+            timing: source_has_timing(&src_pinfo, self.config.timestamping())
+        };
+        if self.subscription == Some((sender, dest)) {
+            return Ok(source);
+        }
+
+        let sub = match PortSubscribe::empty() {
+            Ok(sub) => sub,
+            Err(ref e) => return Err(ConnectError::from_backend(backend_error("could not allocate ALSA port subscription", e), ()))
+        };
+        sub.set_sender(sender);
+        sub.set_dest(dest);
+        if let Err(ref e) = self.seq.subscribe_port(&sub) {
+            return Err(ConnectError::from_backend(backend_error("could not create ALSA input subscription", e), ()));
+        }
+        // The notification about the removal of the old subscription does not
+        // match the new one, so the source is not reported as lost
+        if let Some((old_sender, old_dest)) = self.subscription.replace((sender, dest)) {
+            let _ = self.seq.unsubscribe_port(old_sender, old_dest);
+        }
+        self.shared.source_lost.store(false, Ordering::Relaxed);
+        Ok(source)
+    }
+}
+
+/// Starts processing the input of a connection, which happens in a new handler thread
+/// unless external polling is enabled.
+/// Starts processing the input of a connection. If the handler thread can not
+/// be started, the handler data is returned with the error, so that the caller
+/// can release the resources of the connection.
+fn start_input_handler<T: Send>(
+    handler_data: HandlerData<T>, processor: InputProcessor, data: T, threadbuilder: Builder
+) -> Result<InputHandler<T>, (BackendError, HandlerData<T>)> {
+    if handler_data.config.external_polling {
+        return Ok(InputHandler::External(handler_data, data, processor));
+    }
+
+    // The handler data is only sent to the thread once it is running, because
+    // a closure that it is moved into would be lost if spawning fails
+    let rt_priority = handler_data.config.thread_config.rt_priority;
+    let (sender, receiver) = mpsc::channel();
+    let handle = match threadbuilder.spawn(move || {
+        let (handler_data, processor, mut d) = receiver.recv().unwrap();
+        let h = handle_input(handler_data, processor, &mut d);
+        (h, d) // return both the handler data and the user data 
+    }) {
+        Ok(handle) => handle,
+        Err(e) => return Err((BackendError::new("could not start ALSA input handler thread", e.raw_os_error()), handler_data))
+    };
+    let _ = sender.send((handler_data, processor, data));
+    if let Some(priority) = rt_priority {
+        set_realtime_priority(&handle, priority);
+    }
+    Ok(InputHandler::Thread(handle))
+}
+
+/// Switches a spawned thread to the `SCHED_FIFO` policy. Failing to do so is
+/// not fatal (the thread keeps running at normal priority), but is reported.
+fn set_realtime_priority<R>(handle: &JoinHandle<R>, priority: i32) {
+    let param = self::libc::sched_param { sched_priority: priority };
+    let res = unsafe { self::libc::pthread_setschedparam(handle.as_pthread_t(), self::libc::SCHED_FIFO, &param) };
+    if res != 0 {
+        log_warn!("could not set real-time priority {} for the ALSA input handler ({})", priority, io::Error::from_raw_os_error(res));
+    }
+}
+
+/// Whether the events of a source port arrive at the time they are meant for
+/// (see `MidiInputConnectionExt::source_has_timing`).
+fn source_has_timing(src_pinfo: &PortInfo, timestamping: bool) -> bool {
+    // Hardware ports deliver events as they arrive on the wire, and applications that
+    // timestamp their own port presumably send events at the time they are meant for
+    timestamping && (
+        src_pinfo.get_type().intersects(PortType::HARDWARE | PortType::PORT) || src_pinfo.get_timestamping()
+    )
+}
+
+/// The name and timing of the source port of a connection after rewiring.
+struct SourceInfo {
+    name: Option<String>,
+    timing: bool,
+}
+
+/// A request to the handler thread to move the subscription of a connection
+/// to another source port, which is answered through `done`.
+struct RewireRequest {
+    sender: Addr,
+    dest: Addr,
+    done: mpsc::Sender<Result<SourceInfo, ConnectError<()>>>,
+}
+
+/// State that is shared between a `MidiInputConnection` and its handler thread.
+struct SharedState {
+    ignore_flags: AtomicU8, // read for every event, so that the flags can be changed while connected
+    flush_requested: AtomicBool,
+    queue_paused: AtomicBool, // the state of the input queue that was requested by the user
+    queue_change_requested: AtomicBool,
+    timing: CallbackTiming,
+    eagain_count: AtomicU64,
+    partial_message_count: AtomicU64,
+    overrun_count: AtomicU64,
+    merged_message_count: AtomicU64,
+    overrun: AtomicBool, // latched until it is cleared by the user
+    source_lost: AtomicBool, // set when the subscription to the source port has been removed
+    handler_stopped: AtomicBool, // set when the input handler has stopped because of an error
+    source: Mutex<Option<(Addr, Option<String>)>>, // the address and name of the source port, updated by the handler
+    rewire_requested: AtomicBool,
+    rewire_request: Mutex<Option<RewireRequest>>,
+    started: Instant,
+    timestamp_log: Mutex<TimestampLog>,
+}
+
+/// The timestamps that have been recorded, but not yet been taken by the user.
+struct TimestampLog {
+    samples: Vec<TimestampSample>, // preallocated, so that recording does not allocate
+    dropped: u64,
+}
+
+impl SharedState {
+    fn new(timestamp_log_capacity: usize, ignore_flags: Ignore) -> SharedState {
+        SharedState {
+            ignore_flags: AtomicU8::new(ignore_flags as u8),
+            flush_requested: AtomicBool::new(false),
+            queue_paused: AtomicBool::new(false),
+            queue_change_requested: AtomicBool::new(false),
+            timing: CallbackTiming::new(),
+            eagain_count: AtomicU64::new(0),
+            partial_message_count: AtomicU64::new(0),
+            overrun_count: AtomicU64::new(0),
+            merged_message_count: AtomicU64::new(0),
+            overrun: AtomicBool::new(false),
+            source_lost: AtomicBool::new(false),
+            handler_stopped: AtomicBool::new(false),
+            source: Mutex::new(None),
+            rewire_requested: AtomicBool::new(false),
+            rewire_request: Mutex::new(None),
+            started: Instant::now(),
+            timestamp_log: Mutex::new(TimestampLog {
+                samples: Vec::with_capacity(timestamp_log_capacity),
+                dropped: 0
+            }),
+        }
+    }
+
+    fn log_timestamp(&self, event_time: u64, received: u64) {
+        let mut log = self.timestamp_log.lock().unwrap();
+        if log.samples.len() < log.samples.capacity() {
+            log.samples.push(TimestampSample { event_time, received });
+        } else {
+            log.dropped += 1;
+        }
+    }
+}
+
+/// Reacts to `EAGAIN` from `event_input`, which means that no event was available.
+fn on_eagain(policy: EagainPolicy, count: &AtomicU64, log: &mut dyn Write) {
+    count.fetch_add(1, Ordering::Relaxed);
+    match policy {
+        EagainPolicy::Silent => {},
+        EagainPolicy::Log => {
+            #[cfg(feature = "logging")]
+            { let _ = log; warn!("no input event from ALSA MIDI input buffer"); }
+            #[cfg(not(feature = "logging"))]
+            { let _ = writeln!(log, "\nError in handle_input: no input event from ALSA MIDI input buffer!\n"); }
+        },
+        EagainPolicy::Backoff(delay) => sleep(delay)
+    }
+}
+
+/// Measures how long the user callback takes, compared to the time between messages.
+///
+/// The time between messages is measured with the wall clock when the callback is
+/// called, because the timestamps can be in microseconds, nanoseconds or ticks.
+struct CallbackTiming {
+    origin: Instant,
+    calls: AtomicU64,
+    total_nanos: AtomicU64,
+    first_call: AtomicU64, // in nanoseconds since `origin`
+    last_call: AtomicU64,
+}
+
+impl CallbackTiming {
+    fn new() -> CallbackTiming {
+        CallbackTiming {
+            origin: Instant::now(),
+            calls: AtomicU64::new(0),
+            total_nanos: AtomicU64::new(0),
+            first_call: AtomicU64::new(0),
+            last_call: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, start: Instant, duration: Duration) {
+        let call = duration_to_nanos(start.duration_since(self.origin));
+        // Only the handler thread writes, so there are no concurrent updates
+        if self.calls.fetch_add(1, Ordering::Relaxed) == 0 {
+            self.first_call.store(call, Ordering::Relaxed);
+        }
+        self.last_call.store(call, Ordering::Relaxed);
+        self.total_nanos.fetch_add(duration_to_nanos(duration), Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> CallbackStats {
+        let calls = self.calls.load(Ordering::Relaxed);
+        let total_nanos = self.total_nanos.load(Ordering::Relaxed);
+        let span = self.last_call.load(Ordering::Relaxed).saturating_sub(self.first_call.load(Ordering::Relaxed));
+        CallbackStats {
+            calls,
+            average_duration: Duration::from_nanos(total_nanos.checked_div(calls).unwrap_or(0)),
+            average_interval: if calls > 1 { Some(Duration::from_nanos(span / (calls - 1))) } else { None }
+        }
+    }
+}
+
+#[inline]
+fn invoke_callback<T>(callback: &mut dyn FnMut(u64, &[u8], &mut T), timing: Option<&CallbackTiming>,
+                      timestamp: u64, bytes: &[u8], user_data: &mut T) {
+    match timing {
+        None => callback(timestamp, bytes, user_data),
+        Some(timing) => {
+            let start = Instant::now();
+            callback(timestamp, bytes, user_data);
+            timing.record(start, start.elapsed());
+        }
+    }
+}
+
+/// Holds back messages for a fixed delay, so that messages from different
+/// sources can be delivered in the order of their timestamps.
+struct ReorderBuffer {
+    delay: u64, // in the unit of the timestamps
+    messages: VecDeque<(u64, Vec<u8>)>,
+}
+
+impl ReorderBuffer {
+    fn new(delay: u64) -> ReorderBuffer {
+        ReorderBuffer {
+            delay,
+            messages: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, timestamp: u64, bytes: &[u8]) {
+        // Messages mostly arrive in order, so search for the position from the back.
+        // Messages with equal timestamps keep the order in which they arrived.
+        let pos = self.messages.iter().rposition(|&(t, _)| t <= timestamp).map_or(0, |p| p + 1);
+        self.messages.insert(pos, (timestamp, bytes.to_vec()));
+    }
+
+    /// Returns the next message if it has been held back for long enough.
+    fn pop_due(&mut self, now: u64) -> Option<(u64, Vec<u8>)> {
+        match self.next_due() {
+            Some(due) if due <= now => self.messages.pop_front(),
+            _ => None
+        }
+    }
+
+    /// Returns the time at which the next message is due.
+    fn next_due(&self) -> Option<u64> {
+        self.messages.front().map(|&(t, _)| t + self.delay)
+    }
 }
 
 impl MidiInput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        let seq = match Seq::open(None, None, true) {
+        let seq = match helpers::open_seq(None) {
             Ok(s) => s,
-            Err(_) => { return Err(InitError); }
+            Err(ref e) => { return Err(InitError::new(Some(backend_error("could not open ALSA sequencer", e)))); }
         };
         
-        let c_client_name = CString::new(client_name).map_err(|_| InitError)?;
-        seq.set_client_name(&c_client_name).map_err(|_| InitError)?;
+        let c_client_name = CString::new(client_name).map_err(|_| InitError::new(None))?;
+        seq.set_client_name(&c_client_name).map_err(|ref e| InitError::new(Some(backend_error("could not set ALSA client name", e))))?;
         
         Ok(MidiInput {
             ignore_flags: Ignore::None,
             seq: Some(seq),
+            config: Box::default(),
         })
     }
     
@@ -161,73 +863,263 @@ impl MidiInput {
         self.ignore_flags = flags;
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiInputPort> {
-        helpers::get_ports(self.seq.as_ref().unwrap(), PortCap::READ | PortCap::SUBS_READ, |p| ::common::MidiInputPort {
-            imp: MidiInputPort {
-                addr: p.addr()
-            }
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.ignore_flags.contains(flags)
+    }
+
+    pub fn set_reorder_delay(&mut self, delay: Duration) {
+        self.config.reorder_delay = delay;
+    }
+
+    pub fn set_max_retained_capacity(&mut self, capacity: Option<usize>) {
+        self.config.max_retained_capacity = capacity;
+    }
+
+    pub fn set_measure_callback_time(&mut self, enabled: bool) {
+        self.config.measure_callback_time = enabled;
+    }
+
+    pub fn set_sysex_filter(&mut self, manufacturers: Option<Vec<ManufacturerId>>) {
+        self.config.sysex_filter = manufacturers;
+    }
+
+    pub fn set_eagain_policy(&mut self, policy: EagainPolicy) {
+        self.config.eagain_policy = policy;
+    }
+
+    #[cfg(feature = "diagnostics")]
+    pub fn set_raw_event_hook(&mut self, hook: Option<RawEventHook>) {
+        self.config.raw_event_hook = hook;
+    }
+
+    pub fn set_external_polling(&mut self, enabled: bool) {
+        self.config.external_polling = enabled;
+    }
+
+    pub fn set_system_common_callback(&mut self, callback: Option<MessageCallback>) {
+        self.config.system_common_callback = callback;
+    }
+
+    pub fn set_max_sysex_size(&mut self, size: usize) {
+        self.config.max_sysex_size = Some(size);
+    }
+
+    pub fn set_zero_copy_sysex(&mut self, enabled: bool) {
+        self.config.zero_copy_sysex = enabled;
+    }
+
+    pub fn set_connection_callback(&mut self, callback: Option<Box<dyn FnMut(PortEvent) + Send>>) {
+        self.config.connection_callback = callback;
+    }
+
+    pub fn set_timestamp_log_capacity(&mut self, capacity: usize) {
+        self.config.timestamp_log_capacity = capacity;
+    }
+
+    pub fn set_sysex_chunk_callback(&mut self, callback: Option<SysexChunkCallback>) {
+        self.config.sysex_chunk_callback = callback;
+    }
+
+    pub fn set_partial_message_policy(&mut self, policy: PartialMessagePolicy) {
+        self.config.partial_message_policy = policy;
+    }
+
+    pub fn set_split_merged_messages(&mut self, enabled: bool) {
+        self.config.split_merged_messages = enabled;
+    }
+
+    pub fn set_partial_message_callback(&mut self, callback: Option<MessageCallback>) {
+        self.config.partial_message_callback = callback;
+    }
+
+    pub fn set_timestamp_offset(&mut self, offset: Duration) {
+        self.config.timestamp_offset = offset;
+    }
+
+    pub fn set_nanosecond_timestamps(&mut self, enabled: bool) {
+        self.config.nanosecond_timestamps = enabled;
+    }
+
+    pub fn set_private_ports(&mut self, enabled: bool) {
+        self.config.private_ports = enabled;
+    }
+
+    pub fn set_port_filter(&mut self, filter: PortFilter) {
+        self.config.port_filter = filter;
+    }
+
+    pub fn set_detach_on_drop(&mut self, enabled: bool) {
+        self.config.detach_on_drop = enabled;
+    }
+
+    pub fn set_thread_config(&mut self, config: ThreadConfig) {
+        self.config.thread_config = config;
+    }
+
+    pub fn set_unsubscribe_timeout(&mut self, timeout: Option<Duration>) {
+        self.config.unsubscribe_timeout = timeout;
+    }
+
+    pub fn set_timestamping(&mut self, enabled: bool) {
+        self.config.timestamping = Some(enabled);
+    }
+
+    pub fn set_tick_timestamps(&mut self, ppq: Option<NonZeroU16>) {
+        self.config.tick_ppq = ppq.map(NonZeroU16::get);
+    }
+
+    pub fn set_input_buffer_size(&mut self, bytes: usize) -> Result<(), BackendError> {
+        if !(SEQ_EVENT_SIZE..=SEQ_INPUT_POOL_CELLS_MAX * SEQ_EVENT_SIZE).contains(&bytes) {
+            return Err(BackendError::new("invalid ALSA input buffer size", Some(self::nix::errno::Errno::EINVAL as i32)));
+        }
+        self.config.input_pool_cells = Some(bytes / SEQ_EVENT_SIZE);
+        Ok(())
+    }
+
+    /// The tempo (in microseconds per quarter note) that determines the duration
+    /// of a tick when tick timestamps are used.
+    pub(crate) fn tick_tempo(&self) -> u32 {
+        self.config.tick_tempo() as u32
+    }
+
+    pub fn set_queue_tempo(&mut self, tempo: NonZeroU32, ppq: NonZeroU16) {
+        self.config.queue_tempo = Some((tempo.get(), ppq.get()));
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<MidiInputPort> {
+        helpers::get_ports(self.seq.as_ref().unwrap(), PortCap::READ | PortCap::SUBS_READ, &self.config.port_filter, |p| MidiInputPort {
+            addr: p.addr()
         })
     }
     
     pub fn port_count(&self) -> usize {
-        helpers::get_port_count(self.seq.as_ref().unwrap(), PortCap::READ | PortCap::SUBS_READ)
+        helpers::get_port_count(self.seq.as_ref().unwrap(), PortCap::READ | PortCap::SUBS_READ, &self.config.port_filter)
     }
     
     pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
         helpers::get_port_name(self.seq.as_ref().unwrap(), port.addr)
     }
+
+    pub fn port_group(&self, port: &MidiInputPort) -> Option<String> {
+        helpers::get_client_name(self.seq.as_ref().unwrap(), port.addr)
+    }
+
+    pub fn port_capabilities(&self, port: &MidiInputPort) -> Result<PortCapabilities, PortInfoError> {
+        helpers::get_port_capabilities(self.seq.as_ref().unwrap(), port.addr)
+    }
+
+    pub fn port_address(&self, port: &MidiInputPort) -> (i32, i32) {
+        (port.addr.client, port.addr.port)
+    }
+
+    pub fn client_name(&self) -> Result<String, PortInfoError> {
+        helpers::get_own_client_name(self.seq.as_ref().unwrap())
+    }
     
-    fn init_queue(&mut self) -> i32 {
+    /// Resizes the input pool of the client in the kernel, if requested (see
+    /// `MidiInputExt::set_input_buffer_size`). This also discards events that are
+    /// still in the pool, so it has to happen before the port is created.
+    fn init_input_pool(&mut self) -> Result<(), BackendError> {
+        if let Some(cells) = self.config.input_pool_cells {
+            if let Err(ref e) = self.seq.as_ref().unwrap().set_client_pool_input(cells as u32) {
+                return Err(backend_error("could not resize ALSA input pool", e));
+            }
+        }
+        Ok(())
+    }
+
+    fn init_queue(&mut self) -> Result<i32, BackendError> {
+        let (tempo, ppq) = match (self.config.tick_ppq, self.config.queue_tempo) {
+            (Some(ppq), _) => (self.config.tick_tempo() as u32, ppq),
+            (None, Some(queue_tempo)) => queue_tempo,
+            // Set arbitrary tempo (mm=100) and resolution (240), the real time does not depend on it
+            (None, None) => (600_000, 240)
+        };
         let seq = self.seq.as_mut().unwrap();
         let mut queue_id = 0;
         // Create the input queue
-        if !cfg!(feature = "avoid_timestamping") {
-            queue_id = seq.alloc_named_queue(unsafe { CStr::from_bytes_with_nul_unchecked(b"midir queue\0") }).unwrap();
-            // Set arbitrary tempo (mm=100) and resolution (240)
-            let qtempo = QueueTempo::empty().unwrap();
-            qtempo.set_tempo(600_000);
-            qtempo.set_ppq(240);
-            seq.set_queue_tempo(queue_id, &qtempo).unwrap();
+        if self.config.timestamping() {
+            queue_id = seq.alloc_named_queue(unsafe { CStr::from_bytes_with_nul_unchecked(b"midir queue\0") })
+                .map_err(|ref e| backend_error("could not allocate ALSA input queue", e))?;
+            let qtempo = match QueueTempo::empty() {
+                Ok(qtempo) => qtempo,
+                Err(ref e) => {
+                    let _ = seq.free_queue(queue_id);
+                    return Err(backend_error("could not allocate ALSA queue tempo", e));
+                }
+            };
+            qtempo.set_tempo(tempo);
+            qtempo.set_ppq(ppq as i32);
+            if let Err(ref e) = seq.set_queue_tempo(queue_id, &qtempo) {
+                let _ = seq.free_queue(queue_id);
+                return Err(backend_error("could not set the tempo of the ALSA input queue", e));
+            }
             let _ = seq.drain_output();
         }
         
-        queue_id
+        Ok(queue_id)
+    }
+    
+    /// Releases what `connect` or `create_virtual` has set up before it failed,
+    /// so that the `MidiInput` can be used again.
+    fn release_unstarted(&mut self, trigger_fds: [i32; 2], queue_id: Option<i32>, vport: Option<i32>) {
+        unsafe {
+            self::libc::close(trigger_fds[0]);
+            self::libc::close(trigger_fds[1]);
+        }
+        let seq = self.seq.as_ref().unwrap();
+        if let Some(vport) = vport {
+            let _ = seq.delete_port(vport);
+        }
+        if let Some(queue_id) = queue_id {
+            if self.config.timestamping() {
+                let _ = seq.control_queue(queue_id, EventType::Stop, 0, None);
+                let _ = seq.drain_output();
+                let _ = seq.free_queue(queue_id);
+            }
+        }
     }
     
-    fn init_trigger(&mut self) -> Result<[i32; 2], ()> {
+    fn init_trigger(&mut self) -> Result<[i32; 2], BackendError> {
         let mut trigger_fds = [-1, -1];
         
         if unsafe { self::libc::pipe(trigger_fds.as_mut_ptr()) } == -1 {
-            Err(())
+            Err(BackendError::new("could not create communication pipe for ALSA handler", io::Error::last_os_error().raw_os_error()))
         } else {
             Ok(trigger_fds)
         }
     }
     
-    fn create_port(&mut self, port_name: &CStr, queue_id: i32) -> Result<i32, ()> {
-        let mut pinfo = PortInfo::empty().unwrap();
+    fn create_port(&mut self, port_name: &CStr, queue_id: i32) -> Result<i32, BackendError> {
+        let mut pinfo = PortInfo::empty().map_err(|ref e| backend_error("could not allocate ALSA port info", e))?;
         // these functions are private, and the values are zeroed already by `empty()`
         //pinfo.set_client(0);
         //pinfo.set_port(0);
-        pinfo.set_capability(PortCap::WRITE | PortCap::SUBS_WRITE);
+        if self.config.private_ports {
+            pinfo.set_capability(PortCap::WRITE | PortCap::SUBS_WRITE | PortCap::NO_EXPORT);
+        } else {
+            pinfo.set_capability(PortCap::WRITE | PortCap::SUBS_WRITE);
+        }
         pinfo.set_type(PortType::MIDI_GENERIC | PortType::APPLICATION);
         pinfo.set_midi_channels(16);
         
-        if !cfg!(feature = "avoid_timestamping") {
+        if self.config.timestamping() {
             pinfo.set_timestamping(true);
-            pinfo.set_timestamp_real(true);
+            pinfo.set_timestamp_real(self.config.tick_ppq.is_none());
             pinfo.set_timestamp_queue(queue_id);
         }
         
         pinfo.set_name(port_name);
         match self.seq.as_mut().unwrap().create_port(&mut pinfo) {
             Ok(_) => Ok(pinfo.get_port()),
-            Err(_) => Err(())
+            Err(ref e) => Err(backend_error("could not create ALSA input port", e))
         }
     }
     
+    /// Starts the input queue, which anchors the timestamps of the connection
+    /// to the current point in time.
     fn start_input_queue(&mut self, queue_id: i32) {
-        if !cfg!(feature = "avoid_timestamping") {
+        if self.config.timestamping() {
             let seq = self.seq.as_mut().unwrap();
             let _ = seq.control_queue(queue_id, EventType::Start, 0, None);
             let _ = seq.drain_output();
@@ -239,94 +1131,176 @@ impl MidiInput {
     ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
         where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
         
+        if let Err(err) = self.init_input_pool() {
+            return Err(ConnectError::from_backend(err, self));
+        }
+
         let trigger_fds = match self.init_trigger() {
             Ok(fds) => fds,
-            Err(()) => { return Err(ConnectError::other("could not create communication pipe for ALSA handler", self)); }
+            Err(err) => { return Err(ConnectError::from_backend(err, self)); }
         };
         
-        let queue_id = self.init_queue();
-
+        let queue_id = match self.init_queue() {
+            Ok(queue_id) => queue_id,
+            Err(err) => {
+                self.release_unstarted(trigger_fds, None, None);
+                return Err(ConnectError::from_backend(err, self));
+            }
+        };
+
         let src_pinfo = match self.seq.as_ref().unwrap().get_any_port_info(port.addr) {
             Ok(p) => p,
-            Err(_) => return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self))
+            Err(_) => {
+                self.release_unstarted(trigger_fds, Some(queue_id), None);
+                return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self));
+            }
         };
 
         let c_port_name = match CString::new(port_name) {
             Ok(c_port_name) => c_port_name,
-            Err(_) => return Err(ConnectError::other("port_name must not contain null bytes", self))
+            Err(_) => {
+                self.release_unstarted(trigger_fds, Some(queue_id), None);
+                return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self));
+            }
         };
         
         let vport = match self.create_port(&c_port_name, queue_id) {
             Ok(vp) => vp,
-            Err(_) => {
-                return Err(ConnectError::other("could not create ALSA input port", self));
+            Err(err) => {
+                self.release_unstarted(trigger_fds, Some(queue_id), None);
+                return Err(ConnectError::from_backend(err, self));
             }
         };
         
         // Make subscription
-        let sub = PortSubscribe::empty().unwrap();
+        let sub = match PortSubscribe::empty() {
+            Ok(sub) => sub,
+            Err(ref e) => {
+                let err = backend_error("could not allocate ALSA port subscription", e);
+                self.release_unstarted(trigger_fds, Some(queue_id), Some(vport));
+                return Err(ConnectError::from_backend(err, self));
+            }
+        };
         sub.set_sender(src_pinfo.addr());
         sub.set_dest(Addr { client: self.seq.as_ref().unwrap().client_id().unwrap(), port: vport});
-        if self.seq.as_ref().unwrap().subscribe_port(&sub).is_err() {
-            return Err(ConnectError::other("could not create ALSA input subscription", self));
+        if let Err(ref e) = self.seq.as_ref().unwrap().subscribe_port(&sub) {
+            let err = backend_error("could not create ALSA input subscription", e);
+            self.release_unstarted(trigger_fds, Some(queue_id), Some(vport));
+            return Err(ConnectError::from_backend(err, self));
         }
-        let subscription = sub;
+        let subscription = (sub.get_sender(), sub.get_dest());
+        let source_name = helpers::get_port_name(self.seq.as_ref().unwrap(), src_pinfo.addr()).ok();
+        let source_timing = source_has_timing(&src_pinfo, self.config.timestamping());
+
+        // Everything that can fail has to happen before the sequencer and the
+        // configuration are moved into the handler data
+        let processor = match InputProcessor::new(&self.config) {
+            Ok(processor) => processor,
+            Err(err) => {
+                // Deleting the port also removes the subscription
+                self.release_unstarted(trigger_fds, Some(queue_id), Some(vport));
+                return Err(ConnectError::from_backend(err, self));
+            }
+        };
         
         // Start the input queue
         self.start_input_queue(queue_id);
 
         // Start our MIDI input thread.
+        let timestamping = self.config.timestamping();
+        let detach_on_drop = self.config.detach_on_drop;
+        let thread_name = self.config.thread_config.name.clone();
+        let client_id = self.seq.as_ref().unwrap().client_id().unwrap();
+        let shared = Arc::new(SharedState::new(self.config.timestamp_log_capacity, self.ignore_flags));
+        *shared.source.lock().unwrap() = Some((subscription.0, source_name));
         let handler_data = HandlerData {
-            ignore_flags: self.ignore_flags,
             seq: self.seq.take().unwrap(),
             trigger_rcv_fd: trigger_fds[0],
             callback: Box::new(callback),
-            queue_id: queue_id
+            queue_id,
+            shared: shared.clone(),
+            config: mem::take(&mut self.config),
+            subscription: Some(subscription)
         };
         
-        let threadbuilder = Builder::new();
-        let name = format!("midir ALSA input handler (port '{}')", port_name);
-        let threadbuilder = threadbuilder.name(name);
-        let thread = match threadbuilder.spawn(move || {
-            let mut d = data;
-            let h = handle_input(handler_data, &mut d);
-            (h, d) // return both the handler data and the user data 
-        }) {
-            Ok(handle) => handle,
-            Err(_) => {
-                //unsafe { snd_seq_unsubscribe_port(self.seq.as_mut_ptr(), sub.as_ptr()) };
-                return Err(ConnectError::other("could not start ALSA input handler thread", self));
+        let name = thread_name.unwrap_or_else(|| format!("midir ALSA input handler (port '{}')", port_name));
+        let threadbuilder = Builder::new().name(name);
+        let handler = match start_input_handler(handler_data, processor, data, threadbuilder) {
+            Ok(handler) => handler,
+            Err((err, handler_data)) => {
+                self.seq = Some(handler_data.seq);
+                self.config = handler_data.config;
+                self.release_unstarted(trigger_fds, Some(queue_id), Some(vport));
+                return Err(ConnectError::from_backend(err, self));
             }
         };
 
         Ok(MidiInputConnection {
             subscription: Some(subscription),
-            thread: Some(thread),
-            vport: vport,
-            trigger_send_fd: trigger_fds[1]
+            handler: Some(handler),
+            vport,
+            client_id,
+            trigger_send_fd: trigger_fds[1],
+            trigger_rcv_fd: trigger_fds[0],
+            shared,
+            source_timing: Some(source_timing),
+            queue_id,
+            timestamping,
+            detach_on_drop
         })
     }
     
+    pub fn create_virtual_connected<F, T: Send>(
+        self, port_name: &str, port: &MidiInputPort, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        // The port that is created by `connect` accepts subscriptions from other clients as well,
+        // so it is a virtual port that is subscribed to the source before the handler starts.
+        self.connect(port, port_name, callback, data)
+    }
+
     pub fn create_virtual<F, T: Send>(
         mut self, port_name: &str, callback: F, data: T
     ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
     where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        if let Err(err) = self.init_input_pool() {
+            return Err(ConnectError::from_backend(err, self));
+        }
+
         let trigger_fds = match self.init_trigger() {
             Ok(fds) => fds,
-            Err(()) => { return Err(ConnectError::other("could not create communication pipe for ALSA handler", self)); }
+            Err(err) => { return Err(ConnectError::from_backend(err, self)); }
         };
         
-        let queue_id = self.init_queue();
+        let queue_id = match self.init_queue() {
+            Ok(queue_id) => queue_id,
+            Err(err) => {
+                self.release_unstarted(trigger_fds, None, None);
+                return Err(ConnectError::from_backend(err, self));
+            }
+        };
 
         let c_port_name = match CString::new(port_name) {
             Ok(c_port_name) => c_port_name,
-            Err(_) => return Err(ConnectError::other("port_name must not contain null bytes", self))
+            Err(_) => {
+                self.release_unstarted(trigger_fds, Some(queue_id), None);
+                return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self));
+            }
         };
         
         let vport = match self.create_port(&c_port_name, queue_id) {
             Ok(vp) => vp,
-            Err(_) => {
-                return Err(ConnectError::other("could not create ALSA input port", self));
+            Err(err) => {
+                self.release_unstarted(trigger_fds, Some(queue_id), None);
+                return Err(ConnectError::from_backend(err, self));
+            }
+        };
+
+        let processor = match InputProcessor::new(&self.config) {
+            Ok(processor) => processor,
+            Err(err) => {
+                self.release_unstarted(trigger_fds, Some(queue_id), Some(vport));
+                return Err(ConnectError::from_backend(err, self));
             }
         };
         
@@ -334,105 +1308,383 @@ impl MidiInput {
         self.start_input_queue(queue_id);
         
         // Start our MIDI input thread.
+        let timestamping = self.config.timestamping();
+        let detach_on_drop = self.config.detach_on_drop;
+        let thread_name = self.config.thread_config.name.clone();
+        let client_id = self.seq.as_ref().unwrap().client_id().unwrap();
+        let shared = Arc::new(SharedState::new(self.config.timestamp_log_capacity, self.ignore_flags));
         let handler_data = HandlerData {
-            ignore_flags: self.ignore_flags,
             seq: self.seq.take().unwrap(),
             trigger_rcv_fd: trigger_fds[0],
             callback: Box::new(callback),
-            queue_id: queue_id
+            queue_id,
+            shared: shared.clone(),
+            config: mem::take(&mut self.config),
+            subscription: None
         };
         
-        let threadbuilder = Builder::new();
-        let thread = match threadbuilder.spawn(move || {
-            let mut d = data;
-            let h = handle_input(handler_data, &mut d);
-            (h, d) // return both the handler data and the user data 
-        }) {
-            Ok(handle) => handle,
-            Err(_) => {
-                //unsafe { snd_seq_unsubscribe_port(self.seq.as_mut_ptr(), sub.as_ptr()) };
-                return Err(ConnectError::other("could not start ALSA input handler thread", self));
+        let name = thread_name.unwrap_or_else(|| format!("midir ALSA input handler (virtual port '{}')", port_name));
+        let threadbuilder = Builder::new().name(name);
+        let handler = match start_input_handler(handler_data, processor, data, threadbuilder) {
+            Ok(handler) => handler,
+            Err((err, handler_data)) => {
+                self.seq = Some(handler_data.seq);
+                self.config = handler_data.config;
+                self.release_unstarted(trigger_fds, Some(queue_id), Some(vport));
+                return Err(ConnectError::from_backend(err, self));
             }
         };
 
         Ok(MidiInputConnection {
             subscription: None,
-            thread: Some(thread),
-            vport: vport,
-            trigger_send_fd: trigger_fds[1]
+            handler: Some(handler),
+            vport,
+            client_id,
+            trigger_send_fd: trigger_fds[1],
+            trigger_rcv_fd: trigger_fds[0],
+            shared,
+            source_timing: None,
+            queue_id,
+            timestamping,
+            detach_on_drop
         })
     }
 }
 
 impl<T> MidiInputConnection<T> {
-    pub fn close(mut self) -> (MidiInput, T) {
-        let (handler_data, user_data) = self.close_internal();
+    pub fn close(self) -> (MidiInput, T) {
+        let (midi_in, user_data, _) = self.close_with_callback();
+        (midi_in, user_data)
+    }
+
+    pub fn close_with_callback(mut self) -> (MidiInput, T, ::MidiInputCallback<T>) {
+        let (handler_data, user_data) = match self.close_internal() {
+            Ok(data) => data,
+            // The user data has been lost with the handler thread, so pass on its panic
+            Err(payload) => panic::resume_unwind(payload)
+        };
         
         (MidiInput {
-            ignore_flags: handler_data.ignore_flags,
+            ignore_flags: Ignore::from_bits(handler_data.shared.ignore_flags.load(Ordering::Relaxed)),
             seq: Some(handler_data.seq),
-        }, user_data)
+            config: handler_data.config,
+        }, user_data, handler_data.callback)
     }
     
-    /// This must only be called if the handler thread has not yet been shut down
-    fn close_internal(&mut self) -> (HandlerData<T>, T) {
-        // Request the thread to stop.
-        let _res = unsafe { self::libc::write(self.trigger_send_fd, &false as *const bool as *const _, mem::size_of::<bool>() as self::libc::size_t) };
-        
-        let thread = self.thread.take().unwrap(); 
-        // Join the thread to get the handler_data back
-        let (handler_data, user_data) = match thread.join() {
-            Ok(data) => data,
-            // TODO: handle this more gracefully?
-            Err(e) => {
-                if let Some(e) = e.downcast_ref::<&'static str>() {
-                    panic!("Error when joining ALSA thread: {}", e);
-                } else {
-                    panic!("Unknown error when joining ALSA thread: {:?}", e);
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        self.shared.ignore_flags.store(flags as u8, Ordering::Relaxed);
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        Ignore::from_bits(self.shared.ignore_flags.load(Ordering::Relaxed)).contains(flags)
+    }
+
+    pub fn callback_stats(&self) -> CallbackStats {
+        self.shared.timing.stats()
+    }
+
+    pub fn eagain_count(&self) -> u64 {
+        self.shared.eagain_count.load(Ordering::Relaxed)
+    }
+
+    pub fn partial_message_count(&self) -> u64 {
+        self.shared.partial_message_count.load(Ordering::Relaxed)
+    }
+
+    pub fn overrun_count(&self) -> u64 {
+        self.shared.overrun_count.load(Ordering::Relaxed)
+    }
+
+    pub fn merged_message_count(&self) -> u64 {
+        self.shared.merged_message_count.load(Ordering::Relaxed)
+    }
+
+    pub fn has_overrun(&self) -> bool {
+        self.shared.overrun.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_overrun(&self) -> bool {
+        self.shared.overrun.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn flush_input(&self) {
+        self.shared.flush_requested.store(true, Ordering::SeqCst);
+        // Wake up the handler thread (but keep it running) so that it performs the flush.
+        self.trigger(true);
+    }
+
+    pub fn pause_queue(&self) {
+        self.set_queue_paused(true);
+    }
+
+    pub fn resume_queue(&self) {
+        self.set_queue_paused(false);
+    }
+
+    fn set_queue_paused(&self, paused: bool) {
+        self.shared.queue_paused.store(paused, Ordering::SeqCst);
+        self.shared.queue_change_requested.store(true, Ordering::SeqCst);
+        // The queue is controlled by the handler thread, which has to be woken up for that
+        self.trigger(true);
+    }
+
+    pub fn take_timestamps(&self, buffer: &mut Vec<TimestampSample>) -> u64 {
+        let mut log = self.shared.timestamp_log.lock().unwrap();
+        buffer.append(&mut log.samples);
+        mem::replace(&mut log.dropped, 0)
+    }
+
+    pub fn source_name(&self) -> Option<String> {
+        self.shared.source.lock().unwrap().as_ref().and_then(|(_, name)| name.clone())
+    }
+
+    pub fn source_has_timing(&self) -> Option<bool> {
+        self.source_timing
+    }
+
+    pub fn is_source_connected(&self) -> Option<bool> {
+        self.subscription.as_ref().map(|_| !self.shared.source_lost.load(Ordering::Relaxed))
+    }
+
+    pub fn virtual_port_addr(&self) -> (i32, i32) {
+        (self.client_id, self.vport)
+    }
+
+    pub fn rewire(&mut self, port: &MidiInputPort) -> Result<(), ConnectError<()>> {
+        let dest = Addr { client: self.client_id, port: self.vport };
+        let result = match self.handler {
+            Some(InputHandler::External(ref mut handler_data, _, _)) => handler_data.rewire(port.addr, dest),
+            _ => {
+                // The sequencer handle belongs to the handler thread, which has to be woken up for that
+                let (done_send, done_rcv) = mpsc::channel();
+                *self.shared.rewire_request.lock().unwrap() = Some(RewireRequest { sender: port.addr, dest, done: done_send });
+                self.shared.rewire_requested.store(true, Ordering::SeqCst);
+                self.trigger(true);
+                loop {
+                    match done_rcv.recv_timeout(Duration::from_millis(100)) {
+                        Ok(result) => break result,
+                        // Only the handler thread holds another reference to the shared state,
+                        // so if there is none, it has stopped (i.e. the callback has panicked).
+                        // If it has stopped because of an error, it has set a flag instead.
+                        Err(_) if Arc::strong_count(&self.shared) == 1 || self.shared.handler_stopped.load(Ordering::SeqCst) => {
+                            break Err(ConnectError::other("ALSA input handler has stopped", ()));
+                        },
+                        Err(_) => {}
+                    }
                 }
             }
         };
-        
-        // TODO: find out why snd_seq_unsubscribe_port takes a long time if there was not yet any input message
-        if let Some(ref subscription) = self.subscription {
-            let _ = handler_data.seq.unsubscribe_port(subscription.get_sender(), subscription.get_dest());
+        let source = result?;
+        self.subscription = Some((port.addr, dest));
+        *self.shared.source.lock().unwrap() = Some((port.addr, source.name));
+        self.source_timing = Some(source.timing);
+        Ok(())
+    }
+
+    pub fn queue_timer(&self) -> Option<QueueTimerInfo> {
+        if !self.timestamping {
+            return None;
         }
-        
-        // Close the trigger fds (TODO: make sure that these are closed even in the presence of panic in thread)
-        unsafe {
-            self::libc::close(handler_data.trigger_rcv_fd);
-            self::libc::close(self.trigger_send_fd);
+        let mut info = String::new();
+        File::open("/proc/asound/seq/timer").and_then(|mut f| f.read_to_string(&mut info)).ok()?;
+        parse_queue_timer(&info, self.queue_id)
+    }
+
+    pub fn queue_id(&self) -> Option<i32> {
+        if self.timestamping { Some(self.queue_id) } else { None }
+    }
+
+    pub fn timestamp_epoch(&self) -> Option<Instant> {
+        timestamp_epochs(&[self.queue_id()])[0]
+    }
+
+    pub fn poll_descriptors(&self) -> Vec<RawFd> {
+        match self.handler {
+            Some(InputHandler::External(ref handler_data, _, _)) => match handler_data.poll_fds() {
+                Ok(poll_fds) => poll_fds.iter().map(|p| p.fd).collect(),
+                Err(err) => {
+                    log_warn!("{}", err);
+                    Vec::new()
+                }
+            },
+            _ => Vec::new()
         }
-        
-        // Stop and free the input queue
-        if !cfg!(feature = "avoid_timestamping") {
-            let _ = handler_data.seq.control_queue(handler_data.queue_id, EventType::Stop, 0, None);
-            let _ = handler_data.seq.drain_output();
-            let _ = handler_data.seq.free_queue(handler_data.queue_id);
+    }
+
+    pub fn process_ready_events(&mut self) -> Option<Duration> {
+        match self.handler {
+            Some(InputHandler::External(ref mut handler_data, ref mut user_data, ref mut processor)) => {
+                // Consume the pending notifications of the trigger pipe, so that it is not signalled anymore
+                let mut trigger = [self::libc::pollfd { fd: handler_data.trigger_rcv_fd, events: self::libc::POLLIN, revents: 0 }];
+                while helpers::poll(&mut trigger, 0) > 0 && trigger[0].revents & self::libc::POLLIN != 0 {
+                    let mut keep_running = true;
+                    let _res = unsafe { self::libc::read(trigger[0].fd, &mut keep_running as *mut bool as *mut _, mem::size_of::<bool>() as self::libc::size_t) };
+                }
+
+                match processor.process(handler_data, user_data) {
+                    -1 => None,
+                    timeout => Some(Duration::from_millis(timeout as u64))
+                }
+            },
+            _ => None
         }
+    }
+
+    /// Writes to the communication pipe of the handler thread.
+    /// Writing `false` requests the thread to stop.
+    fn trigger(&self, keep_running: bool) {
+        let _res = unsafe { self::libc::write(self.trigger_send_fd, &keep_running as *const bool as *const _, mem::size_of::<bool>() as self::libc::size_t) };
+    }
+    
+    /// This must only be called if the handler has not yet been shut down.
+    /// If the handler thread has panicked (i.e. the callback has panicked),
+    /// the remaining resources are released and the panic payload is returned.
+    fn close_internal(&mut self) -> thread::Result<(HandlerData<T>, T)> {
+        let (handler_data, user_data) = match self.handler.take().unwrap() {
+            InputHandler::Thread(thread) => {
+                // Request the thread to stop.
+                self.trigger(false);
+
+                // Join the thread to get the handler_data back
+                match thread.join() {
+                    Ok(data) => data,
+                    Err(payload) => {
+                        close_after_panic(self.trigger_rcv_fd, self.trigger_send_fd);
+                        return Err(payload);
+                    }
+                }
+            },
+            InputHandler::External(mut handler_data, mut user_data, mut processor) => {
+                processor.finish(&mut handler_data, &mut user_data);
+                (handler_data, user_data)
+            }
+        };
         
-        // Delete the port
-        let _ = handler_data.seq.delete_port(self.vport);
-        
-        (handler_data, user_data)
+        let subscription = self.subscription;
+        release_input(&handler_data, subscription, self.trigger_send_fd, self.vport);
+        Ok((handler_data, user_data))
+    }
+
+    /// Requests the handler thread to stop and leaves joining it and releasing
+    /// the resources of the connection to a background thread, so that this
+    /// does not block. This must only be called if the handler is a thread.
+    fn detach(&mut self) {
+        let thread = match self.handler.take() {
+            Some(InputHandler::Thread(thread)) => thread,
+            _ => unreachable!()
+        };
+        self.trigger(false);
+
+        let subscription = self.subscription;
+        let trigger_send_fd = self.trigger_send_fd;
+        let trigger_rcv_fd = self.trigger_rcv_fd;
+        let vport = self.vport;
+        // If the cleanup thread can not be started, the handler thread still
+        // stops, and its sequencer client (with the port and the queue) is
+        // closed when it exits. Only the trigger fds are leaked in that case.
+        let _ = Builder::new().name("midir ALSA input cleanup".to_string()).spawn(move || {
+            // The user data is dropped on this thread
+            match thread.join() {
+                Ok((handler_data, _)) => release_input(&handler_data, subscription, trigger_send_fd, vport),
+                Err(_) => close_after_panic(trigger_rcv_fd, trigger_send_fd)
+            }
+        });
+    }
+}
+
+/// Releases the resources of an input connection whose handler thread has panicked.
+/// The sequencer handle of the handler has been dropped while unwinding, which
+/// closed it, so the kernel has already deleted the port, the subscription and
+/// the queue along with the client. Only the trigger fds are left to be closed.
+fn close_after_panic(trigger_rcv_fd: i32, trigger_send_fd: i32) {
+    unsafe {
+        self::libc::close(trigger_rcv_fd);
+        self::libc::close(trigger_send_fd);
     }
 }
 
+/// Removes a subscription through a separate sequencer client on a helper thread,
+/// and waits for at most `timeout` (see `MidiInputExt::set_unsubscribe_timeout`).
+/// If the helper has not started to unsubscribe by then, it does not do so anymore,
+/// because the port of the connection is deleted afterwards (which removes the
+/// subscription as well) and its number may be reused by a new connection, whose
+/// subscription must not be removed.
+fn unsubscribe_bounded(sender: Addr, dest: Addr, timeout: Duration) {
+    const PENDING: u8 = 0;
+    const STARTED: u8 = 1;
+    const ABANDONED: u8 = 2;
+    let state = Arc::new(AtomicU8::new(PENDING));
+    let helper_state = state.clone();
+    let (done_send, done_rcv) = mpsc::channel();
+    let spawned = Builder::new().name("midir ALSA unsubscribe".to_string()).spawn(move || {
+        if let Ok(seq) = helpers::open_seq(None) {
+            if helper_state.compare_exchange(PENDING, STARTED, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                let _ = seq.unsubscribe_port(sender, dest);
+            }
+        }
+        let _ = done_send.send(());
+    });
+    // Without the helper thread, the subscription is still removed when the port is deleted
+    if spawned.is_ok() && done_rcv.recv_timeout(timeout).is_err() {
+        if state.compare_exchange(PENDING, ABANDONED, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            log_warn!("could not unsubscribe from ALSA port {}:{} within {:?}, leaving it to the deletion of the port",
+                sender.client, sender.port, timeout);
+        } else {
+            log_warn!("unsubscribing from ALSA port {}:{} takes longer than {:?}, continuing in the background",
+                sender.client, sender.port, timeout);
+        }
+    }
+}
+
+/// Releases the resources of an input connection after its handler has been stopped.
+fn release_input<T>(handler_data: &HandlerData<T>, subscription: Option<(Addr, Addr)>, trigger_send_fd: i32, vport: i32) {
+    // snd_seq_unsubscribe_port blocks until the kernel has removed the subscription,
+    // which can optionally be bounded
+    if let Some((sender, dest)) = subscription {
+        match handler_data.config.unsubscribe_timeout {
+            Some(timeout) => unsubscribe_bounded(sender, dest, timeout),
+            None => { let _ = handler_data.seq.unsubscribe_port(sender, dest); }
+        }
+    }
+    
+    // Close the trigger fds (TODO: make sure that these are closed even in the presence of panic in thread)
+    unsafe {
+        self::libc::close(handler_data.trigger_rcv_fd);
+        self::libc::close(trigger_send_fd);
+    }
+    
+    // Stop and free the input queue
+    if handler_data.config.timestamping() {
+        let _ = handler_data.seq.control_queue(handler_data.queue_id, EventType::Stop, 0, None);
+        let _ = handler_data.seq.drain_output();
+        let _ = handler_data.seq.free_queue(handler_data.queue_id);
+    }
+    
+    // Delete the port
+    let _ = handler_data.seq.delete_port(vport);
+}
+
 
 impl<T> Drop for MidiInputConnection<T> {
     fn drop(&mut self) {
-        // Use `self.thread` as a flag whether the connection has already been dropped
-        if self.thread.is_some() {
-            self.close_internal();
+        // Use `self.handler` as a flag whether the connection has already been dropped
+        match self.handler {
+            Some(InputHandler::Thread(_)) if self.detach_on_drop => self.detach(),
+            // A panic of the handler thread has already been reported on that thread
+            Some(_) => { let _ = self.close_internal(); },
+            None => {}
         }
     }
 }
 
 pub struct MidiOutput {
     seq: Option<Seq>, // TODO: if `Seq` is marked as non-zero, this should just be pointer-sized 
+    output_mode: OutputMode,
+    private_ports: bool,
+    port_filter: PortFilter,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiOutputPort {
     addr: Addr
 }
@@ -441,39 +1693,110 @@ pub struct MidiOutputConnection {
     seq: Option<Seq>,
     vport: i32,
     coder: helpers::EventEncoder,
-    subscription: Option<PortSubscribe>
+    subscription: Option<PortSubscribe>,
+    output_mode: OutputMode,
+    private_ports: bool,
+    port_filter: PortFilter,
+    dest: Option<Addr>, // only set for `OutputMode::Direct`, events are then not sent to subscribers
+    queue_id: Option<i32>, // the output queue is only allocated when scheduling is used
+    owns_queue: bool, // false if the queue of an input connection is used
+    schedule_transform: Option<Box<dyn FnMut(u64) -> u64 + Send>>,
+    note_off_policy: NoteOffPolicy,
+    send_through_queue: bool,
+    active_notes: [u128; 16], // one bit per key and channel, only used for `NoteOffPolicy::TrackNotes`
 }
 
 impl MidiOutput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        let seq = match Seq::open(None, Some(Direction::Playback), true) {
+        let seq = match helpers::open_seq(Some(Direction::Playback)) {
             Ok(s) => s,
-            Err(_) => { return Err(InitError); }
+            Err(ref e) => { return Err(InitError::new(Some(backend_error("could not open ALSA sequencer", e)))); }
         };
         
-        let c_client_name = CString::new(client_name).map_err(|_| InitError)?;
-        seq.set_client_name(&c_client_name).map_err(|_| InitError)?;
+        let c_client_name = CString::new(client_name).map_err(|_| InitError::new(None))?;
+        seq.set_client_name(&c_client_name).map_err(|ref e| InitError::new(Some(backend_error("could not set ALSA client name", e))))?;
         
         Ok(MidiOutput {
             seq: Some(seq),
+            output_mode: OutputMode::Auto,
+            private_ports: false,
+            port_filter: PortFilter::default(),
         })
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiOutputPort> {
-        helpers::get_ports(self.seq.as_ref().unwrap(), PortCap::WRITE | PortCap::SUBS_WRITE, |p| ::common::MidiOutputPort {
-            imp: MidiOutputPort {
-                addr: p.addr()
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.output_mode = mode;
+    }
+
+    pub fn set_blocking(&mut self, enabled: bool) -> Result<(), BackendError> {
+        use self::alsa::PollDescriptors;
+
+        // The mode belongs to the sequencer handle, which is passed on to the connections.
+        // The alsa crate does not give access to the handle that `snd_seq_nonblock` takes,
+        // but for the kernel sequencer, all that does is to toggle `O_NONBLOCK` on its file.
+        let fds = (self.seq.as_ref().unwrap(), Some(Direction::Playback)).get().map_err(|ref e| backend_error("could not get ALSA poll descriptors", e))?;
+        for pfd in fds {
+            let flags = unsafe { self::libc::fcntl(pfd.fd, self::libc::F_GETFL) };
+            let res = if flags == -1 {
+                -1
+            } else if enabled {
+                unsafe { self::libc::fcntl(pfd.fd, self::libc::F_SETFL, flags & !self::libc::O_NONBLOCK) }
+            } else {
+                unsafe { self::libc::fcntl(pfd.fd, self::libc::F_SETFL, flags | self::libc::O_NONBLOCK) }
+            };
+            if res == -1 {
+                return Err(BackendError::new("could not set ALSA blocking mode", io::Error::last_os_error().raw_os_error()));
             }
+        }
+        Ok(())
+    }
+
+    pub fn set_private_ports(&mut self, enabled: bool) {
+        self.private_ports = enabled;
+    }
+
+    pub fn set_port_filter(&mut self, filter: PortFilter) {
+        self.port_filter = filter;
+    }
+
+    /// The capabilities of the ports that are created for output.
+    fn port_capability(&self) -> PortCap {
+        if self.private_ports {
+            PortCap::READ | PortCap::SUBS_READ | PortCap::NO_EXPORT
+        } else {
+            PortCap::READ | PortCap::SUBS_READ
+        }
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<MidiOutputPort> {
+        helpers::get_ports(self.seq.as_ref().unwrap(), PortCap::WRITE | PortCap::SUBS_WRITE, &self.port_filter, |p| MidiOutputPort {
+            addr: p.addr()
         })
     }
     
     pub fn port_count(&self) -> usize {
-        helpers::get_port_count(self.seq.as_ref().unwrap(), PortCap::WRITE | PortCap::SUBS_WRITE)
+        helpers::get_port_count(self.seq.as_ref().unwrap(), PortCap::WRITE | PortCap::SUBS_WRITE, &self.port_filter)
     }
     
     pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
         helpers::get_port_name(self.seq.as_ref().unwrap(), port.addr)
     }
+
+    pub fn port_group(&self, port: &MidiOutputPort) -> Option<String> {
+        helpers::get_client_name(self.seq.as_ref().unwrap(), port.addr)
+    }
+
+    pub fn port_capabilities(&self, port: &MidiOutputPort) -> Result<PortCapabilities, PortInfoError> {
+        helpers::get_port_capabilities(self.seq.as_ref().unwrap(), port.addr)
+    }
+
+    pub fn port_address(&self, port: &MidiOutputPort) -> (i32, i32) {
+        (port.addr.client, port.addr.port)
+    }
+
+    pub fn client_name(&self) -> Result<String, PortInfoError> {
+        helpers::get_own_client_name(self.seq.as_ref().unwrap())
+    }
     
     pub fn connect(mut self, port: &MidiOutputPort, port_name: &str) -> Result<MidiOutputConnection, ConnectError<Self>> {
         let pinfo = match self.seq.as_ref().unwrap().get_any_port_info(port.addr) {
@@ -483,50 +1806,128 @@ impl MidiOutput {
 
         let c_port_name = match CString::new(port_name) {
             Ok(c_port_name) => c_port_name,
-            Err(_) => return Err(ConnectError::other("port_name must not contain null bytes", self))
+            Err(_) => return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self))
         };
 
-        let vport = match self.seq.as_ref().unwrap().create_simple_port(&c_port_name, PortCap::READ | PortCap::SUBS_READ, PortType::MIDI_GENERIC | PortType::APPLICATION) {
+        let coder = match helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32) {
+            Ok(coder) => coder,
+            Err(ref e) => return Err(ConnectError::from_backend(backend_error("could not create ALSA event encoder", e), self))
+        };
+
+        let vport = match self.seq.as_ref().unwrap().create_simple_port(&c_port_name, self.port_capability(), PortType::MIDI_GENERIC | PortType::APPLICATION) {
             Ok(vport) => vport,
-            Err(_) => return Err(ConnectError::other("could not create ALSA output port", self))
+            Err(ref e) => return Err(ConnectError::from_backend(backend_error("could not create ALSA output port", e), self))
         };
 
-        // Make subscription
-        let sub = PortSubscribe::empty().unwrap();
-        sub.set_sender(Addr { client: self.seq.as_ref().unwrap().client_id().unwrap(), port: vport });
-        sub.set_dest(pinfo.addr());
-        sub.set_time_update(true);
-        sub.set_time_real(true);
-        if self.seq.as_ref().unwrap().subscribe_port(&sub).is_err() {
-            return Err(ConnectError::other("could not create ALSA output subscription", self));
+        // Decide whether to subscribe or to address events directly to the destination
+        let caps = pinfo.get_capability();
+        let subscribe = match self.output_mode {
+            OutputMode::Auto => caps.contains(PortCap::SUBS_WRITE),
+            OutputMode::Subscribe => true,
+            OutputMode::Direct => false
+        };
+
+        let mut subscription = None;
+        if subscribe {
+            // Make subscription
+            let sub = match PortSubscribe::empty() {
+                Ok(sub) => sub,
+                Err(ref e) => {
+                    let err = backend_error("could not allocate ALSA port subscription", e);
+                    let _ = self.seq.as_ref().unwrap().delete_port(vport);
+                    return Err(ConnectError::from_backend(err, self));
+                }
+            };
+            sub.set_sender(Addr { client: self.seq.as_ref().unwrap().client_id().unwrap(), port: vport });
+            sub.set_dest(pinfo.addr());
+            sub.set_time_update(true);
+            sub.set_time_real(true);
+            match self.seq.as_ref().unwrap().subscribe_port(&sub) {
+                Ok(_) => subscription = Some(sub),
+                Err(ref e) if self.output_mode != OutputMode::Auto || !caps.contains(PortCap::WRITE) => {
+                    let err = backend_error("could not create ALSA output subscription", e);
+                    let _ = self.seq.as_ref().unwrap().delete_port(vport);
+                    return Err(ConnectError::from_backend(err, self));
+                },
+                Err(_) => {}
+            }
         }
+
+        // Without a subscription, fall back to sending directly to the destination port
+        let dest = match subscription {
+            Some(_) => None,
+            None => Some(pinfo.addr())
+        };
         
         Ok(MidiOutputConnection {
             seq: self.seq.take(),
-            vport: vport,
-            coder: helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32),
-            subscription: Some(sub)
+            vport,
+            coder,
+            subscription,
+            output_mode: self.output_mode,
+            private_ports: self.private_ports,
+            port_filter: self.port_filter,
+            dest,
+            queue_id: None,
+            owns_queue: false,
+            schedule_transform: None,
+            note_off_policy: NoteOffPolicy::None,
+            send_through_queue: false,
+            active_notes: [0; 16]
         })
     }
     
+    pub fn create_virtual_connected(
+        mut self, port_name: &str, port: &MidiOutputPort
+    ) -> Result<MidiOutputConnection, ConnectError<Self>> {
+        // Like for a virtual port, events must go to all subscribers (not only to the destination),
+        // so a subscription is required. The port created by `connect` accepts further subscriptions.
+        let output_mode = mem::replace(&mut self.output_mode, OutputMode::Subscribe);
+        match self.connect(port, port_name) {
+            Ok(mut conn) => {
+                conn.output_mode = output_mode;
+                Ok(conn)
+            },
+            Err(err) => Err(err.map_inner(|mut midi_out| {
+                midi_out.output_mode = output_mode;
+                midi_out
+            }))
+        }
+    }
+
     pub fn create_virtual(
         mut self, port_name: &str
     ) -> Result<MidiOutputConnection, ConnectError<Self>> {
         let c_port_name = match CString::new(port_name) {
             Ok(c_port_name) => c_port_name,
-            Err(_) => return Err(ConnectError::other("port_name must not contain null bytes", self))
+            Err(_) => return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self))
+        };
+
+        let coder = match helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32) {
+            Ok(coder) => coder,
+            Err(ref e) => return Err(ConnectError::from_backend(backend_error("could not create ALSA event encoder", e), self))
         };
 
-        let vport = match self.seq.as_ref().unwrap().create_simple_port(&c_port_name, PortCap::READ | PortCap::SUBS_READ, PortType::MIDI_GENERIC | PortType::APPLICATION) {
+        let vport = match self.seq.as_ref().unwrap().create_simple_port(&c_port_name, self.port_capability(), PortType::MIDI_GENERIC | PortType::APPLICATION) {
             Ok(vport) => vport,
-            Err(_) => return Err(ConnectError::other("could not create ALSA output port", self))
+            Err(ref e) => return Err(ConnectError::from_backend(backend_error("could not create ALSA output port", e), self))
         };
         
         Ok(MidiOutputConnection {
             seq: self.seq.take(),
-            vport: vport,
-            coder: helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32),
-            subscription: None
+            vport,
+            coder,
+            subscription: None,
+            output_mode: self.output_mode,
+            private_ports: self.private_ports,
+            port_filter: self.port_filter,
+            dest: None,
+            queue_id: None,
+            owns_queue: false,
+            schedule_transform: None,
+            note_off_policy: NoteOffPolicy::None,
+            send_through_queue: false,
+            active_notes: [0; 16]
         })
     }
 }
@@ -538,43 +1939,330 @@ impl MidiOutputConnection {
         
         MidiOutput {
             seq: self.seq.take(),
+            output_mode: self.output_mode,
+            private_ports: self.private_ports,
+            port_filter: self.port_filter,
+        }
+    }
+
+    pub fn virtual_port_addr(&self) -> (i32, i32) {
+        (self.seq.as_ref().unwrap().client_id().unwrap(), self.vport)
+    }
+
+    pub fn output_mode(&self) -> OutputMode {
+        match self.dest {
+            Some(_) => OutputMode::Direct,
+            None => OutputMode::Subscribe
         }
     }
     
     pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {  
+        self.track_notes(message);
+        let queue = self.immediate_queue();
+        let mut ev = Self::encode(&mut self.coder, message)?;
+        Self::schedule_now(&mut ev, queue);
+        Self::output(self.seq.as_ref().unwrap(), &mut ev, self.vport, self.dest)
+    }
+
+    /// Returns the queue that events which are sent immediately go through, if any.
+    fn immediate_queue(&self) -> Option<i32> {
+        if self.send_through_queue { self.queue_id } else { None }
+    }
+
+    /// Sets up an event to be delivered now, either directly or through the given queue.
+    fn schedule_now(ev: &mut Event, queue: Option<i32>) {
+        match queue {
+            Some(queue_id) => ev.schedule_real(queue_id, true, Duration::from_secs(0)),
+            None => ev.set_direct()
+        }
+    }
+
+    pub fn send_batch(&mut self, messages: &[&[u8]]) -> Result<(), (usize, SendError)> {
+        let mut result = Ok(());
+        let queue = self.immediate_queue();
+        for (i, message) in messages.iter().enumerate() {
+            self.track_notes(message);
+            let sent = match Self::encode(&mut self.coder, message) {
+                Ok(mut ev) => {
+                    Self::schedule_now(&mut ev, queue);
+                    Self::enqueue(self.seq.as_ref().unwrap(), &mut ev, self.vport, self.dest)
+                },
+                Err(e) => Err(e)
+            };
+            if let Err(e) = sent {
+                result = Err((i, e));
+                break;
+            }
+        }
+        // Deliver what has been enqueued, even if a message failed
+        let _ = self.seq.as_ref().unwrap().drain_output();
+        result
+    }
+
+    pub fn send_stream(&mut self, bytes: &[u8]) -> Result<usize, (usize, SendError)> {
+        // The buffer only needs to hold the largest message, not the whole stream
+        let buffer_size = cmp::min(bytes.len(), self.max_message_size());
+        if buffer_size > self.coder.get_buffer_size() as usize && self.coder.resize_buffer(buffer_size as u32).is_err() {
+            return Err((0, SendError::Other("could not resize ALSA encoding buffer")));
+        }
+        let queue = self.immediate_queue();
+        let mut sent = 0; // the end of the last message that has been enqueued
+        let mut pos = 0;
+        let mut result = Ok(());
+        while pos < bytes.len() {
+            let (consumed, complete) = match self.coder.get_wrapped().encode(&bytes[pos..]) {
+                Ok((consumed, Some(mut ev))) => {
+                    Self::schedule_now(&mut ev, queue);
+                    if let Err(e) = Self::enqueue(self.seq.as_ref().unwrap(), &mut ev, self.vport, self.dest) {
+                        result = Err(e);
+                        break;
+                    }
+                    (consumed, true)
+                },
+                Ok((consumed, None)) => (consumed, false),
+                Err(_) => {
+                    result = Err(SendError::InvalidData("ALSA encoder reported invalid data"));
+                    break;
+                }
+            };
+            pos += consumed;
+            if complete {
+                self.track_notes(&bytes[sent..pos]);
+                sent = pos;
+            }
+            if consumed == 0 { break; }
+        }
+        // The encoder keeps the bytes of an incomplete message at the end, but
+        // they are left to the caller, who passes them again with the rest
+        self.coder.get_wrapped().reset_encode();
+        // Deliver what has been enqueued, even if a message failed
+        let drained = self.seq.as_ref().unwrap().drain_output();
+        match (result, drained) {
+            (Err(e), _) => Err((sent, e)),
+            (Ok(()), Err(ref e)) => Err((sent, SendError::Backend(backend_error("could not drain ALSA output buffer", e)))),
+            (Ok(()), Ok(_)) => Ok(sent)
+        }
+    }
+
+    pub fn send_buffered(&mut self, message: &[u8]) -> Result<(), SendError> {
+        self.track_notes(message);
+        let queue = self.immediate_queue();
+        let mut ev = Self::encode(&mut self.coder, message)?;
+        Self::schedule_now(&mut ev, queue);
+        Self::enqueue(self.seq.as_ref().unwrap(), &mut ev, self.vport, self.dest)
+    }
+
+    pub fn flush(&mut self) -> Result<(), SendError> {
+        match self.seq.as_ref().unwrap().drain_output() {
+            Ok(_) => Ok(()),
+            Err(ref e) => Err(SendError::Backend(backend_error("could not drain ALSA output buffer", e)))
+        }
+    }
+
+    pub fn send_scheduled(&mut self, timestamp: u64, message: &[u8]) -> Result<(), SendError> {
+        let queue_id = self.output_queue()?;
+        let timestamp = match self.schedule_transform {
+            Some(ref mut transform) => transform(timestamp),
+            None => timestamp
+        };
+
+        self.track_notes(message);
+        let mut ev = Self::encode(&mut self.coder, message)?;
+        ev.schedule_real(queue_id, false, Duration::from_micros(timestamp));
+        Self::output(self.seq.as_ref().unwrap(), &mut ev, self.vport, self.dest)
+    }
+
+    pub fn set_schedule_transform(&mut self, transform: Option<Box<dyn FnMut(u64) -> u64 + Send>>) {
+        self.schedule_transform = transform;
+    }
+
+    pub fn queue_time(&mut self) -> Result<u64, SendError> {
+        let queue_id = self.output_queue()?;
+        match self.seq.as_ref().unwrap().get_queue_status(queue_id) {
+            Ok(status) => Ok(duration_to_micros(status.get_real_time())),
+            Err(ref e) => Err(SendError::Backend(backend_error("could not get ALSA queue status", e)))
+        }
+    }
+
+    pub fn send_at_system_time(&mut self, time: SystemTime, message: &[u8]) -> Result<(), SendError> {
+        // Relate the system clock to the queue clock by reading both right after each other
+        let queue_now = self.queue_time()?;
+        let timestamp = match time.duration_since(SystemTime::now()) {
+            Ok(ahead) => queue_now + duration_to_micros(ahead),
+            Err(_) => queue_now // the time has already passed
+        };
+        self.send_scheduled(timestamp, message)
+    }
+
+    pub fn wait_for_drain(&mut self, timeout: Duration) -> Result<bool, SendError> {
+        let seq = self.seq.as_ref().unwrap();
+        let queue_id = match self.queue_id {
+            Some(queue_id) => queue_id,
+            None => return Ok(true) // nothing has ever been scheduled
+        };
+        let _ = seq.drain_output();
+        let start = Instant::now();
+        loop {
+            match seq.get_queue_status(queue_id) {
+                Ok(ref status) if status.get_events() == 0 => return Ok(true),
+                Ok(_) => {},
+                Err(ref e) => return Err(SendError::Backend(backend_error("could not get ALSA queue status", e)))
+            }
+            if start.elapsed() >= timeout {
+                return Ok(false);
+            }
+            sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn set_note_off_policy(&mut self, policy: NoteOffPolicy) {
+        self.note_off_policy = policy;
+    }
+
+    pub fn set_send_through_queue(&mut self, enabled: bool) {
+        self.send_through_queue = enabled;
+    }
+
+    pub fn shrink_buffer(&mut self) {
+        if self.coder.get_buffer_size() as usize > INITIAL_CODER_BUFFER_SIZE {
+            // If this fails, the buffer just keeps its size
+            let _ = self.coder.resize_buffer(INITIAL_CODER_BUFFER_SIZE as u32);
+        }
+    }
+
+    pub fn max_message_size(&self) -> usize {
+        // An event (header plus data) must be smaller than the output buffer,
+        // and the data of a scheduled event must fit into fewer cells than the pool has.
+        let buffer_limit = SEQ_OUTPUT_BUFFER_SIZE - SEQ_EVENT_SIZE - 1;
+        let pool_limit = (SEQ_OUTPUT_POOL_CELLS - 1) * SEQ_EVENT_SIZE;
+        cmp::min(buffer_limit, pool_limit)
+    }
+
+    /// Keeps track of the notes that are currently on, if that is required.
+    fn track_notes(&mut self, message: &[u8]) {
+        if self.note_off_policy != NoteOffPolicy::TrackNotes || message.len() < 3 {
+            return;
+        }
+        let channel = (message[0] & 0x0F) as usize;
+        let key = message[1] & 0x7F;
+        match message[0] & 0xF0 {
+            0x90 if message[2] > 0 => self.active_notes[channel] |= 1 << key,
+            0x80 | 0x90 => self.active_notes[channel] &= !(1 << key),
+            _ => {}
+        }
+    }
+
+    /// Sends note-off messages according to the `NoteOffPolicy`.
+    fn send_note_offs(&mut self) {
+        // The connection is being closed and the queue freed, which would discard queued note-offs
+        self.send_through_queue = false;
+        match self.note_off_policy {
+            NoteOffPolicy::None => {},
+            NoteOffPolicy::AllNotesOff => {
+                for channel in 0..16 {
+                    let _ = self.send(&[0xB0 | channel, 123, 0]);
+                }
+            },
+            NoteOffPolicy::TrackNotes => {
+                for channel in 0..16 {
+                    for key in 0..128 {
+                        if self.active_notes[channel as usize] & (1 << key) != 0 {
+                            let _ = self.send(&[0x80 | channel, key, 0]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the output queue, which is allocated and started on first use.
+    fn output_queue(&mut self) -> Result<i32, SendError> {
+        if let Some(queue_id) = self.queue_id {
+            return Ok(queue_id);
+        }
+        let seq = self.seq.as_ref().unwrap();
+        let queue_id = match seq.alloc_named_queue(unsafe { CStr::from_bytes_with_nul_unchecked(b"midir output queue\0") }) {
+            Ok(q) => q,
+            Err(ref e) => return Err(SendError::Backend(backend_error("could not allocate ALSA output queue", e)))
+        };
+        let _ = seq.control_queue(queue_id, EventType::Start, 0, None);
+        let _ = seq.drain_output();
+        self.queue_id = Some(queue_id);
+        self.owns_queue = true;
+        Ok(queue_id)
+    }
+
+    pub fn use_queue_of<T>(&mut self, input: &MidiInputConnection<T>) -> Result<(), SendError> {
+        if !input.timestamping {
+            return Err(SendError::Other("input connections have no queue when timestamping is disabled"));
+        }
+        self.free_output_queue();
+        self.queue_id = Some(input.queue_id);
+        Ok(())
+    }
+
+    /// Stops and frees the output queue (which discards events that are still scheduled),
+    /// unless it belongs to an input connection.
+    fn free_output_queue(&mut self) {
+        if let Some(queue_id) = self.queue_id.take() {
+            if self.owns_queue {
+                let seq = self.seq.as_ref().unwrap();
+                let _ = seq.control_queue(queue_id, EventType::Stop, 0, None);
+                let _ = seq.drain_output();
+                let _ = seq.free_queue(queue_id);
+            }
+        }
+        self.owns_queue = false;
+    }
+
+    fn encode<'a>(coder: &'a mut helpers::EventEncoder, message: &[u8]) -> Result<Event<'a>, SendError> {
         let nbytes = message.len();
         assert!(nbytes <= u32::max_value() as usize);
         
-        if nbytes > self.coder.get_buffer_size() as usize {
-            if self.coder.resize_buffer(nbytes as u32).is_err() {
+        if nbytes > coder.get_buffer_size() as usize {
+            if coder.resize_buffer(nbytes as u32).is_err() {
                 return Err(SendError::Other("could not resize ALSA encoding buffer"));
             }
         }
         
-        let mut ev = match self.coder.get_wrapped().encode(message) {
-            Ok((_, Some(ev))) => ev,
-            _ => return Err(SendError::InvalidData("ALSA encoder reported invalid data"))
-        };
+        match coder.get_wrapped().encode(message) {
+            Ok((_, Some(ev))) => Ok(ev),
+            _ => Err(SendError::InvalidData("ALSA encoder reported invalid data"))
+        }
+    }
+
+    fn output(seq: &Seq, ev: &mut Event, vport: i32, dest: Option<Addr>) -> Result<(), SendError> {
+        Self::enqueue(seq, ev, vport, dest)?;
+        // If draining fails (e.g. with `EAGAIN` when the kernel buffer is full),
+        // the event is still in the output buffer and has not been delivered yet
+        match seq.drain_output() {
+            Ok(_) => Ok(()),
+            Err(ref e) => Err(SendError::Backend(backend_error("could not drain ALSA output buffer", e)))
+        }
+    }
 
-        ev.set_source(self.vport);
-        ev.set_subs();
-        ev.set_direct();
+    /// Puts the event into the output buffer, without draining it.
+    fn enqueue(seq: &Seq, ev: &mut Event, vport: i32, dest: Option<Addr>) -> Result<(), SendError> {
+        ev.set_source(vport);
+        match dest {
+            Some(dest) => ev.set_dest(dest),
+            None => ev.set_subs()
+        }
         
         // Send the event.
-        if self.seq.as_ref().unwrap().event_output(&mut ev).is_err() {
-            return Err(SendError::Other("could not send encoded ALSA message"));
+        if let Err(ref e) = seq.event_output(ev) {
+            return Err(SendError::Backend(backend_error("could not send encoded ALSA message", e)));
         }
-        
-        let _ = self.seq.as_mut().unwrap().drain_output();
         Ok(())
     }
     
     fn close_internal(&mut self) {
-        let seq = self.seq.as_mut().unwrap();
+        self.send_note_offs();
         if let Some(ref subscription) = self.subscription {
-            let _ = seq.unsubscribe_port(subscription.get_sender(), subscription.get_dest());
+            let _ = self.seq.as_ref().unwrap().unsubscribe_port(subscription.get_sender(), subscription.get_dest());
         }
-        let _ = seq.delete_port(self.vport);
+        self.free_output_queue();
+        let _ = self.seq.as_ref().unwrap().delete_port(self.vport);
     }
 }
 
@@ -586,149 +2274,499 @@ impl Drop for MidiOutputConnection {
     }
 }
 
-fn handle_input<T>(mut data: HandlerData<T>, user_data: &mut T) -> HandlerData<T> {
-    use self::alsa::PollDescriptors;
-    use self::alsa::seq::Connect;
+/// The state of the input processing that needs to be kept from one event
+/// to the next (e.g. a partially received SysEx message).
+struct InputProcessor {
+    continue_sysex: bool,
+    skip_sysex: bool, // the rest of a SysEx message that exceeded the maximum size is being skipped
+    sysex_sender: Option<Addr>, // the sender of the last SysEx event, which the two flags above refer to
+    buffer: [u8; DECODE_BUFFER_SIZE],
+    largest_decode: usize,
+    coder: helpers::EventDecoder,
+    message: MidiMessage,
+    reorder: Option<ReorderBuffer>,
+}
 
-    let mut continue_sysex: bool = false;
-    
-    // ALSA documentation says:
-    // The required buffer size for a sequencer event it as most 12 bytes, except for System Exclusive events (which we handle separately)
-    let mut buffer = [0; 12];
-    
-    let mut coder = helpers::EventDecoder::new(false);
-    
-    let mut poll_fds: Box<[self::libc::pollfd]>;
-    {
-        let poll_desc_info = (&data.seq, Some(Direction::Capture));
-        let poll_fd_count = poll_desc_info.count() + 1;
-        let mut vec = Vec::with_capacity(poll_fd_count);
-        unsafe {    
-            vec.set_len(poll_fd_count);
-            poll_fds = vec.into_boxed_slice();
-        }
-        poll_desc_info.fill(&mut poll_fds[1..]).unwrap();
+impl InputProcessor {
+    fn new(config: &InputConfig) -> Result<InputProcessor, BackendError> {
+        let coder = helpers::EventDecoder::new(false)
+            .map_err(|ref e| backend_error("could not create ALSA event decoder", e))?;
+        Ok(InputProcessor {
+            continue_sysex: false,
+            skip_sysex: false,
+            sysex_sender: None,
+            buffer: [0; DECODE_BUFFER_SIZE],
+            largest_decode: 0,
+            coder,
+            message: MidiMessage::new(),
+            // Only hold back messages for reordering if there actually are timestamps
+            reorder: if config.reorder_delay > Duration::from_secs(0) && config.timestamping() {
+                Some(ReorderBuffer::new(config.to_timestamp(config.reorder_delay)))
+            } else {
+                None
+            }
+        })
     }
-    poll_fds[0].fd = data.trigger_rcv_fd;
-    poll_fds[0].events = self::libc::POLLIN;
 
-            
-    let mut message = MidiMessage::new();
+    /// Processes all events that are pending, without blocking. Returns the
+    /// timeout (in milliseconds) after which this needs to be called again to
+    /// deliver held back messages, or -1 if there are none.
+    fn process<T>(&mut self, data: &mut HandlerData<T>, user_data: &mut T) -> i32 {
+        use self::alsa::seq::Connect;
 
-    { // open scope where we can borrow data.seq
-    let mut seq_input = data.seq.input();
-    
-    let mut do_input = true;
-    while do_input {
-        if let Ok(0) = seq_input.event_input_pending(true) {
-            // No data pending
-            if helpers::poll(&mut poll_fds, -1) >= 0 {
-                // Read from our "channel" whether we should stop the thread 
-                if poll_fds[0].revents & self::libc::POLLIN != 0 {
-                    let _res = unsafe { self::libc::read(poll_fds[0].fd, mem::transmute(&mut do_input), mem::size_of::<bool>() as self::libc::size_t) };
-                }
+        // This needs the handler data mutably, so it comes before `timing` borrows it
+        if data.shared.rewire_requested.swap(false, Ordering::SeqCst) {
+            let request = data.shared.rewire_request.lock().unwrap().take();
+            if let Some(request) = request {
+                let _ = request.done.send(data.rewire(request.sender, request.dest));
             }
-            continue;
         }
 
-        // This is a bit weird, but we now have to decode an ALSA MIDI
-        // event (back) into MIDI bytes. We'll ignore non-MIDI types.
+        let timing = if data.config.measure_callback_time { Some(&data.shared.timing) } else { None };
 
-        // The ALSA sequencer has a maximum buffer size for MIDI sysex
-        // events of 256 bytes. If a device sends sysex messages larger
-        // than this, they are segmented into 256 byte chunks.    So,
-        // we'll watch for this and concatenate sysex chunks into a
-        // single sysex message if necessary.
-        //
-        // TODO: Figure out if this is still true (seems to not be the case)
-        //       If not (i.e., each event represents a complete message), we can
-        //       call the user callback with the byte buffer directly, without the
-        //       copying to `message.bytes` first.
-        if !continue_sysex { message.bytes.clear() }
+        if data.shared.queue_change_requested.swap(false, Ordering::SeqCst) && data.config.timestamping() {
+            // Only the owner of the queue (i.e. the sequencer handle of the handler) may control it
+            let command = if data.shared.queue_paused.load(Ordering::SeqCst) { EventType::Stop } else { EventType::Continue };
+            let _ = data.seq.control_queue(data.queue_id, command, 0, None);
+            let _ = data.seq.drain_output();
+        }
 
-        let ignore_flags = data.ignore_flags;
+        let mut seq_input = data.seq.input();
 
-        // If here, there should be data.
-        let mut ev = match seq_input.event_input() {
-            Ok(ev) => ev,
-            Err(ref e) if e.errno() == Some(self::nix::errno::Errno::ENOSPC) => {
-                let _ = writeln!(stderr(), "\nError in handle_input: ALSA MIDI input buffer overrun!\n");
-                continue;
-            },
-            Err(ref e) if e.errno() == Some(self::nix::errno::Errno::EAGAIN) => {
-                let _ = writeln!(stderr(), "\nError in handle_input: no input event from ALSA MIDI input buffer!\n");
-                continue;
-            },
-            Err(ref e) => {
-                let _ = writeln!(stderr(), "\nError in handle_input: unknown ALSA MIDI input error ({})!\n", e);
-                //perror("System reports");
-                continue;
+        if data.shared.flush_requested.swap(false, Ordering::SeqCst) {
+            // Discard everything that has been buffered up to now, without delivering it.
+            // Events that arrive in the meantime are not affected, to make sure that
+            // this terminates even if a device keeps sending.
+            let pending = seq_input.event_input_pending(true).unwrap_or(0);
+            for _ in 0..pending {
+                if seq_input.event_input().is_err() { break; }
             }
-        };
-        
-        let do_decode = match ev.get_type() {
-            EventType::PortSubscribed => {
-                if cfg!(debug) { println!("Notice from handle_input: ALSA port connection made!") };
-                false
-            },
-            EventType::PortUnsubscribed => {
-                if cfg!(debug) {
-                    let _ = writeln!(stderr(), "Notice from handle_input: ALSA port connection has closed!");
-                    let connect = ev.get_data::<Connect>().unwrap();
-                    let _ = writeln!(stderr(), "sender = {}:{}, dest = {}:{}",
-                        connect.sender.client,
-                        connect.sender.port,
-                        connect.dest.client,
-                        connect.dest.port
-                    );
+            self.continue_sysex = false;
+            self.skip_sysex = false;
+            self.message.bytes.clear();
+            if let Some(ref mut reorder) = self.reorder {
+                reorder.messages.clear();
+            }
+        }
+
+        loop {
+            match seq_input.event_input_pending(true) {
+                Ok(0) | Err(_) => break, // No data pending
+                Ok(_) => {}
+            }
+
+            // This is a bit weird, but we now have to decode an ALSA MIDI
+            // event (back) into MIDI bytes. We'll ignore non-MIDI types.
+
+            // The ALSA sequencer has a maximum buffer size for MIDI sysex
+            // events of 256 bytes. If a device sends sysex messages larger
+            // than this, they are segmented into 256 byte chunks.    So,
+            // we'll watch for this and concatenate sysex chunks into a
+            // single sysex message if necessary.
+            //
+            // If a complete SysEx message arrives in a single event, it can be
+            // passed to the callback directly (see `set_zero_copy_sysex`).
+            if !self.continue_sysex { self.message.bytes.clear() }
+
+            let ignore_flags = Ignore::from_bits(data.shared.ignore_flags.load(Ordering::Relaxed));
+
+            // If here, there should be data.
+            let mut ev = match seq_input.event_input() {
+                Ok(ev) => ev,
+                Err(ref e) if e.errno() == Some(self::nix::errno::Errno::ENOSPC) => {
+                    log_warn!("ALSA MIDI input buffer overrun, events have been dropped");
+                    data.shared.overrun_count.fetch_add(1, Ordering::Relaxed);
+                    data.shared.overrun.store(true, Ordering::Relaxed);
+                    continue;
+                },
+                Err(ref e) if e.errno() == Some(self::nix::errno::Errno::EAGAIN) => {
+                    on_eagain(data.config.eagain_policy, &data.shared.eagain_count, &mut stderr());
+                    continue;
+                },
+                Err(ref e) => {
+                    log_warn!("unknown ALSA MIDI input error ({})", e);
+                    //perror("System reports");
+                    continue;
                 }
-                false
-            },
-            EventType::Qframe => { // MIDI time code
-                !ignore_flags.contains(Ignore::Time)
-            },
-            EventType::Tick => { // 0xF9 ... MIDI timing tick
-                !ignore_flags.contains(Ignore::Time)
-            },
-            EventType::Clock => { // 0xF8 ... MIDI timing (clock) tick
-                !ignore_flags.contains(Ignore::Time)
-            },
-            EventType::Sensing => { // Active sensing
-                !ignore_flags.contains(Ignore::ActiveSense)
-            },
-            EventType::Sysex => {
-                if !ignore_flags.contains(Ignore::Sysex) {
-                    // Directly copy the data from the external buffer to our message
-                    message.bytes.extend_from_slice(ev.get_ext().unwrap());
-                    continue_sysex = *message.bytes.last().unwrap() != 0xF7;
+            };
+
+            #[cfg(feature = "diagnostics")]
+            let decoded_start = self.message.bytes.len();
+
+            let do_decode = match ev.get_type() {
+                EventType::PortSubscribed => {
+                    if let Some(connect) = ev.get_data::<Connect>() {
+                        log_debug!("ALSA port connection made (sender = {}:{}, dest = {}:{})",
+                            connect.sender.client,
+                            connect.sender.port,
+                            connect.dest.client,
+                            connect.dest.port
+                        );
+                        if data.subscription == Some((connect.sender, connect.dest)) {
+                            data.shared.source_lost.store(false, Ordering::Relaxed);
+                        }
+                        data.update_source(connect.sender, connect.dest, true);
+                        if let Some(ref mut callback) = data.config.connection_callback {
+                            callback(PortEvent::Connected {
+                                sender: (connect.sender.client, connect.sender.port),
+                                dest: (connect.dest.client, connect.dest.port)
+                            });
+                        }
+                    }
+                    false
+                },
+                EventType::PortUnsubscribed => {
+                    if let Some(connect) = ev.get_data::<Connect>() {
+                        log_debug!("ALSA port connection has closed (sender = {}:{}, dest = {}:{})",
+                            connect.sender.client,
+                            connect.sender.port,
+                            connect.dest.client,
+                            connect.dest.port
+                        );
+                        if data.subscription == Some((connect.sender, connect.dest)) {
+                            log_warn!("ALSA source port {}:{} has been disconnected", connect.sender.client, connect.sender.port);
+                            data.shared.source_lost.store(true, Ordering::Relaxed);
+                        }
+                        data.update_source(connect.sender, connect.dest, false);
+                        if let Some(ref mut callback) = data.config.connection_callback {
+                            callback(PortEvent::Disconnected {
+                                sender: (connect.sender.client, connect.sender.port),
+                                dest: (connect.dest.client, connect.dest.port)
+                            });
+                        }
+                        // A SysEx message that was interrupted (e.g. by unplugging the device) will never
+                        // be completed, but one from another sender (e.g. of a virtual port) still can
+                        if self.sysex_sender == Some(connect.sender) {
+                            if self.continue_sysex {
+                                data.shared.partial_message_count.fetch_add(1, Ordering::Relaxed);
+                            }
+                            self.continue_sysex = false;
+                            self.skip_sysex = false;
+                            self.message.bytes.clear();
+                        }
+                    }
+                    false
+                },
+                EventType::Qframe => { // MIDI time code
+                    !ignore_flags.contains(Ignore::Time)
+                },
+                EventType::Tick => { // 0xF9 ... MIDI timing tick
+                    !ignore_flags.contains(Ignore::Time)
+                },
+                EventType::Clock => { // 0xF8 ... MIDI timing (clock) tick
+                    !ignore_flags.contains(Ignore::Time)
+                },
+                EventType::Sensing => { // Active sensing
+                    !ignore_flags.contains(Ignore::ActiveSense)
+                },
+                EventType::Sysex => {
+                    self.sysex_sender = Some(ev.get_source());
+                    if !ignore_flags.contains(Ignore::Sysex) {
+                        let timestamp = data.config.event_timestamp(&ev);
+                        match data.config.sysex_chunk_callback {
+                            Some(ref mut callback) => {
+                                // Pass the chunk on directly, without assembling the complete message
+                                let chunk = ev.get_ext().unwrap();
+                                let position = match (self.continue_sysex, chunk.last() == Some(&0xF7)) {
+                                    (false, true) => SysexChunk::Complete,
+                                    (false, false) => SysexChunk::First,
+                                    (true, false) => SysexChunk::Continuation,
+                                    (true, true) => SysexChunk::Last
+                                };
+                                self.continue_sysex = position == SysexChunk::First || position == SysexChunk::Continuation;
+                                callback(timestamp, chunk, position);
+                            },
+                            None if data.config.zero_copy_sysex && self.reorder.is_none() && !self.continue_sysex
+                                    && is_single_event_sysex(ev.get_ext().unwrap(), data.config.max_sysex_size()) => {
+                                // Deliver the message from the buffer of the event, `message.bytes` stays empty
+                                let chunk = ev.get_ext().unwrap();
+                                self.skip_sysex = false;
+                                if data.config.timestamp_log_capacity > 0 {
+                                    let received = data.config.to_timestamp(data.shared.started.elapsed());
+                                    data.shared.log_timestamp(timestamp, received);
+                                }
+                                let deliver = match data.config.sysex_filter {
+                                    Some(ref ids) => ids.iter().any(|id| id.matches(chunk)),
+                                    None => true
+                                };
+                                if deliver {
+                                    invoke_callback(&mut data.callback, timing, timestamp, chunk, user_data);
+                                }
+                            },
+                            None => {
+                                let chunk = ev.get_ext().unwrap();
+                                if chunk.first() == Some(&0xF0) {
+                                    if self.continue_sysex {
+                                        // The previous message has not been terminated, and never will be
+                                        data.shared.partial_message_count.fetch_add(1, Ordering::Relaxed);
+                                        self.message.bytes.clear();
+                                    }
+                                    self.skip_sysex = false;
+                                }
+                                let max_sysex_size = data.config.max_sysex_size();
+                                if self.skip_sysex {
+                                    self.skip_sysex = chunk.last() != Some(&0xF7);
+                                } else if self.message.bytes.len() + chunk.len() > max_sysex_size {
+                                    log_warn!("SysEx message exceeds the maximum size ({} bytes) and was dropped", max_sysex_size);
+                                    self.message.bytes.clear();
+                                    self.continue_sysex = false;
+                                    self.skip_sysex = chunk.last() != Some(&0xF7);
+                                } else {
+                                    // Directly copy the data from the external buffer to our message
+                                    self.message.bytes.extend_from_slice(chunk);
+                                    self.continue_sysex = *self.message.bytes.last().unwrap() != 0xF7;
+                                }
+                            }
+                        }
+                    }
+                    false // don't ever decode sysex messages (it would unnecessarily copy the message content to another buffer)
+                },
+                _ => true
+            };
+
+            // NOTE: SysEx messages have already been "decoded" at this point!
+            if do_decode {
+                match self.coder.get_wrapped().decode(&mut self.buffer, &mut ev) {
+                    Ok(nbytes) => {
+                        if nbytes > 0 {
+                            self.message.bytes.extend_from_slice(&self.buffer[0..nbytes]);
+                        }
+                        // Events of the maximum size are legitimate (e.g. NRPN changes), but worth noticing
+                        if nbytes > self.largest_decode {
+                            self.largest_decode = nbytes;
+                            if nbytes == DECODE_BUFFER_SIZE {
+                                log_debug!("decoded ALSA MIDI event filled the whole buffer ({} bytes)", nbytes);
+                            }
+                        }
+                    },
+                    Err(ref e) if e.errno() == Some(self::nix::errno::Errno::ENOMEM) => {
+                        log_warn!("ALSA MIDI event does not fit into the decode buffer ({} bytes) and was dropped", DECODE_BUFFER_SIZE);
+                    },
+                    Err(_) => {}
                 }
-                false // don't ever decode sysex messages (it would unnecessarily copy the message content to another buffer)
-            },
-            _ => true
-        };
+            }
 
-        // NOTE: SysEx messages have already been "decoded" at this point!
-        if do_decode {
-            if let Ok(nbytes) = coder.get_wrapped().decode(&mut buffer, &mut ev) {
-                if nbytes > 0 {
-                    message.bytes.extend_from_slice(&buffer[0..nbytes]);
+            #[cfg(feature = "diagnostics")]
+            {
+                if let Some(ref mut hook) = data.config.raw_event_hook {
+                    hook(&RawEvent {
+                        event_type: format!("{:?}", ev.get_type()),
+                        ext: ev.get_ext().map(|ext| ext.to_vec()),
+                        decoded: self.message.bytes[decoded_start..].to_vec()
+                    });
+                }
+            }
+
+            if self.message.bytes.len() == 0 || self.continue_sysex { continue; }
+
+            let complete = is_complete_message(&self.message.bytes);
+            if !complete {
+                data.shared.partial_message_count.fetch_add(1, Ordering::Relaxed);
+                if data.config.partial_message_policy == PartialMessagePolicy::Drop {
+                    continue;
+                }
+            } else if message_count(&self.message.bytes) > 1 {
+                data.shared.merged_message_count.fetch_add(1, Ordering::Relaxed);
+            }
+
+            // Calculate the time stamp:
+            // Use the ALSA sequencer event time data.
+            // (thanks to Pedro Lopez-Cabanillas!).
+            // The offset compensates for the latency of the device.
+            self.message.timestamp = data.config.event_timestamp(&ev);
+
+            if data.config.timestamp_log_capacity > 0 {
+                let received = data.config.to_timestamp(data.shared.started.elapsed());
+                data.shared.log_timestamp(self.message.timestamp, received);
+            }
+
+            // Incomplete messages go to their own callback, if there is one, so that they can be told apart
+            if !complete {
+                if let Some(ref mut callback) = data.config.partial_message_callback {
+                    callback(self.message.timestamp, &self.message.bytes);
+                    continue;
+                }
+            }
+
+            // Messages that have been merged into one event are delivered one by one, if requested
+            let split = complete && data.config.split_merged_messages;
+            let mut start = 0;
+            while start < self.message.bytes.len() {
+                let end = if split { start + first_message_len(&self.message.bytes[start..]) } else { self.message.bytes.len() };
+                let message = &self.message.bytes[start..end];
+                start = end;
+
+                // Song position pointer, song select and tune request go to their own callback, if there is one
+                if let Some(ref mut callback) = data.config.system_common_callback {
+                    match message[0] {
+                        0xF2 | 0xF3 | 0xF6 => {
+                            callback(self.message.timestamp, message);
+                            continue;
+                        },
+                        _ => {}
+                    }
+                }
+
+                // Drop SysEx messages from manufacturers that we are not interested in
+                let deliver = match data.config.sysex_filter {
+                    Some(ref ids) if message[0] == 0xF0 => ids.iter().any(|id| id.matches(message)),
+                    _ => true
+                };
+
+                if deliver {
+                    match self.reorder {
+                        Some(ref mut reorder) => reorder.push(self.message.timestamp, message),
+                        None => invoke_callback(&mut data.callback, timing, self.message.timestamp, message, user_data)
+                    }
+                }
+            }
+
+            // Release the memory of an unusually large (SysEx) message
+            if let Some(max_capacity) = data.config.max_retained_capacity {
+                if self.message.bytes.capacity() > max_capacity {
+                    self.message.bytes = Vec::with_capacity(max_capacity);
                 }
             }
         }
 
-        if message.bytes.len() == 0 || continue_sysex { continue; }
+        // Deliver all held back messages that are due
+        let mut timeout = -1;
+        if let Some(ref mut reorder) = self.reorder {
+            let now = if data.shared.queue_paused.load(Ordering::Relaxed) {
+                // The time of a paused queue does not advance, so nothing would ever become due
+                u64::MAX
+            } else {
+                match data.seq.get_queue_status(data.queue_id) {
+                    Ok(status) => match data.config.tick_ppq {
+                        Some(_) => status.get_tick_time() as u64,
+                        None => data.config.to_timestamp(status.get_real_time())
+                    },
+                    Err(_) => u64::MAX // deliver everything if the time can not be determined
+                }
+            };
+            while let Some((timestamp, bytes)) = reorder.pop_due(now) {
+                invoke_callback(&mut data.callback, timing, timestamp, &bytes, user_data);
+            }
+            if let Some(due) = reorder.next_due() {
+                // Wake up in time for the next message (rounding up to full milliseconds)
+                timeout = data.config.to_millis(due - now) as i32;
+            }
+        }
+        timeout
+    }
 
-        // Calculate the time stamp:
-        // Use the ALSA sequencer event time data.
-        // (thanks to Pedro Lopez-Cabanillas!).
-        let alsa_time = ev.get_time().unwrap();
-        let secs = alsa_time.as_secs();
-        let nsecs = alsa_time.subsec_nanos();
+    /// Delivers the messages that are still held back when the connection is closed.
+    fn finish<T>(&mut self, data: &mut HandlerData<T>, user_data: &mut T) {
+        let timing = if data.config.measure_callback_time { Some(&data.shared.timing) } else { None };
+        if let Some(ref mut reorder) = self.reorder {
+            while let Some((timestamp, bytes)) = reorder.pop_due(u64::MAX) {
+                invoke_callback(&mut data.callback, timing, timestamp, &bytes, user_data);
+            }
+        }
+    }
+}
+
+fn handle_input<T>(mut data: HandlerData<T>, mut processor: InputProcessor, user_data: &mut T) -> HandlerData<T> {
+    // Without the descriptors of the sequencer, no input would ever be received
+    let mut poll_fds = match data.poll_fds() {
+        Ok(poll_fds) => poll_fds,
+        Err(err) => {
+            log_warn!("{}, stopping the input handler", err);
+            data.shared.handler_stopped.store(true, Ordering::SeqCst);
+            return data;
+        }
+    };
 
-        message.timestamp = ( secs as u64 * 1_000_000 ) + ( nsecs as u64 / 1_000 );        
-        (data.callback)(message.timestamp, &message.bytes, user_data);
+    let mut do_input = true;
+    while do_input {
+        let poll_timeout = processor.process(&mut data, user_data);
+
+        if helpers::poll(&mut poll_fds, poll_timeout) >= 0 {
+            // Read from our "channel" whether we should stop the thread 
+            if poll_fds[0].revents & self::libc::POLLIN != 0 {
+                let _res = unsafe { self::libc::read(poll_fds[0].fd, mem::transmute(&mut do_input), mem::size_of::<bool>() as self::libc::size_t) };
+            }
+        }
     }
-    
-    } // close scope where data.seq is borrowed
+
+    processor.finish(&mut data, user_data);
     data // return data back to thread owner
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eagain_policy() {
+        let count = AtomicU64::new(0);
+        let mut log = Vec::new();
+        on_eagain(EagainPolicy::Silent, &count, &mut log);
+        on_eagain(EagainPolicy::Silent, &count, &mut log);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        assert!(log.is_empty());
+
+        on_eagain(EagainPolicy::Log, &count, &mut log);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+        assert!(cfg!(feature = "logging") || !log.is_empty());
+    }
+
+    #[test]
+    fn message_completeness() {
+        assert!(is_complete_message(&[0x90, 60, 100]));
+        assert!(is_complete_message(&[0xC0, 5]));
+        assert!(is_complete_message(&[0xF8]));
+        assert!(is_complete_message(&[0xF0, 0x7E, 0xF7]));
+        assert!(is_complete_message(&[0xB0, 99, 1, 0xB0, 98, 2])); // several messages from one event
+        assert!(!is_complete_message(&[0x90, 60]));
+        assert!(!is_complete_message(&[0xE0]));
+        assert!(!is_complete_message(&[0xF2, 0]));
+        assert!(!is_complete_message(&[60, 100]));
+    }
+
+    #[test]
+    fn single_event_sysex() {
+        assert!(is_single_event_sysex(&[0xF0, 0x7E, 0xF7], 1024));
+        assert!(!is_single_event_sysex(&[0xF0, 0x7E, 0x01], 1024)); // continues in the next event
+        assert!(!is_single_event_sysex(&[0x7E, 0xF7], 1024)); // continuation of a previous event
+        assert!(!is_single_event_sysex(&[0xF0, 0x7E, 0xF7], 2));
+    }
+
+    #[test]
+    fn merged_messages() {
+        assert_eq!(message_count(&[0x90, 60, 100]), 1);
+        assert_eq!(message_count(&[0xB0, 99, 1, 0xB0, 98, 2, 0xB0, 6, 3]), 3);
+        assert_eq!(message_count(&[0xF0, 0x7E, 0xF7]), 1);
+        assert_eq!(message_count(&[0xF8]), 1);
+        assert_eq!(first_message_len(&[0x90, 60, 100]), 3);
+        assert_eq!(first_message_len(&[0xB0, 99, 1, 0xB0, 98, 2]), 3);
+        assert_eq!(first_message_len(&[0xC0, 5, 0xF8]), 2);
+        assert_eq!(first_message_len(&[0xF0, 0x7E, 0xF7]), 3);
+    }
+
+    #[test]
+    fn roundtrip() {
+        assert_eq!(encode_decode_roundtrip(&[0x90, 60, 100]).unwrap(), vec![0x90, 60, 100]);
+        // Running status is expanded
+        assert_eq!(encode_decode_roundtrip(&[0x90, 60, 100, 62, 100]).unwrap(), vec![0x90, 60, 100, 0x90, 62, 100]);
+        assert_eq!(encode_decode_roundtrip(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]).unwrap(), vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+        assert_eq!(encode_decode_roundtrip(&[0x90, 60]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn queue_timer_info() {
+        let info = "Timer for queue 0 : system timer\n  Period time : 0.004000000\n  Skew : 65536 / 65536\n\
+                    Timer for queue 3 : HR timer\n  Period time : 0.001\n  Skew : 65536 / 65536\n\
+                    Timer for queue 4 : system timer\n";
+        assert_eq!(parse_queue_timer(info, 0), Some(QueueTimerInfo {
+            name: "system timer".to_string(),
+            resolution: Duration::from_millis(4)
+        }));
+        assert_eq!(parse_queue_timer(info, 3), Some(QueueTimerInfo {
+            name: "HR timer".to_string(),
+            resolution: Duration::from_millis(1)
+        }));
+        assert_eq!(parse_queue_timer(info, 1), None);
+        assert_eq!(parse_queue_timer(info, 4), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Direct access to raw MIDI hardware devices, bypassing the sequencer.
+
+use std::io::{Read, Write};
+use std::mem;
+use std::sync::mpsc;
+use std::thread::{Builder, JoinHandle};
+use std::time::Instant;
+
+use super::alsa::{Ctl, Direction, PollDescriptors};
+use super::alsa::card;
+use super::alsa::rawmidi::{Rawmidi, Iter};
+use super::alsa::seq::EventType;
+use super::libc;
+use super::{helpers, duration_to_micros, INITIAL_CODER_BUFFER_SIZE};
+
+use ::errors::*;
+
+/// A raw MIDI device (subdevice) of a sound card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawMidiPort {
+    id: String,
+    name: String
+}
+
+impl RawMidiPort {
+    /// The ALSA device identifier, e.g. `hw:1,0,0`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The name of the device, as reported by the driver.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn raw_ports(direction: Direction) -> Vec<RawMidiPort> {
+    let mut result = Vec::new();
+    for card in card::Iter::new().filter_map(|c| c.ok()) {
+        let ctl = match Ctl::from_card(&card, false) {
+            Ok(ctl) => ctl,
+            Err(_) => continue
+        };
+        for info in Iter::new(&ctl).filter_map(|i| i.ok()) {
+            if info.get_stream() != direction { continue; }
+            result.push(RawMidiPort {
+                id: format!("hw:{},{},{}", card.get_index(), info.get_device(), info.get_subdevice()),
+                name: info.get_subdevice_name().unwrap_or_default()
+            });
+        }
+    }
+    result
+}
+
+/// Get all raw MIDI devices that can be used for input.
+pub fn raw_input_ports() -> Vec<RawMidiPort> {
+    raw_ports(Direction::Capture)
+}
+
+/// Get all raw MIDI devices that can be used for output.
+pub fn raw_output_ports() -> Vec<RawMidiPort> {
+    raw_ports(Direction::Playback)
+}
+
+/// Represents an open connection to a raw MIDI input device.
+pub struct RawMidiInputConnection<T: 'static> {
+    thread: Option<JoinHandle<T>>,
+    trigger_send_fd: i32,
+    trigger_rcv_fd: i32
+}
+
+impl<T: Send> RawMidiInputConnection<T> {
+    /// Open the given raw MIDI device for input. The callback and its data
+    /// work the same as for `MidiInput::connect`, except that timestamps are
+    /// taken when the bytes are read from the device (in microseconds since
+    /// the connection was opened).
+    pub fn connect<F>(port: &RawMidiPort, callback: F, data: T) -> Result<RawMidiInputConnection<T>, ConnectError<()>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        let mut trigger_fds = [-1, -1];
+        if unsafe { libc::pipe(trigger_fds.as_mut_ptr()) } == -1 {
+            return Err(ConnectError::other("could not create communication pipe for ALSA handler", ()));
+        }
+
+        // The raw byte stream is parsed into messages by running it through the
+        // encoder, and then decoding the resulting events, like the sequencer does.
+        let coders = helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32)
+            .and_then(|encoder| helpers::EventDecoder::new(false).map(|decoder| (encoder, decoder)));
+        let (encoder, decoder) = match coders {
+            Ok(coders) => coders,
+            Err(_) => {
+                unsafe {
+                    libc::close(trigger_fds[0]);
+                    libc::close(trigger_fds[1]);
+                }
+                return Err(ConnectError::other("could not create ALSA event coder", ()));
+            }
+        };
+
+        // The device is opened by the handler thread itself, which reports back whether that worked
+        let (opened_send, opened_rcv) = mpsc::channel();
+        let id = port.id.clone();
+        let trigger_rcv_fd = trigger_fds[0];
+        let threadbuilder = Builder::new().name(format!("midir ALSA raw MIDI input handler ({})", port.id));
+        let thread = threadbuilder.spawn(move || {
+            let mut callback = callback;
+            let mut data = data;
+            match Rawmidi::new(&id, Direction::Capture, true) {
+                Ok(rawmidi) => {
+                    let _ = opened_send.send(true);
+                    handle_raw_input(&rawmidi, trigger_rcv_fd, encoder, decoder, &mut callback, &mut data);
+                },
+                Err(_) => { let _ = opened_send.send(false); }
+            }
+            data
+        });
+
+        let conn = RawMidiInputConnection {
+            thread: thread.ok(),
+            trigger_send_fd: trigger_fds[1],
+            trigger_rcv_fd: trigger_fds[0]
+        };
+        if conn.thread.is_none() {
+            return Err(ConnectError::other("could not start ALSA raw MIDI input handler thread", ()));
+        }
+        match opened_rcv.recv() {
+            Ok(true) => Ok(conn),
+            _ => Err(ConnectError::other("could not open ALSA raw MIDI device", ())) // dropping `conn` cleans up
+        }
+    }
+}
+
+impl<T> RawMidiInputConnection<T> {
+    /// Closes the connection and returns the data that was passed to `connect`.
+    pub fn close(mut self) -> T {
+        self.close_internal().unwrap()
+    }
+
+    fn close_internal(&mut self) -> Option<T> {
+        // Request the thread to stop (it might have already stopped if opening the device failed)
+        let _res = unsafe { libc::write(self.trigger_send_fd, &false as *const bool as *const _, mem::size_of::<bool>() as libc::size_t) };
+        let data = self.thread.take().and_then(|thread| thread.join().ok());
+        unsafe {
+            libc::close(self.trigger_rcv_fd);
+            libc::close(self.trigger_send_fd);
+        }
+        data
+    }
+}
+
+impl<T> Drop for RawMidiInputConnection<T> {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.close_internal();
+        }
+    }
+}
+
+fn handle_raw_input<T, F>(rawmidi: &Rawmidi, trigger_rcv_fd: i32, mut encoder: helpers::EventEncoder, mut decoder: helpers::EventDecoder, callback: &mut F, user_data: &mut T)
+    where F: FnMut(u64, &[u8], &mut T) {
+    let start = Instant::now();
+
+    let mut poll_fds = vec![libc::pollfd { fd: trigger_rcv_fd, events: libc::POLLIN, revents: 0 }];
+    poll_fds.resize(rawmidi.count() + 1, libc::pollfd { fd: -1, events: 0, revents: 0 });
+    let filled = rawmidi.fill(&mut poll_fds[1..]).unwrap_or(0);
+    poll_fds.truncate(filled + 1);
+
+    let mut buffer = [0; 256];
+    let mut decoded = [0; 12];
+    let mut sysex = Vec::new();
+    let mut do_input = true;
+    while do_input {
+        if helpers::poll(&mut poll_fds, -1) < 0 { continue; }
+        if poll_fds[0].revents & libc::POLLIN != 0 {
+            let _res = unsafe { libc::read(trigger_rcv_fd, &mut do_input as *mut bool as *mut _, mem::size_of::<bool>() as libc::size_t) };
+            continue;
+        }
+
+        let nbytes = match rawmidi.io().read(&mut buffer) {
+            Ok(n) => n,
+            Err(_) => continue
+        };
+        let timestamp = duration_to_micros(start.elapsed());
+
+        let mut pos = 0;
+        while pos < nbytes {
+            let (consumed, ev) = match encoder.get_wrapped().encode(&buffer[pos..nbytes]) {
+                Ok(result) => result,
+                Err(_) => break
+            };
+            pos += consumed;
+            let mut ev = match ev {
+                Some(ev) => ev,
+                None if consumed == 0 => break,
+                None => continue
+            };
+            match ev.get_type() {
+                EventType::Sysex => {
+                    sysex.extend_from_slice(ev.get_ext().unwrap_or(&[]));
+                    if sysex.last() == Some(&0xF7) {
+                        callback(timestamp, &sysex, user_data);
+                        sysex.clear();
+                    }
+                },
+                _ => {
+                    if let Ok(n) = decoder.get_wrapped().decode(&mut decoded, &mut ev) {
+                        if n > 0 {
+                            callback(timestamp, &decoded[..n], user_data);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Represents an open connection to a raw MIDI output device.
+pub struct RawMidiOutputConnection {
+    rawmidi: Rawmidi
+}
+
+impl RawMidiOutputConnection {
+    /// Open the given raw MIDI device for output.
+    pub fn connect(port: &RawMidiPort) -> Result<RawMidiOutputConnection, ConnectError<()>> {
+        match Rawmidi::new(&port.id, Direction::Playback, false) {
+            Ok(rawmidi) => Ok(RawMidiOutputConnection { rawmidi }),
+            Err(_) => Err(ConnectError::other("could not open ALSA raw MIDI device", ()))
+        }
+    }
+
+    /// Send the bytes of a message to the device. The bytes are written
+    /// unmodified, so they may also contain several messages at once.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        match self.rawmidi.io().write_all(message) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(SendError::Backend(BackendError::new("could not write to ALSA raw MIDI device", e.raw_os_error())))
+        }
+    }
+}
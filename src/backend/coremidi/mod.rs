@@ -1,5 +1,7 @@
 extern crate coremidi;
 
+use std::fmt;
+use std::mem;
 use std::sync::{Arc, Mutex};
 
 use ::errors::*;
@@ -16,6 +18,13 @@ mod external {
     }
 }
 
+/// Whether any existing source or destination has the given unique id.
+fn is_unique_id_used(unique_id: i32) -> bool {
+    let unique_id = Some(unique_id as u32);
+    Sources.into_iter().any(|s| s.unique_id() == unique_id)
+        || Destinations.into_iter().any(|d| d.unique_id() == unique_id)
+}
+
 pub struct MidiInput {
     client: Client,
     ignore_flags: Ignore
@@ -37,23 +46,32 @@ impl PartialEq for MidiInputPort {
     }
 }
 
+impl fmt::Debug for MidiInputPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The endpoint is only a reference, so show the ID that identifies it (like `eq`)
+        f.debug_struct("MidiInputPort").field("unique_id", &self.source.unique_id()).finish()
+    }
+}
+
 impl MidiInput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
         match Client::new(client_name) {
             Ok(cl) => Ok(MidiInput { client: cl, ignore_flags: Ignore::None }),
-            Err(_) => Err(InitError)
+            Err(_) => Err(InitError::new(None))
         }
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiInputPort> {
-        Sources.into_iter().map(|s| ::common::MidiInputPort {
-            imp: MidiInputPort { source: Arc::new(s) }
-        }).collect()
+    pub(crate) fn ports_internal(&self) -> Vec<MidiInputPort> {
+        Sources.into_iter().map(|s| MidiInputPort { source: Arc::new(s) }).collect()
     }
 
     pub fn ignore(&mut self, flags: Ignore) {
         self.ignore_flags = flags;
     }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.ignore_flags.contains(flags)
+    }
     
     pub fn port_count(&self) -> usize {
         Sources::count()
@@ -66,6 +84,10 @@ impl MidiInput {
         }
     }
 
+    pub fn port_group(&self, _port: &MidiInputPort) -> Option<String> {
+        None
+    }
+
     fn handle_input<T>(packets: &PacketList, handler_data: &mut HandlerData<T>) {
         let continue_sysex =  &mut handler_data.continue_sysex;
         let ignore = handler_data.ignore_flags;
@@ -183,13 +205,30 @@ impl MidiInput {
         Ok(MidiInputConnection {
             client: self.client,
             details: InputConnectionDetails::Explicit(iport),
-            handler_data: handler_data
+            handler_data
         })
     }
 
     pub fn create_virtual<F, T: Send + 'static>(
         self, port_name: &str, callback: F, data: T
     ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        self.create_virtual_internal(port_name, None, callback, data)
+    }
+
+    pub fn create_virtual_with_id<F, T: Send + 'static>(
+        self, port_name: &str, unique_id: i32, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        if is_unique_id_used(unique_id) {
+            return Err(ConnectError::other("unique id is already used by another MIDI endpoint", self));
+        }
+        self.create_virtual_internal(port_name, Some(unique_id), callback, data)
+    }
+
+    fn create_virtual_internal<F, T: Send + 'static>(
+        self, port_name: &str, unique_id: Option<i32>, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
     where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
 
         let handler_data = Arc::new(Mutex::new(HandlerData {
@@ -206,10 +245,16 @@ impl MidiInput {
             Ok(p) => p,
             Err(_) => return Err(ConnectError::other("error creating MIDI input port", self))
         };
+        if let Some(unique_id) = unique_id {
+            // The port is disposed again when `vrt` is dropped
+            if Properties::unique_id().set_value(&vrt, unique_id).is_err() {
+                return Err(ConnectError::other("error setting unique id of virtual MIDI destination", self));
+            }
+        }
         Ok(MidiInputConnection {
             client: self.client,
             details: InputConnectionDetails::Virtual(vrt),
-            handler_data: handler_data
+            handler_data
         })
     }
 }
@@ -232,11 +277,25 @@ pub struct MidiInputConnection<T> {
 
 impl<T> MidiInputConnection<T> {
     pub fn close(self) -> (MidiInput, T) {
+        let (midi_in, user_data, _) = self.close_with_callback();
+        (midi_in, user_data)
+    }
+
+    pub fn close_with_callback(self) -> (MidiInput, T, ::MidiInputCallback<T>) {
         let mut handler_data_locked = self.handler_data.lock().unwrap();
+        let callback = mem::replace(&mut handler_data_locked.callback, Box::new(|_, _, _| {}));
         (MidiInput {
             client: self.client,
             ignore_flags: handler_data_locked.ignore_flags
-        }, handler_data_locked.user_data.take().unwrap())
+        }, handler_data_locked.user_data.take().unwrap(), callback)
+    }
+
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        self.handler_data.lock().unwrap().ignore_flags = flags;
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.handler_data.lock().unwrap().ignore_flags.contains(flags)
     }
 }
 
@@ -273,18 +332,23 @@ impl PartialEq for MidiOutputPort {
     }
 }
 
+impl fmt::Debug for MidiOutputPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The endpoint is only a reference, so show the ID that identifies it (like `eq`)
+        f.debug_struct("MidiOutputPort").field("unique_id", &self.dest.unique_id()).finish()
+    }
+}
+
 impl MidiOutput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
         match Client::new(client_name) {
             Ok(cl) => Ok(MidiOutput { client: cl }),
-            Err(_) => Err(InitError)
+            Err(_) => Err(InitError::new(None))
         }
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiOutputPort> {
-        Destinations.into_iter().map(|d| ::common::MidiOutputPort {
-            imp: MidiOutputPort { dest: Arc::new(d) }
-        }).collect()
+    pub(crate) fn ports_internal(&self) -> Vec<MidiOutputPort> {
+        Destinations.into_iter().map(|d| MidiOutputPort { dest: Arc::new(d) }).collect()
     }
     
     pub fn port_count(&self) -> usize {
@@ -297,6 +361,10 @@ impl MidiOutput {
             None => Err(PortInfoError::CannotRetrievePortName)
         }
     }
+
+    pub fn port_group(&self, _port: &MidiOutputPort) -> Option<String> {
+        None
+    }
     
     pub fn connect(self, port: &MidiOutputPort, port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
         let oport = match self.client.output_port(port_name) {
@@ -310,10 +378,27 @@ impl MidiOutput {
     }
 
     pub fn create_virtual(self, port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        self.create_virtual_internal(port_name, None)
+    }
+
+    pub fn create_virtual_with_id(self, port_name: &str, unique_id: i32) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        if is_unique_id_used(unique_id) {
+            return Err(ConnectError::other("unique id is already used by another MIDI endpoint", self));
+        }
+        self.create_virtual_internal(port_name, Some(unique_id))
+    }
+
+    fn create_virtual_internal(self, port_name: &str, unique_id: Option<i32>) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
         let vrt = match self.client.virtual_source(port_name) {
             Ok(p) => p,
             Err(_) => return Err(ConnectError::other("error creating virtual MIDI source", self))
         };
+        if let Some(unique_id) = unique_id {
+            // The port is disposed again when `vrt` is dropped
+            if Properties::unique_id().set_value(&vrt, unique_id).is_err() {
+                return Err(ConnectError::other("error setting unique id of virtual MIDI source", self));
+            }
+        }
         Ok(MidiOutputConnection {
             client: self.client,
             details: OutputConnectionDetails::Virtual(vrt)
@@ -0,0 +1,297 @@
+//! Selection between the ALSA and the JACK backend at runtime, which is used on
+//! Linux when the `jack` feature is enabled. Each object wraps the object of the
+//! backend it was created with, and ports can only be used with objects of the
+//! same backend.
+
+use super::alsa;
+use super::jack;
+
+pub use super::alsa::{RawMidiPort, RawMidiInputConnection, RawMidiOutputConnection, raw_input_ports, raw_output_ports};
+pub use super::alsa::encode_decode_roundtrip;
+
+use ::Ignore;
+use ::errors::*;
+
+/// The backends that can be selected on Linux when the `jack` feature is enabled
+/// (see `MidiInput::new_with_backend` and `MidiOutput::new_with_backend`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The ALSA sequencer.
+    Alsa,
+    /// The JACK Audio Connection Kit, which is used by `new`.
+    Jack,
+}
+
+impl Default for Backend {
+    fn default() -> Backend {
+        Backend::Jack
+    }
+}
+
+pub enum MidiInput {
+    Alsa(alsa::MidiInput),
+    Jack(jack::MidiInput),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MidiInputPort {
+    Alsa(alsa::MidiInputPort),
+    Jack(jack::MidiInputPort),
+}
+
+pub enum MidiInputConnection<T: 'static> {
+    Alsa(alsa::MidiInputConnection<T>),
+    Jack(jack::MidiInputConnection<T>),
+}
+
+impl MidiInput {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        Self::new_with_backend(Backend::default(), client_name)
+    }
+
+    pub fn new_with_backend(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        match backend {
+            Backend::Alsa => alsa::MidiInput::new(client_name).map(MidiInput::Alsa),
+            Backend::Jack => jack::MidiInput::new(client_name).map(MidiInput::Jack),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match *self {
+            MidiInput::Alsa(_) => Backend::Alsa,
+            MidiInput::Jack(_) => Backend::Jack,
+        }
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        match *self {
+            MidiInput::Alsa(ref mut imp) => imp.ignore(flags),
+            MidiInput::Jack(ref mut imp) => imp.ignore(flags),
+        }
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        match *self {
+            MidiInput::Alsa(ref imp) => imp.ignoring(flags),
+            MidiInput::Jack(ref imp) => imp.ignoring(flags),
+        }
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<MidiInputPort> {
+        match *self {
+            MidiInput::Alsa(ref imp) => imp.ports_internal().into_iter().map(MidiInputPort::Alsa).collect(),
+            MidiInput::Jack(ref imp) => imp.ports_internal().into_iter().map(MidiInputPort::Jack).collect(),
+        }
+    }
+
+    pub fn port_count(&self) -> usize {
+        match *self {
+            MidiInput::Alsa(ref imp) => imp.port_count(),
+            MidiInput::Jack(ref imp) => imp.port_count(),
+        }
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        match (self, port) {
+            (&MidiInput::Alsa(ref imp), &MidiInputPort::Alsa(ref port)) => imp.port_name(port),
+            (&MidiInput::Jack(ref imp), &MidiInputPort::Jack(ref port)) => imp.port_name(port),
+            _ => Err(PortInfoError::InvalidPort)
+        }
+    }
+
+    pub fn port_group(&self, port: &MidiInputPort) -> Option<String> {
+        match (self, port) {
+            (&MidiInput::Alsa(ref imp), &MidiInputPort::Alsa(ref port)) => imp.port_group(port),
+            (&MidiInput::Jack(ref imp), &MidiInputPort::Jack(ref port)) => imp.port_group(port),
+            _ => None
+        }
+    }
+
+    pub fn connect<F, T: Send>(
+        self, port: &MidiInputPort, port_name: &str, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        match *port {
+            MidiInputPort::Alsa(ref port) => match self {
+                MidiInput::Alsa(imp) => imp.connect(port, port_name, callback, data)
+                    .map(MidiInputConnection::Alsa)
+                    .map_err(|err| err.map_inner(MidiInput::Alsa)),
+                // the port belongs to the other backend
+                midi_in => Err(ConnectError::new(ConnectErrorKind::InvalidPort, midi_in))
+            },
+            MidiInputPort::Jack(ref port) => match self {
+                MidiInput::Jack(imp) => imp.connect(port, port_name, callback, data)
+                    .map(MidiInputConnection::Jack)
+                    .map_err(|err| err.map_inner(MidiInput::Jack)),
+                midi_in => Err(ConnectError::new(ConnectErrorKind::InvalidPort, midi_in))
+            }
+        }
+    }
+
+    pub fn create_virtual<F, T: Send>(
+        self, port_name: &str, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        match self {
+            MidiInput::Alsa(imp) => imp.create_virtual(port_name, callback, data)
+                .map(MidiInputConnection::Alsa)
+                .map_err(|err| err.map_inner(MidiInput::Alsa)),
+            MidiInput::Jack(imp) => imp.create_virtual(port_name, callback, data)
+                .map(MidiInputConnection::Jack)
+                .map_err(|err| err.map_inner(MidiInput::Jack)),
+        }
+    }
+}
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(self) -> (MidiInput, T) {
+        match self {
+            MidiInputConnection::Alsa(imp) => {
+                let (midi_in, data) = imp.close();
+                (MidiInput::Alsa(midi_in), data)
+            },
+            MidiInputConnection::Jack(imp) => {
+                let (midi_in, data) = imp.close();
+                (MidiInput::Jack(midi_in), data)
+            }
+        }
+    }
+
+    pub fn close_with_callback(self) -> (MidiInput, T, ::MidiInputCallback<T>) {
+        match self {
+            MidiInputConnection::Alsa(imp) => {
+                let (midi_in, data, callback) = imp.close_with_callback();
+                (MidiInput::Alsa(midi_in), data, callback)
+            },
+            MidiInputConnection::Jack(imp) => {
+                let (midi_in, data, callback) = imp.close_with_callback();
+                (MidiInput::Jack(midi_in), data, callback)
+            }
+        }
+    }
+
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        match *self {
+            MidiInputConnection::Alsa(ref mut imp) => imp.set_ignore(flags),
+            MidiInputConnection::Jack(ref mut imp) => imp.set_ignore(flags),
+        }
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        match *self {
+            MidiInputConnection::Alsa(ref imp) => imp.ignoring(flags),
+            MidiInputConnection::Jack(ref imp) => imp.ignoring(flags),
+        }
+    }
+}
+
+pub enum MidiOutput {
+    Alsa(alsa::MidiOutput),
+    Jack(jack::MidiOutput),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MidiOutputPort {
+    Alsa(alsa::MidiOutputPort),
+    Jack(jack::MidiOutputPort),
+}
+
+pub enum MidiOutputConnection {
+    Alsa(alsa::MidiOutputConnection),
+    Jack(jack::MidiOutputConnection),
+}
+
+impl MidiOutput {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        Self::new_with_backend(Backend::default(), client_name)
+    }
+
+    pub fn new_with_backend(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        match backend {
+            Backend::Alsa => alsa::MidiOutput::new(client_name).map(MidiOutput::Alsa),
+            Backend::Jack => jack::MidiOutput::new(client_name).map(MidiOutput::Jack),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match *self {
+            MidiOutput::Alsa(_) => Backend::Alsa,
+            MidiOutput::Jack(_) => Backend::Jack,
+        }
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<MidiOutputPort> {
+        match *self {
+            MidiOutput::Alsa(ref imp) => imp.ports_internal().into_iter().map(MidiOutputPort::Alsa).collect(),
+            MidiOutput::Jack(ref imp) => imp.ports_internal().into_iter().map(MidiOutputPort::Jack).collect(),
+        }
+    }
+
+    pub fn port_count(&self) -> usize {
+        match *self {
+            MidiOutput::Alsa(ref imp) => imp.port_count(),
+            MidiOutput::Jack(ref imp) => imp.port_count(),
+        }
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        match (self, port) {
+            (&MidiOutput::Alsa(ref imp), &MidiOutputPort::Alsa(ref port)) => imp.port_name(port),
+            (&MidiOutput::Jack(ref imp), &MidiOutputPort::Jack(ref port)) => imp.port_name(port),
+            _ => Err(PortInfoError::InvalidPort)
+        }
+    }
+
+    pub fn port_group(&self, port: &MidiOutputPort) -> Option<String> {
+        match (self, port) {
+            (&MidiOutput::Alsa(ref imp), &MidiOutputPort::Alsa(ref port)) => imp.port_group(port),
+            (&MidiOutput::Jack(ref imp), &MidiOutputPort::Jack(ref port)) => imp.port_group(port),
+            _ => None
+        }
+    }
+
+    pub fn connect(self, port: &MidiOutputPort, port_name: &str) -> Result<MidiOutputConnection, ConnectError<Self>> {
+        match *port {
+            MidiOutputPort::Alsa(ref port) => match self {
+                MidiOutput::Alsa(imp) => imp.connect(port, port_name)
+                    .map(MidiOutputConnection::Alsa)
+                    .map_err(|err| err.map_inner(MidiOutput::Alsa)),
+                // the port belongs to the other backend
+                midi_out => Err(ConnectError::new(ConnectErrorKind::InvalidPort, midi_out))
+            },
+            MidiOutputPort::Jack(ref port) => match self {
+                MidiOutput::Jack(imp) => imp.connect(port, port_name)
+                    .map(MidiOutputConnection::Jack)
+                    .map_err(|err| err.map_inner(MidiOutput::Jack)),
+                midi_out => Err(ConnectError::new(ConnectErrorKind::InvalidPort, midi_out))
+            }
+        }
+    }
+
+    pub fn create_virtual(self, port_name: &str) -> Result<MidiOutputConnection, ConnectError<Self>> {
+        match self {
+            MidiOutput::Alsa(imp) => imp.create_virtual(port_name)
+                .map(MidiOutputConnection::Alsa)
+                .map_err(|err| err.map_inner(MidiOutput::Alsa)),
+            MidiOutput::Jack(imp) => imp.create_virtual(port_name)
+                .map(MidiOutputConnection::Jack)
+                .map_err(|err| err.map_inner(MidiOutput::Jack)),
+        }
+    }
+}
+
+impl MidiOutputConnection {
+    pub fn close(self) -> MidiOutput {
+        match self {
+            MidiOutputConnection::Alsa(imp) => MidiOutput::Alsa(imp.close()),
+            MidiOutputConnection::Jack(imp) => MidiOutput::Jack(imp.close()),
+        }
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        match *self {
+            MidiOutputConnection::Alsa(ref mut imp) => imp.send(message),
+            MidiOutputConnection::Jack(ref mut imp) => imp.send(message),
+        }
+    }
+}
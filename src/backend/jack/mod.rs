@@ -6,6 +6,7 @@ use self::libc::c_void;
 
 use std::{mem, slice};
 use std::ffi::CString;
+use std::sync::atomic::{AtomicU8, Ordering};
 
 mod wrappers;
 use self::wrappers::*;
@@ -17,7 +18,7 @@ const OUTPUT_RINGBUFFER_SIZE: usize = 16384;
 
 struct InputHandlerData<T> {
     port: Option<MidiPort>,
-    ignore_flags: Ignore,
+    ignore_flags: AtomicU8, // shared with the process callback, so that it can be changed while connected
     callback: Box<dyn FnMut(u64, &[u8], &mut T) + Send>,
     user_data: Option<T>
 }
@@ -27,7 +28,7 @@ pub struct MidiInput {
     client: Option<Client>,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiInputPort {
     name: CString
 }
@@ -41,7 +42,7 @@ impl MidiInput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
         let client = match Client::open(client_name, JackOpenOptions::NoStartServer) {
             Ok(c) => c,
-            Err(_) => { return Err(InitError); } // TODO: maybe add message that Jack server might not be running
+            Err(_) => { return Err(InitError::new(None)); } // TODO: maybe add message that Jack server might not be running
         };
         
         Ok(MidiInput {
@@ -54,14 +55,16 @@ impl MidiInput {
         self.ignore_flags = flags;
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiInputPort> {
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.ignore_flags.contains(flags)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<MidiInputPort> {
         let ports = self.client.as_ref().unwrap().get_midi_ports(PortFlags::PortIsOutput);
         let mut result = Vec::with_capacity(ports.count());
         for i in 0..ports.count() {
-            result.push(::common::MidiInputPort {
-                imp: MidiInputPort {
-                    name: ports.get_c_name(i).into()
-                }
+            result.push(MidiInputPort {
+                name: ports.get_c_name(i).into()
             })
         }
         result
@@ -74,6 +77,11 @@ impl MidiInput {
     pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
         Ok(port.name.to_string_lossy().into())
     }
+
+    pub fn port_group(&self, port: &MidiInputPort) -> Option<String> {
+        // JACK port names have the form "client_name:port_name"
+        port.name.to_string_lossy().splitn(2, ':').next().map(|client| client.to_string())
+    }
     
     fn activate_callback<F, T: Send>(&mut self, callback: F, data: T)
             -> Box<InputHandlerData<T>>
@@ -81,7 +89,7 @@ impl MidiInput {
     {
         let handler_data = Box::new(InputHandlerData {
             port: None,
-            ignore_flags: self.ignore_flags,
+            ignore_flags: AtomicU8::new(self.ignore_flags as u8),
             callback: Box::new(callback),
             user_data: Some(data)
         });
@@ -97,6 +105,9 @@ impl MidiInput {
         mut self, port: &MidiInputPort, port_name: &str, callback: F, data: T
     ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
         where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        if port_name.contains('\0') {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self));
+        }
 
         let mut handler_data = self.activate_callback(callback, data);
         
@@ -114,7 +125,7 @@ impl MidiInput {
         handler_data.port = Some(dest_port);
         
         Ok(MidiInputConnection {
-            handler_data: handler_data,
+            handler_data,
             client: self.client.take()
         })
     }
@@ -123,7 +134,10 @@ impl MidiInput {
         mut self, port_name: &str, callback: F, data: T
     ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
     where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
-    
+        if port_name.contains('\0') {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self));
+        }
+
         let mut handler_data = self.activate_callback(callback, data);
         
         // Create port
@@ -135,20 +149,34 @@ impl MidiInput {
         handler_data.port = Some(port);
         
         Ok(MidiInputConnection {
-            handler_data: handler_data,
+            handler_data,
             client: self.client.take()
         })
     }
 }
 
 impl<T> MidiInputConnection<T> {
-    pub fn close(mut self) -> (MidiInput, T) {
+    pub fn close(self) -> (MidiInput, T) {
+        let (midi_in, user_data, _) = self.close_with_callback();
+        (midi_in, user_data)
+    }
+
+    pub fn close_with_callback(mut self) -> (MidiInput, T, ::MidiInputCallback<T>) {
         self.close_internal();
         
+        let callback = mem::replace(&mut self.handler_data.callback, Box::new(|_, _, _| {}));
         (MidiInput {
             client: self.client.take(),
-            ignore_flags: self.handler_data.ignore_flags,
-        }, self.handler_data.user_data.take().unwrap())
+            ignore_flags: Ignore::from_bits(self.handler_data.ignore_flags.load(Ordering::Relaxed)),
+        }, self.handler_data.user_data.take().unwrap(), callback)
+    }
+
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        self.handler_data.ignore_flags.store(flags as u8, Ordering::Relaxed);
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        Ignore::from_bits(self.handler_data.ignore_flags.load(Ordering::Relaxed)).contains(flags)
     }
     
     fn close_internal(&mut self) {
@@ -206,7 +234,7 @@ pub struct MidiOutput {
     client: Option<Client>,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiOutputPort {
     name: CString
 }
@@ -220,7 +248,7 @@ impl MidiOutput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
         let client = match Client::open(client_name, JackOpenOptions::NoStartServer) {
             Ok(c) => c,
-            Err(_) => { return Err(InitError); } // TODO: maybe add message that Jack server might not be running
+            Err(_) => { return Err(InitError::new(None)); } // TODO: maybe add message that Jack server might not be running
         };
         
         Ok(MidiOutput {
@@ -228,14 +256,12 @@ impl MidiOutput {
         })
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiOutputPort> {
+    pub(crate) fn ports_internal(&self) -> Vec<MidiOutputPort> {
         let ports = self.client.as_ref().unwrap().get_midi_ports(PortFlags::PortIsInput);
         let mut result = Vec::with_capacity(ports.count());
         for i in 0..ports.count() {
-            result.push(::common::MidiOutputPort {
-                imp: MidiOutputPort {
-                    name: ports.get_c_name(i).into()
-                }
+            result.push(MidiOutputPort {
+                name: ports.get_c_name(i).into()
             })
         }
         result
@@ -248,6 +274,11 @@ impl MidiOutput {
     pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
         Ok(port.name.to_string_lossy().into())
     }
+
+    pub fn port_group(&self, port: &MidiOutputPort) -> Option<String> {
+        // JACK port names have the form "client_name:port_name"
+        port.name.to_string_lossy().splitn(2, ':').next().map(|client| client.to_string())
+    }
     
     fn activate_callback(&mut self) -> Box<OutputHandlerData> {
         let handler_data = Box::new(OutputHandlerData {
@@ -264,6 +295,10 @@ impl MidiOutput {
     }
     
     pub fn connect(mut self, port: &MidiOutputPort, port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        if port_name.contains('\0') {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self));
+        }
+
         let mut handler_data = self.activate_callback();
         
         // Create port ...
@@ -280,7 +315,7 @@ impl MidiOutput {
         handler_data.port = Some(source_port);
         
         Ok(MidiOutputConnection {
-            handler_data: handler_data,
+            handler_data,
             client: self.client.take()
         })
     }
@@ -288,6 +323,10 @@ impl MidiOutput {
     pub fn create_virtual(
         mut self, port_name: &str
     ) -> Result<MidiOutputConnection, ConnectError<Self>> {
+        if port_name.contains('\0') {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPortName, self));
+        }
+
         let mut handler_data = self.activate_callback();
         
         // Create port
@@ -299,7 +338,7 @@ impl MidiOutput {
         handler_data.port = Some(port);
         
         Ok(MidiOutputConnection {
-            handler_data: handler_data,
+            handler_data,
             client: self.client.take()
         })
     }
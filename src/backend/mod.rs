@@ -1,6 +1,6 @@
 // This module is not public
 
-// TODO: improve feature selection (make sure that there is always exactly one implementation, or enable dynamic backend selection)
+// TODO: improve feature selection (make sure that there is always exactly one implementation)
 // TODO: allow to disable build dependency on ALSA
 
 #[cfg(all(target_os="windows", not(feature = "winrt")))] mod winmm;
@@ -16,11 +16,16 @@
 #[cfg(all(target_os="ios", not(feature = "jack")))] mod coremidi;
 #[cfg(all(target_os="ios", not(feature = "jack")))] pub use self::coremidi::*;
 
-#[cfg(all(target_os="linux", not(feature = "jack")))] mod alsa;
+// With the `jack` feature, the ALSA-specific extensions are not available, which leaves parts of the backend unused
+#[cfg(target_os="linux")] #[cfg_attr(feature = "jack", allow(dead_code))] mod alsa;
 #[cfg(all(target_os="linux", not(feature = "jack")))] pub use self::alsa::*;
 
 #[cfg(all(feature = "jack", not(target_os="windows")))] mod jack;
-#[cfg(all(feature = "jack", not(target_os="windows")))] pub use self::jack::*;
+#[cfg(all(feature = "jack", not(any(target_os="windows", target_os="linux"))))] pub use self::jack::*;
+
+// On Linux, both ALSA and JACK can be used when the `jack` feature is enabled, and are selected at runtime
+#[cfg(all(target_os="linux", feature = "jack"))] mod dynamic;
+#[cfg(all(target_os="linux", feature = "jack"))] pub use self::dynamic::*;
 
 #[cfg(target_arch="wasm32")] mod webmidi;
 #[cfg(target_arch="wasm32")] pub use self::webmidi::*;
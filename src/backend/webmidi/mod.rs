@@ -14,6 +14,7 @@ use self::wasm_bindgen::JsCast;
 use self::web_sys::{MidiAccess, MidiOptions, MidiMessageEvent};
 
 use std::cell::RefCell;
+use std::mem;
 use std::sync::{Arc, Mutex};
 
 use ::errors::*;
@@ -81,7 +82,7 @@ impl Static {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiInputPort {
     input: web_sys::MidiInput,
 }
@@ -96,16 +97,14 @@ impl MidiInput {
         Ok(MidiInput { ignore_flags: Ignore::None })
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiInputPort> {
+    pub(crate) fn ports_internal(&self) -> Vec<MidiInputPort> {
         STATIC.with(|s|{
             let mut v = Vec::new();
             let s = s.borrow();
             if let Some(access) = s.access.as_ref() {
                 let inputs : Map = access.inputs().unchecked_into();
                 inputs.for_each(&mut |value, _|{
-                    v.push(::common::MidiInputPort {
-                        imp: MidiInputPort { input: value.dyn_into().unwrap() }
-                    });
+                    v.push(MidiInputPort { input: value.dyn_into().unwrap() });
                 });
             }
             v
@@ -116,6 +115,10 @@ impl MidiInput {
         self.ignore_flags = flags;
     }
 
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.ignore_flags.contains(flags)
+    }
+
     pub fn port_count(&self) -> usize {
         STATIC.with(|s| {
             let s = s.borrow();
@@ -127,31 +130,39 @@ impl MidiInput {
         Ok(port.input.name().unwrap_or_else(|| port.input.id()))
     }
 
+    pub fn port_group(&self, _port: &MidiInputPort) -> Option<String> {
+        None
+    }
+
     pub fn connect<F, T: Send + 'static>(
-        self, port: &MidiInputPort, _port_name: &str, mut callback: F, data: T
+        self, port: &MidiInputPort, _port_name: &str, callback: F, data: T
     ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
         where F: FnMut(u64, &[u8], &mut T) + Send + 'static
     {
         let input = port.input.clone();
         let _ = input.open(); // NOTE: asyncronous!
 
-        let ignore_flags = self.ignore_flags;
+        let ignore_flags = Arc::new(Mutex::new(self.ignore_flags));
         let user_data = Arc::new(Mutex::new(Some(data)));
+        let callback = Arc::new(Mutex::new(Box::new(callback) as Box<dyn FnMut(u64, &[u8], &mut T) + Send>));
 
         let closure = {
             let user_data = user_data.clone();
+            let ignore_flags = ignore_flags.clone();
+            let callback = callback.clone();
 
             let closure = Closure::wrap(Box::new(move |event: MidiMessageEvent| {
                 let time = (event.time_stamp() * 1000.0) as u64; // ms -> us
                 let buffer = event.data().unwrap();
 
                 let status = buffer[0];
+                let ignore_flags = *ignore_flags.lock().unwrap();
                 if !(status == 0xF0 && ignore_flags.contains(Ignore::Sysex) ||
                         status == 0xF1 && ignore_flags.contains(Ignore::Time) ||
                         status == 0xF8 && ignore_flags.contains(Ignore::Time) ||
                         status == 0xFE && ignore_flags.contains(Ignore::ActiveSense))
                 {
-                    callback(time, &buffer[..], user_data.lock().unwrap().as_mut().unwrap());
+                    (callback.lock().unwrap())(time, &buffer[..], user_data.lock().unwrap().as_mut().unwrap());
                 }
             }) as Box<dyn FnMut(MidiMessageEvent)>);
 
@@ -160,33 +171,49 @@ impl MidiInput {
             closure
         };
 
-        Ok(MidiInputConnection { ignore_flags, input, user_data, closure })
+        Ok(MidiInputConnection { ignore_flags, input, user_data, callback, closure })
     }
 }
 
 pub struct MidiInputConnection<T> {
-    ignore_flags:   Ignore,
+    ignore_flags:   Arc<Mutex<Ignore>>,
     input:          web_sys::MidiInput,
     user_data:      Arc<Mutex<Option<T>>>,
+    callback:       Arc<Mutex<Box<dyn FnMut(u64, &[u8], &mut T) + Send>>>,
     #[allow(dead_code)] // Must be kept alive until we decide to unregister from input
     closure:        Closure<dyn FnMut(MidiMessageEvent)>,
 }
 
 impl<T> MidiInputConnection<T> {
     pub fn close(self) -> (MidiInput, T) {
-        let Self { ignore_flags, input, user_data, .. } = self;
+        let (midi_in, user_data, _) = self.close_with_callback();
+        (midi_in, user_data)
+    }
+
+    pub fn close_with_callback(self) -> (MidiInput, T, ::MidiInputCallback<T>) {
+        let Self { ignore_flags, input, user_data, callback, .. } = self;
 
         input.set_onmidimessage(None);
         let mut user_data = user_data.lock().unwrap();
+        let callback = mem::replace(&mut *callback.lock().unwrap(), Box::new(|_, _, _| {}));
 
         (
-            MidiInput { ignore_flags },
-            user_data.take().unwrap()
+            MidiInput { ignore_flags: *ignore_flags.lock().unwrap() },
+            user_data.take().unwrap(),
+            callback
         )
     }
+
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        *self.ignore_flags.lock().unwrap() = flags;
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.ignore_flags.lock().unwrap().contains(flags)
+    }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiOutputPort {
     output: web_sys::MidiOutput,
 }
@@ -200,15 +227,13 @@ impl MidiOutput {
         Ok(MidiOutput {})
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiOutputPort> {
+    pub(crate) fn ports_internal(&self) -> Vec<MidiOutputPort> {
         STATIC.with(|s|{
             let mut v = Vec::new();
             let s = s.borrow();
             if let Some(access) = s.access.as_ref() {
                 access.outputs().unchecked_into::<Map>().for_each(&mut |value, _|{
-                    v.push(::common::MidiOutputPort {
-                        imp: MidiOutputPort { output: value.dyn_into().unwrap() }
-                    });
+                    v.push(MidiOutputPort { output: value.dyn_into().unwrap() });
                 });
             }
             v
@@ -226,6 +251,10 @@ impl MidiOutput {
         Ok(port.output.name().unwrap_or_else(|| port.output.id()))
     }
 
+    pub fn port_group(&self, _port: &MidiOutputPort) -> Option<String> {
+        None
+    }
+
     pub fn connect(self, port: &MidiOutputPort, _port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
         let _ = port.output.open(); // NOTE: asyncronous!
         Ok(MidiOutputConnection{
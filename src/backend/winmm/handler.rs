@@ -1,5 +1,6 @@
 use std::{mem, slice};
 use std::io::{Write, stderr};
+use std::sync::atomic::Ordering;
 
 use super::winapi::shared::basetsd::DWORD_PTR;
 use super::winapi::shared::minwindef::{DWORD, UINT};
@@ -17,6 +18,7 @@ pub extern "system" fn handle_input<T>(_: HMIDIIN,
     if input_status != MM_MIM_DATA && input_status != MM_MIM_LONGDATA && input_status != MM_MIM_LONGERROR { return; }
     
     let data: &mut HandlerData<T> = unsafe { &mut *(instance_ptr as *mut HandlerData<T>) };
+    let ignore_flags = Ignore::from_bits(data.ignore_flags.load(Ordering::Relaxed));
     
     // Calculate time stamp.
     data.message.timestamp = timestamp as u64 * 1000; // milliseconds -> microseconds
@@ -31,14 +33,14 @@ pub extern "system" fn handle_input<T>(_: HMIDIIN,
         else if status < 0xE0 { 2 }
         else if status < 0xF0 { 3 }
         else if status == 0xF1 {
-            if data.ignore_flags.contains(Ignore::Time) { return; }
+            if ignore_flags.contains(Ignore::Time) { return; }
             else  { 2 }
         } else if status == 0xF2 { 3 }
         else if status == 0xF3 { 2 }
-        else if status == 0xF8 && (data.ignore_flags.contains(Ignore::Time)) {
+        else if status == 0xF8 && (ignore_flags.contains(Ignore::Time)) {
             // A MIDI timing tick message and we're ignoring it.
             return;
-        } else if status == 0xFE && (data.ignore_flags.contains(Ignore::ActiveSense)) {
+        } else if status == 0xFE && (ignore_flags.contains(Ignore::ActiveSense)) {
             // A MIDI active sensing message and we're ignoring it.
             return;
         } else { 1 };
@@ -49,7 +51,7 @@ pub extern "system" fn handle_input<T>(_: HMIDIIN,
         data.message.bytes.extend_from_slice(bytes);
     } else { // Sysex message (MIM_LONGDATA or MIM_LONGERROR)
         let sysex = unsafe { &*(midi_message as *const MIDIHDR) };
-        if !data.ignore_flags.contains(Ignore::Sysex) && input_status != MM_MIM_LONGERROR {
+        if !ignore_flags.contains(Ignore::Sysex) && input_status != MM_MIM_LONGERROR {
             // Sysex message and we're not ignoring it
             let bytes: &[u8] = unsafe { slice::from_raw_parts(sysex.lpData as *const u8, sysex.dwBytesRecorded as usize) };
             data.message.bytes.extend_from_slice(bytes);
@@ -74,7 +76,7 @@ pub extern "system" fn handle_input<T>(_: HMIDIIN,
                 let _ = writeln!(stderr(), "\nError in handle_input: Requeuing WinMM input sysex buffer failed.\n");
             }
             
-            if data.ignore_flags.contains(Ignore::Sysex) { return; }
+            if ignore_flags.contains(Ignore::Sysex) { return; }
         } else { return; }
     }
     
@@ -4,6 +4,7 @@ use std::{mem, ptr, slice};
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::io::{Write, stderr};
 use std::thread::sleep;
 use std::time::Duration;
@@ -51,7 +52,7 @@ pub struct MidiInput {
     ignore_flags: Ignore
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct MidiInputPort {
     name: String,
     interface_id: Box<[u16]>
@@ -145,7 +146,7 @@ struct HandlerData<T> {
     message: MidiMessage,
     sysex_buffer: SysexBuffer,
     in_handle: Option<MidiInHandle>,
-    ignore_flags: Ignore,
+    ignore_flags: AtomicU8, // read by the callback, so that the flags can be changed while connected
     callback: Box<dyn FnMut(u64, &[u8], &mut T) + Send + 'static>,
     user_data: Option<T>
 }
@@ -159,7 +160,11 @@ impl MidiInput {
         self.ignore_flags = flags;
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiInputPort> {
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.ignore_flags.contains(flags)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<MidiInputPort> {
         let count = MidiInputPort::count();
         let mut result = Vec::with_capacity(count as usize);
         for i in 0..count {
@@ -167,9 +172,7 @@ impl MidiInput {
                 Ok(p) => p,
                 Err(_) => continue
             };
-            result.push(::common::MidiInputPort {
-                imp: port
-            });
+            result.push(port);
         }
         result
     }
@@ -181,6 +184,10 @@ impl MidiInput {
     pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
         Ok(port.name.clone())
     }
+
+    pub fn port_group(&self, _port: &MidiInputPort) -> Option<String> {
+        None
+    }
     
     pub fn connect<F, T: Send>(
         self, port: &MidiInputPort, _port_name: &str, callback: F, data: T
@@ -196,7 +203,7 @@ impl MidiInput {
             message: MidiMessage::new(),
             sysex_buffer: SysexBuffer([null_mut(); RT_SYSEX_BUFFER_COUNT]),
             in_handle: None,
-            ignore_flags: self.ignore_flags,
+            ignore_flags: AtomicU8::new(self.ignore_flags as u8),
             callback: Box::new(callback),
             user_data: Some(data)
         });
@@ -256,18 +263,32 @@ impl MidiInput {
         }
         
         Ok(MidiInputConnection {
-            handler_data: handler_data
+            handler_data
         })
     }
 }
 
 impl<T> MidiInputConnection<T> {
-    pub fn close(mut self) -> (MidiInput, T) {
+    pub fn close(self) -> (MidiInput, T) {
+        let (midi_in, user_data, _) = self.close_with_callback();
+        (midi_in, user_data)
+    }
+
+    pub fn close_with_callback(mut self) -> (MidiInput, T, ::MidiInputCallback<T>) {
         self.close_internal();
         
+        let callback = mem::replace(&mut self.handler_data.callback, Box::new(|_, _, _| {}));
         (MidiInput {
-            ignore_flags: self.handler_data.ignore_flags,
-        }, self.handler_data.user_data.take().unwrap())
+            ignore_flags: Ignore::from_bits(self.handler_data.ignore_flags.load(Ordering::Relaxed)),
+        }, self.handler_data.user_data.take().unwrap(), callback)
+    }
+
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        self.handler_data.ignore_flags.store(flags as u8, Ordering::Relaxed);
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        Ignore::from_bits(self.handler_data.ignore_flags.load(Ordering::Relaxed)).contains(flags)
     }
     
     fn close_internal(&mut self) {
@@ -311,7 +332,7 @@ impl<T> Drop for MidiInputConnection<T> {
 #[derive(Debug)]
 pub struct MidiOutput;
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct MidiOutputPort {
     name: String,
     interface_id: Box<[u16]>
@@ -397,7 +418,7 @@ impl MidiOutput {
         Ok(MidiOutput)
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiOutputPort> {
+    pub(crate) fn ports_internal(&self) -> Vec<MidiOutputPort> {
         let count = MidiOutputPort::count();
         let mut result = Vec::with_capacity(count as usize);
         for i in 0..count {
@@ -405,9 +426,7 @@ impl MidiOutput {
                 Ok(p) => p,
                 Err(_) => continue
             };
-            result.push(::common::MidiOutputPort {
-                imp: port
-            });
+            result.push(port);
         }
         result
     }
@@ -419,6 +438,10 @@ impl MidiOutput {
     pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
         Ok(port.name.clone())
     }
+
+    pub fn port_group(&self, _port: &MidiOutputPort) -> Option<String> {
+        None
+    }
     
     pub fn connect(self, port: &MidiOutputPort, _port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
         let port_number = match port.current_port_number() {
@@ -1,5 +1,6 @@
 extern crate winrt;
 
+use std::mem;
 use std::sync::{Arc, Mutex};
 
 use ::errors::*;
@@ -22,7 +23,7 @@ use self::windows::devices::midi::*;
 use self::windows::devices::enumeration::DeviceInformation;
 use self::windows::storage::streams::{Buffer, DataWriter};
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiInputPort {
     id: HString
 }
@@ -91,7 +92,7 @@ unsafe impl Send for MidiInput {} // because HString doesn't ...
 
 impl MidiInput {
     pub fn new(_client_name: &str) -> Result<Self, InitError> {
-        let device_selector = MidiInPort::get_device_selector().map_err(|_| InitError)?;
+        let device_selector = MidiInPort::get_device_selector().map_err(|_| InitError::new(None))?;
         Ok(MidiInput { selector: device_selector, ignore_flags: Ignore::None })
     }
 
@@ -99,15 +100,17 @@ impl MidiInput {
         self.ignore_flags = flags;
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiInputPort> {
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.ignore_flags.contains(flags)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<MidiInputPort> {
         let device_collection = DeviceInformation::find_all_async_aqs_filter(&self.selector).unwrap().get().expect("find_all_async failed");
         let count = device_collection.size().expect("get_size failed") as usize;
         let mut result = Vec::with_capacity(count as usize);
         for device_info in device_collection.into_iter() {
             let device_id = device_info.id().expect("get_id failed");
-            result.push(::common::MidiInputPort {
-                imp: MidiInputPort { id: device_id }
-            });
+            result.push(MidiInputPort { id: device_id });
         }
         result
     }
@@ -124,6 +127,10 @@ impl MidiInput {
         Ok(device_name.to_string())
     }
 
+    pub fn port_group(&self, _port: &MidiInputPort) -> Option<String> {
+        None
+    }
+
     fn handle_input<T>(args: &MidiMessageReceivedEventArgs, handler_data: &mut HandlerData<T>) {
         let ignore = handler_data.ignore_flags;
         let data = &mut handler_data.user_data.as_mut().unwrap();
@@ -176,7 +183,7 @@ impl MidiInput {
         
         let event_token = in_port.message_received(&handler).expect("add_message_received failed");
 
-        Ok(MidiInputConnection { port: RtMidiInPort(in_port), event_token: event_token, handler_data: handler_data })
+        Ok(MidiInputConnection { port: RtMidiInPort(in_port), event_token, handler_data })
     }
 }
 
@@ -196,15 +203,29 @@ pub struct MidiInputConnection<T> {
 
 impl<T> MidiInputConnection<T> {
     pub fn close(self) -> (MidiInput, T) {
+        let (midi_in, user_data, _) = self.close_with_callback();
+        (midi_in, user_data)
+    }
+
+    pub fn close_with_callback(self) -> (MidiInput, T, ::MidiInputCallback<T>) {
         let _ = self.port.0.remove_message_received(self.event_token);
         let closable: IClosable = self.port.0.try_into().unwrap();
         let _ = closable.close();
         let device_selector = MidiInPort::get_device_selector().expect("get_device_selector failed"); // probably won't ever fail here, because it worked previously
         let mut handler_data_locked = self.handler_data.lock().unwrap();
+        let callback = mem::replace(&mut handler_data_locked.callback, Box::new(|_, _, _| {}));
         (MidiInput {
             selector: device_selector,
             ignore_flags: handler_data_locked.ignore_flags
-        }, handler_data_locked.user_data.take().unwrap())
+        }, handler_data_locked.user_data.take().unwrap(), callback)
+    }
+
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        self.handler_data.lock().unwrap().ignore_flags = flags;
+    }
+
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.handler_data.lock().unwrap().ignore_flags.contains(flags)
     }
 }
 
@@ -219,7 +240,7 @@ struct HandlerData<T> {
     user_data: Option<T>
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiOutputPort {
     id: HString
 }
@@ -234,19 +255,17 @@ unsafe impl Send for MidiOutput {} // because HString doesn't ...
 
 impl MidiOutput {
     pub fn new(_client_name: &str) -> Result<Self, InitError> {
-        let device_selector = MidiOutPort::get_device_selector().map_err(|_| InitError)?;
+        let device_selector = MidiOutPort::get_device_selector().map_err(|_| InitError::new(None))?;
         Ok(MidiOutput { selector: device_selector })
     }
 
-    pub(crate) fn ports_internal(&self) -> Vec<::common::MidiOutputPort> {
+    pub(crate) fn ports_internal(&self) -> Vec<MidiOutputPort> {
         let device_collection = DeviceInformation::find_all_async_aqs_filter(&self.selector).unwrap().get().expect("find_all_async failed");
         let count = device_collection.size().expect("get_size failed") as usize;
         let mut result = Vec::with_capacity(count as usize);
         for device_info in device_collection.into_iter() {
             let device_id = device_info.id().expect("get_id failed");
-            result.push(::common::MidiOutputPort {
-                imp: MidiOutputPort { id: device_id }
-            });
+            result.push(MidiOutputPort { id: device_id });
         }
         result
     }
@@ -262,6 +281,10 @@ impl MidiOutput {
         let device_name = device_info.name().map_err(|_| PortInfoError::CannotRetrievePortName)?;
         Ok(device_name.to_string())
     }
+
+    pub fn port_group(&self, _port: &MidiOutputPort) -> Option<String> {
+        None
+    }
     
     pub fn connect(self, port: &MidiOutputPort, _port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {        
         let out_port = match MidiOutPort::from_id_async(&port.id) {
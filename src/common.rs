@@ -1,5 +1,10 @@
 #![deny(missing_docs)]
 
+use std::num::NonZeroU32;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
 use ::errors::*;
 use ::backend::{
     MidiInputPort as MidiInputPortImpl,
@@ -10,6 +15,7 @@ use ::backend::{
     MidiOutputConnection as MidiOutputConnectionImpl
 };
 use ::Ignore;
+use ::registry::{Registration, ConnectionDirection};
 
 /// Trait that abstracts over input and output ports.
 pub trait MidiIO {
@@ -29,6 +35,28 @@ pub trait MidiIO {
     /// An error will be returned when the port is no longer valid
     /// (e.g. the respective device has been disconnected).
     fn port_name(&self, port: &Self::Port) -> Result<String, PortInfoError>;
+
+    /// Find the port to use among several matching devices, according to a list
+    /// of preferred name patterns (e.g. to always connect to the same controller
+    /// when several are plugged in). The patterns are tried in order: the first
+    /// one that is contained in the name of any port wins. If several ports
+    /// match that pattern, the first of them (in the order of `ports`) is
+    /// returned. Returns `None` if no port matches any of the patterns.
+    ///
+    /// The order of `ports` can change between runs (e.g. after a reboot), so
+    /// the result is only fully deterministic if each pattern matches only
+    /// one device. Add a last pattern `""` to fall back to the first port.
+    fn find_preferred_port(&self, preferences: &[&str]) -> Option<Self::Port> {
+        let ports = self.ports();
+        let names: Vec<Option<String>> = ports.iter().map(|p| self.port_name(p).ok()).collect();
+        for pattern in preferences {
+            let found = names.iter().position(|name| name.as_ref().is_some_and(|n| n.contains(pattern)));
+            if let Some(i) = found {
+                return Some(ports[i].clone());
+            }
+        }
+        None
+    }
 }
 
 /// An object representing a single input port.
@@ -38,7 +66,7 @@ pub trait MidiIO {
 ///
 /// Use the `ports` method of a `MidiInput` instance to obtain
 /// available ports.
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiInputPort {
     pub(crate) imp: MidiInputPortImpl
 }
@@ -46,6 +74,50 @@ pub struct MidiInputPort {
 /// A collection of input ports.
 pub type MidiInputPorts = Vec<MidiInputPort>;
 
+/// A port that has been listed by `MidiInput::ports_across_backends` or
+/// `MidiOutput::ports_across_backends`.
+#[cfg(all(target_os = "linux", feature = "jack"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendPort<P> {
+    /// The backend that the port belongs to, which has to be passed to
+    /// `new_with_backend` to create an object that can connect to it.
+    pub backend: ::Backend,
+    /// The port itself.
+    pub port: P,
+    /// The name of the port (as returned by `port_name`).
+    pub name: String,
+}
+
+#[cfg(all(target_os = "linux", feature = "jack"))]
+fn ports_across_backends<M, F>(create: F, deduplicate: bool) -> Vec<BackendPort<M::Port>>
+    where M: MidiIO, F: Fn(::Backend) -> Result<M, InitError> {
+    let mut ports: Vec<BackendPort<M::Port>> = Vec::new();
+    let mut keys = Vec::new();
+    for &backend in &[::Backend::Jack, ::Backend::Alsa] {
+        let midi_io = match create(backend) {
+            Ok(midi_io) => midi_io,
+            Err(_) => continue
+        };
+        for port in midi_io.ports() {
+            let name = match midi_io.port_name(&port) {
+                Ok(name) => name,
+                Err(_) => continue // the port has disappeared in the meantime
+            };
+            let key = ::names::device_key(&name);
+            if deduplicate && keys.iter().any(|&(b, ref k)| b != backend && *k == key) {
+                continue;
+            }
+            keys.push((backend, key));
+            ports.push(BackendPort { backend, port, name });
+        }
+    }
+    ports
+}
+
+/// Receives the messages (with their timestamps) of a connection that
+/// is made by `MidiInput::connect_channel`.
+pub type MidiInputReceiver = Receiver<(u64, Vec<u8>)>;
+
 /// An instance of `MidiInput` is required for anything related to MIDI input.
 /// Create one with `MidiInput::new`.
 pub struct MidiInput {
@@ -56,13 +128,59 @@ pub struct MidiInput {
 impl MidiInput {
     /// Creates a new `MidiInput` object that is required for any MIDI input functionality.
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        MidiInputImpl::new(client_name).map(|imp| MidiInput { imp: imp })
+        MidiInputImpl::new(client_name).map(|imp| MidiInput { imp })
+    }
+
+    /// Creates a new `MidiInput` object like `new`, using the given backend
+    /// instead of JACK (which `new` uses). This is only available on Linux with
+    /// the `jack` feature, where both backends are included. The ports of a
+    /// `MidiInput` can only be used with objects that use the same backend.
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    pub fn new_with_backend(backend: ::Backend, client_name: &str) -> Result<Self, InitError> {
+        MidiInputImpl::new_with_backend(backend, client_name).map(|imp| MidiInput { imp })
+    }
+
+    /// Get the backend that this `MidiInput` uses (see `new_with_backend`).
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    pub fn backend(&self) -> ::Backend {
+        self.imp.backend()
+    }
+
+    /// List the input ports of all backends, each tagged with the backend that
+    /// has to be used to connect to it (see `BackendPort`). A `MidiInput` is
+    /// created with the given client name for each backend, and backends that
+    /// are not available (e.g. because no JACK server is running) are skipped.
+    /// The JACK ports are listed first, followed by the ALSA ports.
+    ///
+    /// The same device can appear in both backends, e.g. if `a2jmidid` bridges
+    /// the ALSA ports to JACK. With `deduplicate`, a port is left out if a port
+    /// of another backend that has already been listed has the same name after
+    /// normalizing it with `normalize_port_name` (for ports of `a2jmidid`, the
+    /// part after the last `": "` is compared, which is the name of the ALSA
+    /// port). This is a heuristic: devices that JACK accesses directly (e.g. as
+    /// `system:midi_capture_1`) are not recognized, and different devices with
+    /// the same name in different backends are merged. Ports of the same backend
+    /// are never left out.
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    pub fn ports_across_backends(client_name: &str, deduplicate: bool) -> Vec<BackendPort<MidiInputPort>> {
+        ports_across_backends(|backend| MidiInput::new_with_backend(backend, client_name), deduplicate)
     }
     
     /// Set flags to decide what kind of messages should be ignored (i.e., filtered out)
     /// by this `MidiInput`. By default, no messages are ignored.
     pub fn ignore(&mut self, flags: Ignore) {
-       self.imp.ignore(flags);
+        self.imp.ignore(flags);
+    }
+
+    /// Returns whether messages of the given kind are currently being ignored,
+    /// according to the flags that were last passed to `ignore`. If `flags`
+    /// combines several kinds (e.g. `Ignore::SysexAndTime`), this returns `true`
+    /// if any of them is ignored. The result reflects the state at the time of
+    /// the call; connections that have already been made are not affected by
+    /// later calls to `ignore` (use `MidiInputConnection::set_ignore` instead,
+    /// and `MidiInputConnection::ignoring` to get the flags of a connection).
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.imp.ignoring(flags)
     }
 
     /// Get a collection of all MIDI input ports that *midir* can connect to.
@@ -70,7 +188,7 @@ impl MidiInput {
     /// query metadata about the port or connect to it in order to receive
     /// MIDI messages.
     pub fn ports(&self) -> MidiInputPorts {
-        self.imp.ports_internal()
+        self.imp.ports_internal().into_iter().map(|imp| MidiInputPort { imp }).collect()
     }
     
     /// Get the number of available MIDI input ports that *midir* can connect to.
@@ -85,6 +203,20 @@ impl MidiInput {
     pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
         self.imp.port_name(&port.imp)
     }
+
+    /// Get the name of the group that a specified MIDI input port belongs to,
+    /// which is shared by all ports of the same device or application. This
+    /// allows to present the ports of a multi-port device together.
+    ///
+    /// Group information is only available with the ALSA backend (where it is
+    /// the name of the sequencer client that owns the port) and with JACK (the
+    /// name of the JACK client). Ports created by *midir* itself (including
+    /// virtual ports) belong to the group named by the `client_name` that was
+    /// passed to `new`, which can not be set per port. On all other platforms,
+    /// or if the port is no longer valid, `None` is returned.
+    pub fn port_group(&self, port: &MidiInputPort) -> Option<String> {
+        self.imp.port_group(&port.imp)
+    }
     
     /// Connect to a specified MIDI input port in order to receive messages.
     /// For each incoming MIDI message, the provided `callback` function will
@@ -110,13 +242,119 @@ impl MidiInput {
         self, port: &MidiInputPort, port_name: &str, callback: F, data: T
     ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
         where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        let target = self.imp.port_name(&port.imp).ok();
         match self.imp.connect(&port.imp, port_name, callback, data) {
-            Ok(imp) => Ok(MidiInputConnection { imp: imp }),
-            Err(imp) => {
-                let kind = imp.kind();
-                Err(ConnectError::new(kind, MidiInput { imp: imp.into_inner() }))
-            } 
+            Ok(imp) => Ok(MidiInputConnection::new(imp, target, port_name, false)),
+            Err(err) => Err(err.map_inner(|imp| MidiInput { imp }))
+        }
+    }
+
+    /// Connect to a specified MIDI input port like `connect`, for a `callback`
+    /// that must not allocate memory (e.g. because it passes the messages on
+    /// to a real-time audio thread through a lock-free queue).
+    ///
+    /// In debug builds, any allocation during the callback aborts the process,
+    /// provided that `realtime::CheckedAllocator` has been installed as the
+    /// global allocator (see the `realtime` module). In release builds, nothing
+    /// is checked and this is identical to `connect`.
+    pub fn connect_realtime<F, T: Send>(
+        self, port: &MidiInputPort, port_name: &str, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        let mut callback = callback;
+        self.connect(port, port_name, move |stamp, message, data| {
+            ::realtime::forbid_allocations(|| callback(stamp, message, data))
+        }, data)
+    }
+
+    /// Connect to a specified MIDI input port like `connect`, but instead of
+    /// calling a callback, pass each incoming message (with its timestamp) into
+    /// a channel. The messages can then be received from the returned `Receiver`
+    /// on any thread, so that they can be processed outside of the input handler.
+    ///
+    /// Each message is copied into a new `Vec`. When the connection is closed or
+    /// dropped, the sending side of the channel is dropped as well, so that the
+    /// receiver reports that it is disconnected once all messages have been received.
+    pub fn connect_channel(
+        self, port: &MidiInputPort, port_name: &str
+    ) -> Result<(MidiInputConnection<()>, MidiInputReceiver), ConnectError<MidiInput>> {
+        let (sender, receiver) = channel();
+        let conn = self.connect(port, port_name, move |stamp, message, _| {
+            // The receiver may have been dropped already, in which case the message is discarded
+            let _ = sender.send((stamp, message.to_vec()));
+        }, ())?;
+        Ok((conn, receiver))
+    }
+
+    /// Start configuring a `MidiInput` that is created with the given client
+    /// name when calling `build` (see `MidiInputBuilder`).
+    pub fn builder(client_name: &str) -> MidiInputBuilder {
+        MidiInputBuilder {
+            client_name: client_name.to_string(),
+            ignore_flags: Ignore::None,
+            #[cfg(all(target_os = "linux", not(feature = "jack")))]
+            queue_tempo: None,
+            #[cfg(all(target_os = "linux", not(feature = "jack")))]
+            thread_name: None,
+        }
+    }
+}
+
+/// Collects the configuration of a `MidiInput`, which is applied when it is
+/// created by `build`. Obtain one with `MidiInput::builder`:
+///
+/// ```no_run
+/// # use midir::{MidiInput, Ignore};
+/// let midi_in = MidiInput::builder("My client").ignore(Ignore::ActiveSense).build().unwrap();
+/// ```
+///
+/// Settings that are specific to a platform are added by extension traits
+/// (e.g. `os::linux::MidiInputBuilderExt`).
+#[derive(Debug, Clone)]
+pub struct MidiInputBuilder {
+    client_name: String,
+    ignore_flags: Ignore,
+    #[cfg(all(target_os = "linux", not(feature = "jack")))]
+    queue_tempo: Option<(NonZeroU32, ::std::num::NonZeroU16)>,
+    #[cfg(all(target_os = "linux", not(feature = "jack")))]
+    thread_name: Option<String>,
+}
+
+impl MidiInputBuilder {
+    /// Set the kind of messages that are ignored (see `MidiInput::ignore`).
+    pub fn ignore(mut self, flags: Ignore) -> Self {
+        self.ignore_flags = flags;
+        self
+    }
+
+    /// Create the `MidiInput` and apply the configuration.
+    pub fn build(self) -> Result<MidiInput, InitError> {
+        let mut midi_in = MidiInput::new(&self.client_name)?;
+        midi_in.ignore(self.ignore_flags);
+        #[cfg(all(target_os = "linux", not(feature = "jack")))]
+        {
+            use ::os::linux::{MidiInputExt, ThreadConfig};
+            if let Some((tempo, ppq)) = self.queue_tempo {
+                midi_in.set_queue_tempo(tempo, ppq);
+            }
+            if self.thread_name.is_some() {
+                midi_in.set_thread_config(ThreadConfig { name: self.thread_name, ..ThreadConfig::default() });
+            }
         }
+        Ok(midi_in)
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "jack")))]
+impl ::os::linux::MidiInputBuilderExt for MidiInputBuilder {
+    fn queue_tempo(mut self, tempo: NonZeroU32, ppq: ::std::num::NonZeroU16) -> Self {
+        self.queue_tempo = Some((tempo, ppq));
+        self
+    }
+
+    fn thread_name(mut self, name: &str) -> Self {
+        self.thread_name = Some(name.to_string());
+        self
     }
 }
 
@@ -124,7 +362,7 @@ impl MidiIO for MidiInput {
     type Port = MidiInputPort;
 
     fn ports(&self) -> MidiInputPorts {
-        self.imp.ports_internal()
+        MidiInput::ports(self)
     }
 
     fn port_count(&self) -> usize {
@@ -143,28 +381,339 @@ impl<T: Send> ::os::unix::VirtualInput<T> for MidiInput {
     ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
     where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
         match self.imp.create_virtual(port_name, callback, data) {
-            Ok(imp) => Ok(MidiInputConnection { imp: imp }),
-            Err(imp) => {
-                let kind = imp.kind();
-                Err(ConnectError::new(kind, MidiInput { imp: imp.into_inner() }))
-            } 
+            Ok(imp) => Ok(MidiInputConnection::new(imp, None, port_name, true)),
+            Err(err) => Err(err.map_inner(|imp| MidiInput { imp }))
         }
     }
 }
 
+#[cfg(all(any(target_os = "macos", target_os = "ios"), not(feature = "jack")))]
+impl ::os::macos::MidiInputExt for MidiInput {
+    fn create_virtual_with_id<F, T: Send + 'static>(
+        self, port_name: &str, unique_id: i32, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        match self.imp.create_virtual_with_id(port_name, unique_id, callback, data) {
+            Ok(imp) => Ok(MidiInputConnection::new(imp, None, port_name, true)),
+            Err(err) => Err(err.map_inner(|imp| MidiInput { imp }))
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "jack")))]
+impl MidiInput {
+    /// The tempo (in microseconds per quarter note) of tick timestamps.
+    pub(crate) fn tick_tempo(&self) -> u32 {
+        self.imp.tick_tempo()
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "jack")))]
+impl ::os::linux::MidiInputExt for MidiInput {
+    fn port_capabilities(&self, port: &MidiInputPort) -> Result<::os::linux::PortCapabilities, PortInfoError> {
+        self.imp.port_capabilities(&port.imp)
+    }
+
+    fn port_address(&self, port: &MidiInputPort) -> (i32, i32) {
+        self.imp.port_address(&port.imp)
+    }
+
+    fn client_name(&self) -> Result<String, PortInfoError> {
+        self.imp.client_name()
+    }
+
+    fn set_reorder_delay(&mut self, delay: ::std::time::Duration) {
+        self.imp.set_reorder_delay(delay);
+    }
+
+    fn set_max_retained_capacity(&mut self, capacity: Option<usize>) {
+        self.imp.set_max_retained_capacity(capacity);
+    }
+
+    fn set_measure_callback_time(&mut self, enabled: bool) {
+        self.imp.set_measure_callback_time(enabled);
+    }
+
+    fn set_sysex_filter(&mut self, manufacturers: Option<Vec<::ManufacturerId>>) {
+        self.imp.set_sysex_filter(manufacturers);
+    }
+
+    fn set_eagain_policy(&mut self, policy: ::os::linux::EagainPolicy) {
+        self.imp.set_eagain_policy(policy);
+    }
+
+    #[cfg(feature = "diagnostics")]
+    fn set_raw_event_hook(&mut self, hook: Option<::os::linux::RawEventHook>) {
+        self.imp.set_raw_event_hook(hook);
+    }
+
+    fn set_external_polling(&mut self, enabled: bool) {
+        self.imp.set_external_polling(enabled);
+    }
+
+    fn set_system_common_callback(&mut self, callback: Option<::os::linux::MessageCallback>) {
+        self.imp.set_system_common_callback(callback);
+    }
+
+    fn set_connection_callback(&mut self, callback: Option<Box<dyn FnMut(::os::linux::PortEvent) + Send>>) {
+        self.imp.set_connection_callback(callback);
+    }
+
+    fn set_max_sysex_size(&mut self, size: usize) {
+        self.imp.set_max_sysex_size(size);
+    }
+
+    fn set_zero_copy_sysex(&mut self, enabled: bool) {
+        self.imp.set_zero_copy_sysex(enabled);
+    }
+
+    fn set_timestamp_log_capacity(&mut self, capacity: usize) {
+        self.imp.set_timestamp_log_capacity(capacity);
+    }
+
+    fn set_sysex_chunk_callback(&mut self, callback: Option<::os::linux::SysexChunkCallback>) {
+        self.imp.set_sysex_chunk_callback(callback);
+    }
+
+    fn set_partial_message_policy(&mut self, policy: ::os::linux::PartialMessagePolicy) {
+        self.imp.set_partial_message_policy(policy);
+    }
+
+    fn set_partial_message_callback(&mut self, callback: Option<::os::linux::MessageCallback>) {
+        self.imp.set_partial_message_callback(callback);
+    }
+
+    fn set_split_merged_messages(&mut self, enabled: bool) {
+        self.imp.set_split_merged_messages(enabled);
+    }
+
+    fn set_timestamp_offset(&mut self, offset: ::std::time::Duration) {
+        self.imp.set_timestamp_offset(offset);
+    }
+
+    fn set_nanosecond_timestamps(&mut self, enabled: bool) {
+        self.imp.set_nanosecond_timestamps(enabled);
+    }
+
+    fn set_private_ports(&mut self, enabled: bool) {
+        self.imp.set_private_ports(enabled);
+    }
+
+    fn set_port_filter(&mut self, filter: ::os::linux::PortFilter) {
+        self.imp.set_port_filter(filter);
+    }
+
+    fn set_tick_timestamps(&mut self, ppq: Option<::std::num::NonZeroU16>) {
+        self.imp.set_tick_timestamps(ppq);
+    }
+
+    fn set_timestamping(&mut self, enabled: bool) {
+        self.imp.set_timestamping(enabled);
+    }
+
+    fn set_queue_tempo(&mut self, tempo: NonZeroU32, ppq: ::std::num::NonZeroU16) {
+        self.imp.set_queue_tempo(tempo, ppq);
+    }
+
+    fn set_input_buffer_size(&mut self, bytes: usize) -> Result<(), BackendError> {
+        self.imp.set_input_buffer_size(bytes)
+    }
+
+    fn set_detach_on_drop(&mut self, enabled: bool) {
+        self.imp.set_detach_on_drop(enabled);
+    }
+
+    fn set_thread_config(&mut self, config: ::os::linux::ThreadConfig) {
+        self.imp.set_thread_config(config);
+    }
+
+    fn set_unsubscribe_timeout(&mut self, timeout: Option<Duration>) {
+        self.imp.set_unsubscribe_timeout(timeout);
+    }
+
+    fn create_virtual_connected<F, T: Send>(
+        self, port_name: &str, port: &MidiInputPort, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        let target = self.imp.port_name(&port.imp).ok();
+        match self.imp.create_virtual_connected(port_name, &port.imp, callback, data) {
+            Ok(imp) => Ok(MidiInputConnection::new(imp, target, port_name, true)),
+            Err(err) => Err(err.map_inner(|imp| MidiInput { imp }))
+        }
+    }
+
+    fn create_virtual_connected_by_name<F, T: Send>(
+        self, port_name: &str, target: &str, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        let port = self.ports().into_iter().find(|port| self.port_name(port).ok().as_deref() == Some(target));
+        match port {
+            Some(port) => self.create_virtual_connected(port_name, &port, callback, data),
+            None => Err(ConnectError::new(ConnectErrorKind::InvalidPort, self))
+        }
+    }
+}
+
+/// The callback of an input connection, as it is returned by
+/// `MidiInputConnection::close_with_callback`.
+pub type MidiInputCallback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
 /// Represents an open connection to a MIDI input port.
 pub struct MidiInputConnection<T: 'static> {
-    imp: MidiInputConnectionImpl<T>
+    imp: MidiInputConnectionImpl<T>,
+    label: Option<String>,
+    registration: Registration
 }
 
 impl<T> MidiInputConnection<T> {
+    fn new(imp: MidiInputConnectionImpl<T>, port: Option<String>, port_name: &str, is_virtual: bool) -> MidiInputConnection<T> {
+        #[cfg(all(target_os = "linux", not(feature = "jack")))]
+        let address = Some(imp.virtual_port_addr());
+        #[cfg(not(all(target_os = "linux", not(feature = "jack"))))]
+        let address = None;
+        MidiInputConnection {
+            imp,
+            label: None,
+            registration: Registration::new(ConnectionDirection::Input, port, port_name, address, is_virtual)
+        }
+    }
+
     /// Closes the connection. The returned values allow you to
     /// inspect the additional data passed to the callback (the `data`
     /// parameter of `connect`), or to reuse the `MidiInput` object,
     /// but they can be safely ignored.
     pub fn close(self) -> (MidiInput, T) {
         let (imp, data) = self.imp.close();
-        (MidiInput { imp: imp }, data)
+        (MidiInput { imp }, data)
+    }
+
+    /// Closes the connection like `close`, but also returns the callback that
+    /// was passed to `connect` (or `create_virtual`), so that it can be passed
+    /// to `connect` again without recreating the state that it has captured.
+    pub fn close_with_callback(self) -> (MidiInput, T, MidiInputCallback<T>) {
+        let (imp, data, callback) = self.imp.close_with_callback();
+        (MidiInput { imp }, data, callback)
+    }
+
+    /// Change the flags that decide what kind of messages are ignored by this
+    /// connection (initially those that were set with `MidiInput::ignore`).
+    /// Messages that have already been received, but not yet been passed to
+    /// the callback, may still be filtered using the previous flags. The
+    /// `MidiInput` returned by `close` keeps the latest flags.
+    pub fn set_ignore(&mut self, flags: Ignore) {
+        self.imp.set_ignore(flags);
+    }
+
+    /// Returns whether messages of the given kind are currently being ignored
+    /// by this connection, according to the flags that were last passed to
+    /// `set_ignore` (or to `MidiInput::ignore` before connecting). If `flags`
+    /// combines several kinds, this returns `true` if any of them is ignored.
+    pub fn ignoring(&self, flags: Ignore) -> bool {
+        self.imp.ignoring(flags)
+    }
+
+    /// Attach a label to this connection, e.g. to identify it when managing
+    /// many connections. The label is purely application-side metadata,
+    /// which is not interpreted by *midir* in any way.
+    pub fn set_label(&mut self, label: String) {
+        self.registration.set_label(Some(label.clone()));
+        self.label = Some(label);
+    }
+
+    /// Get the label that has been attached using `set_label`, if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "jack")))]
+impl<T> ::os::linux::MidiInputConnectionExt for MidiInputConnection<T> {
+    fn flush_input(&self) {
+        self.imp.flush_input();
+    }
+
+    fn pause_queue(&self) {
+        self.imp.pause_queue();
+    }
+
+    fn resume_queue(&self) {
+        self.imp.resume_queue();
+    }
+
+    fn callback_stats(&self) -> ::os::linux::CallbackStats {
+        self.imp.callback_stats()
+    }
+
+    fn eagain_count(&self) -> u64 {
+        self.imp.eagain_count()
+    }
+
+    fn partial_message_count(&self) -> u64 {
+        self.imp.partial_message_count()
+    }
+
+    fn overrun_count(&self) -> u64 {
+        self.imp.overrun_count()
+    }
+
+    fn merged_message_count(&self) -> u64 {
+        self.imp.merged_message_count()
+    }
+
+    fn has_overrun(&self) -> bool {
+        self.imp.has_overrun()
+    }
+
+    fn clear_overrun(&self) -> bool {
+        self.imp.clear_overrun()
+    }
+
+    fn source_name(&self) -> Option<String> {
+        self.imp.source_name()
+    }
+
+    fn source_has_timing(&self) -> Option<bool> {
+        self.imp.source_has_timing()
+    }
+
+    fn is_source_connected(&self) -> Option<bool> {
+        self.imp.is_source_connected()
+    }
+
+    fn virtual_port_addr(&self) -> (i32, i32) {
+        self.imp.virtual_port_addr()
+    }
+
+    fn rewire(&mut self, port: &MidiInputPort) -> Result<(), ConnectError<()>> {
+        self.imp.rewire(&port.imp)?;
+        if let Some(name) = self.imp.source_name() {
+            self.registration.set_source(name);
+        }
+        Ok(())
+    }
+
+    fn take_timestamps(&self, buffer: &mut Vec<::os::linux::TimestampSample>) -> u64 {
+        self.imp.take_timestamps(buffer)
+    }
+
+    fn queue_timer(&self) -> Option<::os::linux::QueueTimerInfo> {
+        self.imp.queue_timer()
+    }
+
+    fn queue_id(&self) -> Option<i32> {
+        self.imp.queue_id()
+    }
+
+    fn timestamp_epoch(&self) -> Option<Instant> {
+        self.imp.timestamp_epoch()
+    }
+
+    fn poll_descriptors(&self) -> Vec<::std::os::unix::io::RawFd> {
+        self.imp.poll_descriptors()
+    }
+
+    fn process_ready_events(&mut self) -> Option<::std::time::Duration> {
+        self.imp.process_ready_events()
     }
 }
 
@@ -175,7 +724,7 @@ impl<T> MidiInputConnection<T> {
 ///
 /// Use the `ports` method of a `MidiOutput` instance to obtain
 /// available ports.
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiOutputPort {
     pub(crate) imp: MidiOutputPortImpl
 }
@@ -192,7 +741,28 @@ pub struct MidiOutput {
 impl MidiOutput {
     /// Creates a new `MidiOutput` object that is required for any MIDI output functionality.
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        MidiOutputImpl::new(client_name).map(|imp| MidiOutput { imp: imp })
+        MidiOutputImpl::new(client_name).map(|imp| MidiOutput { imp })
+    }
+
+    /// Creates a new `MidiOutput` object like `new`, using the given backend
+    /// (see `MidiInput::new_with_backend`).
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    pub fn new_with_backend(backend: ::Backend, client_name: &str) -> Result<Self, InitError> {
+        MidiOutputImpl::new_with_backend(backend, client_name).map(|imp| MidiOutput { imp })
+    }
+
+    /// Get the backend that this `MidiOutput` uses (see `new_with_backend`).
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    pub fn backend(&self) -> ::Backend {
+        self.imp.backend()
+    }
+
+    /// List the output ports of all backends, each tagged with the backend that
+    /// has to be used to connect to it (see `MidiInput::ports_across_backends`,
+    /// which describes how duplicates are detected).
+    #[cfg(all(target_os = "linux", feature = "jack"))]
+    pub fn ports_across_backends(client_name: &str, deduplicate: bool) -> Vec<BackendPort<MidiOutputPort>> {
+        ports_across_backends(|backend| MidiOutput::new_with_backend(backend, client_name), deduplicate)
     }
 
     /// Get a collection of all MIDI output ports that *midir* can connect to.
@@ -200,7 +770,7 @@ impl MidiOutput {
     /// query metadata about the port or connect to it in order to send
     /// MIDI messages.
     pub fn ports(&self) -> MidiOutputPorts {
-        self.imp.ports_internal()
+        self.imp.ports_internal().into_iter().map(|imp| MidiOutputPort { imp }).collect()
     }
     
     /// Get the number of available MIDI output ports that *midir* can connect to.
@@ -215,6 +785,20 @@ impl MidiOutput {
     pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
         self.imp.port_name(&port.imp)
     }
+
+    /// Get the name of the group that a specified MIDI output port belongs to,
+    /// which is shared by all ports of the same device or application. This
+    /// allows to present the ports of a multi-port device together.
+    ///
+    /// Group information is only available with the ALSA backend (where it is
+    /// the name of the sequencer client that owns the port) and with JACK (the
+    /// name of the JACK client). Ports created by *midir* itself (including
+    /// virtual ports) belong to the group named by the `client_name` that was
+    /// passed to `new`, which can not be set per port. On all other platforms,
+    /// or if the port is no longer valid, `None` is returned.
+    pub fn port_group(&self, port: &MidiOutputPort) -> Option<String> {
+        self.imp.port_group(&port.imp)
+    }
     
     /// Connect to a specified MIDI output port in order to send messages.
     /// The connection will be kept open as long as the returned
@@ -226,12 +810,10 @@ impl MidiOutput {
     /// An error will be returned when the port is no longer valid
     /// (e.g. the respective device has been disconnected).
     pub fn connect(self, port: &MidiOutputPort, port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let target = self.imp.port_name(&port.imp).ok();
         match self.imp.connect(&port.imp, port_name) {
-            Ok(imp) => Ok(MidiOutputConnection { imp: imp }),
-            Err(imp) => {
-                let kind = imp.kind();
-                Err(ConnectError::new(kind, MidiOutput { imp: imp.into_inner() }))
-            } 
+            Ok(imp) => Ok(MidiOutputConnection::new(imp, target, port_name, false)),
+            Err(err) => Err(err.map_inner(|imp| MidiOutput { imp }))
         }
     }
 }
@@ -240,7 +822,7 @@ impl MidiIO for MidiOutput {
     type Port = MidiOutputPort;
 
     fn ports(&self) -> MidiOutputPorts {
-        self.imp.ports_internal()
+        MidiOutput::ports(self)
     }
 
     fn port_count(&self) -> usize {
@@ -256,21 +838,114 @@ impl MidiIO for MidiOutput {
 impl ::os::unix::VirtualOutput for MidiOutput {
     fn create_virtual(self, port_name: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
         match self.imp.create_virtual(port_name) {
-            Ok(imp) => Ok(MidiOutputConnection { imp: imp }),
-            Err(imp) => {
-                let kind = imp.kind();
-                Err(ConnectError::new(kind, MidiOutput { imp: imp.into_inner() }))
-            } 
+            Ok(imp) => Ok(MidiOutputConnection::new(imp, None, port_name, true)),
+            Err(err) => Err(err.map_inner(|imp| MidiOutput { imp }))
+        }
+    }
+}
+
+#[cfg(all(any(target_os = "macos", target_os = "ios"), not(feature = "jack")))]
+impl ::os::macos::MidiOutputExt for MidiOutput {
+    fn create_virtual_with_id(self, port_name: &str, unique_id: i32) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        match self.imp.create_virtual_with_id(port_name, unique_id) {
+            Ok(imp) => Ok(MidiOutputConnection::new(imp, None, port_name, true)),
+            Err(err) => Err(err.map_inner(|imp| MidiOutput { imp }))
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "jack")))]
+impl ::os::linux::MidiOutputExt for MidiOutput {
+    fn port_capabilities(&self, port: &MidiOutputPort) -> Result<::os::linux::PortCapabilities, PortInfoError> {
+        self.imp.port_capabilities(&port.imp)
+    }
+
+    fn port_address(&self, port: &MidiOutputPort) -> (i32, i32) {
+        self.imp.port_address(&port.imp)
+    }
+
+    fn client_name(&self) -> Result<String, PortInfoError> {
+        self.imp.client_name()
+    }
+
+    fn set_output_mode(&mut self, mode: ::os::linux::OutputMode) {
+        self.imp.set_output_mode(mode);
+    }
+
+    fn set_blocking(&mut self, enabled: bool) -> Result<(), BackendError> {
+        self.imp.set_blocking(enabled)
+    }
+
+    fn set_private_ports(&mut self, enabled: bool) {
+        self.imp.set_private_ports(enabled);
+    }
+
+    fn set_port_filter(&mut self, filter: ::os::linux::PortFilter) {
+        self.imp.set_port_filter(filter);
+    }
+
+    fn create_virtual_connected(self, port_name: &str, port: &MidiOutputPort) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let target = self.imp.port_name(&port.imp).ok();
+        match self.imp.create_virtual_connected(port_name, &port.imp) {
+            Ok(imp) => Ok(MidiOutputConnection::new(imp, target, port_name, true)),
+            Err(err) => Err(err.map_inner(|imp| MidiOutput { imp }))
+        }
+    }
+
+    fn create_virtual_connected_by_name(self, port_name: &str, target: &str) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let port = self.ports().into_iter().find(|port| self.port_name(port).ok().as_deref() == Some(target));
+        match port {
+            Some(port) => self.create_virtual_connected(port_name, &port),
+            None => Err(ConnectError::new(ConnectErrorKind::InvalidPort, self))
         }
     }
 }
 
 /// Represents an open connection to a MIDI output port.
 pub struct MidiOutputConnection {
-   imp: MidiOutputConnectionImpl
+   imp: MidiOutputConnectionImpl,
+   label: Option<String>,
+   registration: Registration,
+   rate_limiter: Option<RateLimiter>
+}
+
+/// Paces outgoing messages, so that no more than the given number of bytes is sent per second.
+struct RateLimiter {
+    bytes_per_second: u32,
+    next_free: Option<Instant> // the time at which the previously sent bytes have been transmitted
+}
+
+impl RateLimiter {
+    /// Blocks until the bytes that have been sent before have been transmitted
+    /// at the configured rate, and accounts for the `nbytes` that are sent next.
+    fn wait(&mut self, nbytes: usize) {
+        let now = Instant::now();
+        let start = match self.next_free {
+            Some(next_free) if next_free > now => {
+                sleep(next_free - now);
+                next_free
+            },
+            _ => now
+        };
+        let nanos = nbytes as u64 * 1_000_000_000 / self.bytes_per_second as u64;
+        self.next_free = Some(start + Duration::from_nanos(nanos));
+    }
 }
 
 impl MidiOutputConnection {
+    fn new(imp: MidiOutputConnectionImpl, port: Option<String>, port_name: &str, is_virtual: bool) -> MidiOutputConnection {
+        #[cfg(all(target_os = "linux", not(feature = "jack")))]
+        let address = Some(imp.virtual_port_addr());
+        #[cfg(not(all(target_os = "linux", not(feature = "jack"))))]
+        let address = None;
+        MidiOutputConnection {
+            imp,
+            label: None,
+            registration: Registration::new(ConnectionDirection::Output, port, port_name, address, is_virtual),
+            rate_limiter: None
+        }
+    }
+
     /// Closes the connection. The returned value allows you to
     /// reuse the `MidiOutput` object, but it can be safely ignored.
     pub fn close(self) -> MidiOutput {
@@ -280,8 +955,123 @@ impl MidiOutputConnection {
     /// Send a message to the port that this output connection is connected to.
     /// The message must be a valid MIDI message (see https://www.midi.org/specifications-old/item/table-1-summary-of-midi-message).
     pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if let Some(ref mut rate_limiter) = self.rate_limiter {
+            rate_limiter.wait(message.len());
+        }
         self.imp.send(message)
     }
+
+    /// Limit the rate at which messages are sent to the given number of bytes
+    /// per second, e.g. to avoid overrunning the input buffer of a device with
+    /// a slow serial MIDI connection (31250 baud corresponds to 3125 bytes per
+    /// second) during a bulk dump. Pass `None` to remove the limit, which is the default.
+    ///
+    /// The limit is enforced by `send`, which blocks the calling thread (using
+    /// `std::thread::sleep`) until all previously sent bytes would have been
+    /// transmitted at that rate. A message that is sent after a pause goes out
+    /// immediately, but one that follows a burst of messages is delayed, which
+    /// adds latency accordingly. Messages that are scheduled by the backend
+    /// (e.g. `send_scheduled` on ALSA) are not paced.
+    pub fn set_max_byte_rate(&mut self, bytes_per_second: Option<NonZeroU32>) {
+        self.rate_limiter = bytes_per_second.map(|rate| RateLimiter {
+            bytes_per_second: rate.get(),
+            next_free: None
+        });
+    }
+
+    /// Attach a label to this connection, e.g. to identify it when managing
+    /// many connections. The label is purely application-side metadata,
+    /// which is not interpreted by *midir* in any way.
+    pub fn set_label(&mut self, label: String) {
+        self.registration.set_label(Some(label.clone()));
+        self.label = Some(label);
+    }
+
+    /// Get the label that has been attached using `set_label`, if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "jack")))]
+impl ::os::linux::MidiOutputConnectionExt for MidiOutputConnection {
+    fn output_mode(&self) -> ::os::linux::OutputMode {
+        self.imp.output_mode()
+    }
+
+    fn virtual_port_addr(&self) -> (i32, i32) {
+        self.imp.virtual_port_addr()
+    }
+
+    fn queue_time(&mut self) -> Result<u64, SendError> {
+        self.imp.queue_time()
+    }
+
+    fn send_scheduled(&mut self, timestamp: u64, message: &[u8]) -> Result<(), SendError> {
+        self.imp.send_scheduled(timestamp, message)
+    }
+
+    fn send_at_system_time(&mut self, time: ::std::time::SystemTime, message: &[u8]) -> Result<(), SendError> {
+        self.imp.send_at_system_time(time, message)
+    }
+
+    fn send_batch(&mut self, messages: &[&[u8]]) -> Result<(), (usize, SendError)> {
+        if self.rate_limiter.is_none() {
+            return self.imp.send_batch(messages);
+        }
+        // Sending all messages at once would defeat the rate limit
+        for (i, message) in messages.iter().enumerate() {
+            self.send(message).map_err(|e| (i, e))?;
+        }
+        Ok(())
+    }
+
+    fn send_stream(&mut self, bytes: &[u8]) -> Result<usize, (usize, SendError)> {
+        if let Some(ref mut rate_limiter) = self.rate_limiter {
+            rate_limiter.wait(bytes.len());
+        }
+        self.imp.send_stream(bytes)
+    }
+
+    fn send_buffered(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if self.rate_limiter.is_some() {
+            // Buffering messages would defeat the rate limit
+            return self.send(message);
+        }
+        self.imp.send_buffered(message)
+    }
+
+    fn flush(&mut self) -> Result<(), SendError> {
+        self.imp.flush()
+    }
+
+    fn use_queue_of<T>(&mut self, input: &MidiInputConnection<T>) -> Result<(), SendError> {
+        self.imp.use_queue_of(&input.imp)
+    }
+
+    fn set_schedule_transform(&mut self, transform: Option<Box<dyn FnMut(u64) -> u64 + Send>>) {
+        self.imp.set_schedule_transform(transform);
+    }
+
+    fn wait_for_drain(&mut self, timeout: ::std::time::Duration) -> Result<bool, SendError> {
+        self.imp.wait_for_drain(timeout)
+    }
+
+    fn set_note_off_policy(&mut self, policy: ::os::linux::NoteOffPolicy) {
+        self.imp.set_note_off_policy(policy);
+    }
+
+    fn set_send_through_queue(&mut self, enabled: bool) {
+        self.imp.set_send_through_queue(enabled);
+    }
+
+    fn max_message_size(&self) -> usize {
+        self.imp.max_message_size()
+    }
+
+    fn shrink_buffer(&mut self) {
+        self.imp.shrink_buffer();
+    }
 }
 
 #[cfg(test)]
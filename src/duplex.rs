@@ -0,0 +1,287 @@
+#![deny(missing_docs)]
+
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::{Duration, Instant};
+
+use ::errors::*;
+use ::common::*;
+use ::ManufacturerId;
+
+/// Universal Identity Request, addressed to all devices (device ID `0x7F`).
+const IDENTITY_REQUEST: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+
+/// Represents a pair of open connections to the input and the output port
+/// of the same device (e.g. a control surface that provides feedback).
+pub struct MidiDuplexConnection<T: 'static> {
+    input: MidiInputConnection<T>,
+    output: MidiOutputConnection,
+    replies: Arc<ReplyWaiter>
+}
+
+/// Allows `send_and_wait` to wait for a message from the input callback.
+struct ReplyWaiter {
+    pending: Mutex<Option<PendingReply>>,
+    received: Condvar
+}
+
+/// Decides whether an incoming message is the reply that is waited for.
+type ReplyMatcher = Box<dyn FnMut(&[u8]) -> bool + Send>;
+
+struct PendingReply {
+    matcher: ReplyMatcher,
+    reply: Option<Vec<u8>>
+}
+
+impl ReplyWaiter {
+    fn new() -> ReplyWaiter {
+        ReplyWaiter {
+            pending: Mutex::new(None),
+            received: Condvar::new()
+        }
+    }
+
+    /// Checks whether the message is the reply that is waited for,
+    /// in which case it is taken and `true` is returned.
+    fn offer(&self, message: &[u8]) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(ref mut pending) = *pending {
+            if pending.reply.is_none() && (pending.matcher)(message) {
+                pending.reply = Some(message.to_vec());
+                self.received.notify_one();
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The identity of a device, as reported in its reply to a Universal
+/// Identity Request (see `MidiDuplexConnection::request_identity`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    /// The manufacturer of the device.
+    pub manufacturer: ManufacturerId,
+    /// The device family code (14 bits).
+    pub family: u16,
+    /// The device family member (model) code (14 bits).
+    pub model: u16,
+    /// The software revision level, whose meaning is manufacturer-specific.
+    pub version: [u8; 4],
+}
+
+impl DeviceIdentity {
+    /// Parses an Identity Reply message, which has the following format:
+    ///
+    /// `F0 7E <device ID> 06 02 <manufacturer ID> <family> <model> <version> F7`
+    ///
+    /// The manufacturer ID takes one or three bytes (see `ManufacturerId`), the
+    /// family and model codes take two bytes each (least significant first, 7 bits
+    /// each), and the version takes four bytes. Returns `None` if the message is
+    /// not a valid Identity Reply.
+    pub fn parse(message: &[u8]) -> Option<DeviceIdentity> {
+        if message.len() < 5 || message[0] != 0xF0 || message[1] != 0x7E || message[3] != 0x06 || message[4] != 0x02 {
+            return None;
+        }
+        let (manufacturer, rest) = match message.get(5) {
+            Some(&0x00) if message.len() >= 8 => (ManufacturerId::Extended(message[6], message[7]), &message[8..]),
+            Some(&id) if id != 0x00 => (ManufacturerId::Standard(id), &message[6..]),
+            _ => return None
+        };
+        if rest.len() != 9 || rest[8] != 0xF7 {
+            return None;
+        }
+        Some(DeviceIdentity {
+            manufacturer,
+            family: rest[0] as u16 | (rest[1] as u16) << 7,
+            model: rest[2] as u16 | (rest[3] as u16) << 7,
+            version: [rest[4], rest[5], rest[6], rest[7]]
+        })
+    }
+}
+
+impl<T: Send> MidiDuplexConnection<T> {
+    /// Connect to both the input and the output port of the device with the
+    /// given name. The first input port and the first output port whose names
+    /// contain `device_name` are used. The remaining parameters have the same
+    /// meaning as for `MidiInput::connect`.
+    ///
+    /// An error will be returned if no matching port could be found for either
+    /// direction, or when connecting to one of the ports fails. In that case,
+    /// neither of the two ports stays connected.
+    pub fn connect<F>(
+        midi_in: MidiInput, midi_out: MidiOutput, device_name: &str, port_name: &str, callback: F, data: T
+    ) -> Result<MidiDuplexConnection<T>, ConnectError<(MidiInput, MidiOutput)>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static {
+        let in_port = midi_in.ports().into_iter().find(|p| {
+            midi_in.port_name(p).map(|name| name.contains(device_name)).unwrap_or(false)
+        });
+        let out_port = midi_out.ports().into_iter().find(|p| {
+            midi_out.port_name(p).map(|name| name.contains(device_name)).unwrap_or(false)
+        });
+
+        let (in_port, out_port) = match (in_port, out_port) {
+            (Some(i), Some(o)) => (i, o),
+            (None, Some(_)) => return Err(ConnectError::other("no input port found for the device (only output)", (midi_in, midi_out))),
+            (Some(_), None) => return Err(ConnectError::other("no output port found for the device (only input)", (midi_in, midi_out))),
+            (None, None) => return Err(ConnectError::other("no port found for the device", (midi_in, midi_out)))
+        };
+
+        // Replies to `send_and_wait` are intercepted before they reach the callback
+        let replies = Arc::new(ReplyWaiter::new());
+        let waiter = replies.clone();
+        let mut callback = callback;
+        let input = match midi_in.connect(&in_port, port_name, move |stamp, message, data| {
+            if !waiter.offer(message) {
+                callback(stamp, message, data);
+            }
+        }, data) {
+            Ok(conn) => conn,
+            Err(err) => return Err(err.map_inner(|midi_in| (midi_in, midi_out)))
+        };
+
+        let output = match midi_out.connect(&out_port, port_name) {
+            Ok(conn) => conn,
+            Err(err) => {
+                let (midi_in, _) = input.close();
+                return Err(err.map_inner(|midi_out| (midi_in, midi_out)));
+            }
+        };
+
+        Ok(MidiDuplexConnection {
+            input,
+            output,
+            replies
+        })
+    }
+}
+
+impl<T> MidiDuplexConnection<T> {
+    /// Combines two existing connections (which should usually belong to the same device).
+    ///
+    /// Because the callback of the input connection has already been set,
+    /// `send_and_wait` can not receive any replies on a connection created this way.
+    pub fn new(input: MidiInputConnection<T>, output: MidiOutputConnection) -> MidiDuplexConnection<T> {
+        MidiDuplexConnection {
+            input,
+            output,
+            replies: Arc::new(ReplyWaiter::new())
+        }
+    }
+
+    /// Get the input connection.
+    pub fn input(&self) -> &MidiInputConnection<T> {
+        &self.input
+    }
+
+    /// Get the output connection.
+    pub fn output(&mut self) -> &mut MidiOutputConnection {
+        &mut self.output
+    }
+
+    /// Send a message to the output port of the device.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        self.output.send(message)
+    }
+
+    /// Send a request to the device and wait for its reply, e.g. a SysEx dump
+    /// request followed by the dump. The first incoming message for which
+    /// `is_reply` returns `true` is returned, and is not passed to the callback
+    /// (all other messages are passed to the callback as usual). `is_reply` is
+    /// called on the input handler thread, so it should return quickly.
+    ///
+    /// If no reply has arrived within `timeout`, `Ok(None)` is returned and
+    /// a reply that arrives later is passed to the callback. An error is
+    /// returned if the request can not be sent.
+    ///
+    /// This only works for connections created with `MidiDuplexConnection::connect`.
+    pub fn send_and_wait<M>(&mut self, request: &[u8], is_reply: M, timeout: Duration) -> Result<Option<Vec<u8>>, SendError>
+        where M: FnMut(&[u8]) -> bool + Send + 'static {
+        // Start waiting before sending, so that a fast reply can not be missed
+        *self.replies.pending.lock().unwrap() = Some(PendingReply {
+            matcher: Box::new(is_reply),
+            reply: None
+        });
+
+        if let Err(err) = self.output.send(request) {
+            *self.replies.pending.lock().unwrap() = None;
+            return Err(err);
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut pending = self.replies.pending.lock().unwrap();
+        loop {
+            if let Some(reply) = pending.as_mut().and_then(|p| p.reply.take()) {
+                *pending = None;
+                return Ok(Some(reply));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                *pending = None;
+                return Ok(None);
+            }
+            pending = self.replies.received.wait_timeout(pending, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Ask the device for its identity by sending a Universal Identity Request
+    /// (`F0 7E 7F 06 01 F7`, addressed to all device IDs) and waiting for the
+    /// Identity Reply (see `DeviceIdentity::parse` for its format), using
+    /// `send_and_wait`. An error is returned if the request can not be sent,
+    /// or if the device does not reply within `timeout` (many devices do not
+    /// implement this request).
+    ///
+    /// This only works for connections created with `MidiDuplexConnection::connect`.
+    pub fn request_identity(&mut self, timeout: Duration) -> Result<DeviceIdentity, SendError> {
+        let reply = self.send_and_wait(&IDENTITY_REQUEST, |message| DeviceIdentity::parse(message).is_some(), timeout)?;
+        match reply.and_then(|message| DeviceIdentity::parse(&message)) {
+            Some(identity) => Ok(identity),
+            None => Err(SendError::Other("the device did not reply to the identity request in time"))
+        }
+    }
+
+    /// Split the duplex connection into its input and output connection.
+    pub fn into_inner(self) -> (MidiInputConnection<T>, MidiOutputConnection) {
+        (self.input, self.output)
+    }
+
+    /// Schedule output on the queue of the input connection, so that both
+    /// directions share one timebase (see `MidiOutputConnectionExt::use_queue_of`).
+    /// This makes timing coherent when echoing or transforming the input of
+    /// the device back to it, and when measuring round-trip times.
+    #[cfg(all(target_os = "linux", not(feature = "jack")))]
+    pub fn share_queue(&mut self) -> Result<(), SendError> {
+        use ::os::linux::MidiOutputConnectionExt;
+        self.output.use_queue_of(&self.input)
+    }
+
+    /// Closes both connections. The returned values allow you to reuse
+    /// the `MidiInput` and `MidiOutput` objects and to inspect the
+    /// additional data passed to the callback, but they can be safely ignored.
+    pub fn close(self) -> (MidiInput, T, MidiOutput) {
+        let (midi_in, data) = self.input.close();
+        let midi_out = self.output.close();
+        (midi_in, data, midi_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_identity() {
+        let reply = [0xF0, 0x7E, 0x10, 0x06, 0x02, 0x41, 0x2B, 0x02, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0xF7];
+        assert_eq!(DeviceIdentity::parse(&reply), Some(DeviceIdentity {
+            manufacturer: ManufacturerId::Standard(0x41),
+            family: 0x012B,
+            model: 0x0003,
+            version: [0x00, 0x00, 0x01, 0x00]
+        }));
+
+        let extended = [0xF0, 0x7E, 0x7F, 0x06, 0x02, 0x00, 0x20, 0x29, 0x01, 0x00, 0x02, 0x00, 0x01, 0x02, 0x03, 0x04, 0xF7];
+        assert_eq!(DeviceIdentity::parse(&extended).map(|id| id.manufacturer), Some(ManufacturerId::Extended(0x20, 0x29)));
+
+        assert_eq!(DeviceIdentity::parse(&IDENTITY_REQUEST), None);
+        assert_eq!(DeviceIdentity::parse(&reply[..reply.len() - 1]), None);
+    }
+}
@@ -1,20 +1,39 @@
 use std::error::Error;
 use std::fmt;
+use std::io;
 
 const INVALID_PORT_MSG: &str = "invalid port";
+const INVALID_PORT_NAME_MSG: &str = "invalid port name (it must not contain null bytes)";
 const PORT_OUT_OF_RANGE_MSG: &str = "provided port number was out of range";
 const CANNOT_RETRIEVE_PORT_NAME_MSG: &str = "unknown error when trying to retrieve the port name";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// An error that can occur during initialization (i.e., while
 /// creating a `MidiInput` or `MidiOutput` object).
-pub struct InitError;
+pub struct InitError {
+    backend: Option<BackendError>
+}
+
+impl InitError {
+    pub(crate) fn new(backend: Option<BackendError>) -> InitError {
+        InitError { backend }
+    }
+
+    /// The error that was reported by the underlying platform API, if available
+    /// (e.g. because the sequencer device does not exist or can not be accessed).
+    pub fn backend(&self) -> Option<BackendError> {
+        self.backend
+    }
+}
 
 impl Error for InitError {}
 
 impl fmt::Display for InitError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        "MIDI support could not be initialized".fmt(f)
+        match self.backend {
+            Some(ref err) => write!(f, "MIDI support could not be initialized: {}", err),
+            None => "MIDI support could not be initialized".fmt(f)
+        }
     }
 }
 
@@ -43,6 +62,10 @@ impl fmt::Display for PortInfoError {
 /// The kind of error for a `ConnectError`.
 pub enum ConnectErrorKind {
     InvalidPort,
+    /// The name for the port that would be created (the `port_name` parameter
+    /// of `connect` or `create_virtual`) can not be used, e.g. because it
+    /// contains null bytes.
+    InvalidPortName,
     Other(&'static str)
 }
 
@@ -52,6 +75,7 @@ impl fmt::Display for ConnectErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ConnectErrorKind::InvalidPort => INVALID_PORT_MSG.fmt(f),
+            ConnectErrorKind::InvalidPortName => INVALID_PORT_NAME_MSG.fmt(f),
             ConnectErrorKind::Other(msg) => msg.fmt(f)
         }
     }
@@ -60,22 +84,42 @@ impl fmt::Display for ConnectErrorKind {
 /// An error that can occur when trying to connect to a port.
 pub struct ConnectError<T> {
     kind: ConnectErrorKind,
+    backend: Option<BackendError>,
     inner: T
 }
 
 impl<T> ConnectError<T> {
     pub fn new(kind: ConnectErrorKind, inner: T) -> ConnectError<T> {
-        ConnectError { kind: kind, inner: inner }
+        ConnectError { kind, backend: None, inner }
     }
     
     /// Helper method to create ConnectErrorKind::Other.
     pub fn other(msg: &'static str, inner: T) -> ConnectError<T> {
         Self::new(ConnectErrorKind::Other(msg), inner)
     }
+
+    /// Creates a `ConnectErrorKind::Other` that keeps the error reported by the backend.
+    #[allow(dead_code)] // not used by all backends
+    pub(crate) fn from_backend(err: BackendError, inner: T) -> ConnectError<T> {
+        ConnectError { kind: ConnectErrorKind::Other(err.message()), backend: Some(err), inner }
+    }
+    
+    /// Replaces the object that is returned by `into_inner`, keeping the kind
+    /// and the backend error.
+    pub(crate) fn map_inner<U, F: FnOnce(T) -> U>(self, f: F) -> ConnectError<U> {
+        ConnectError { kind: self.kind, backend: self.backend, inner: f(self.inner) }
+    }
     
     pub fn kind(&self) -> ConnectErrorKind {
         self.kind
     }
+
+    /// The error that was reported by the underlying platform API, if available.
+    /// Its message is the same as that of `kind`, but it also contains the
+    /// error code (e.g. an `errno` value on Linux).
+    pub fn backend(&self) -> Option<BackendError> {
+        self.backend
+    }
     
     pub fn into_inner(self) -> T {
         self.inner
@@ -84,23 +128,66 @@ impl<T> ConnectError<T> {
 
 impl<T> fmt::Debug for ConnectError<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        self.kind.fmt(f)
+        fmt::Display::fmt(self, f)
     }
 }
 
 impl<T> fmt::Display for ConnectError<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.kind.fmt(f)
+        match self.backend {
+            Some(ref err) => err.fmt(f),
+            None => self.kind.fmt(f)
+        }
     }
 }
 
 impl<T> Error for ConnectError<T> {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An error that was reported by the underlying platform API, consisting
+/// of a description of the operation that failed and (if available) the
+/// error code reported by the system (e.g. an `errno` value on Linux).
+pub struct BackendError {
+    msg: &'static str,
+    code: Option<i32>
+}
+
+impl BackendError {
+    #[allow(dead_code)] // not used by all backends
+    pub(crate) fn new(msg: &'static str, code: Option<i32>) -> BackendError {
+        BackendError { msg, code }
+    }
+
+    /// A human-readable description of the operation that failed.
+    pub fn message(&self) -> &'static str {
+        self.msg
+    }
+
+    /// The raw error code reported by the system, if available.
+    pub fn code(&self) -> Option<i32> {
+        self.code
+    }
+}
+
+impl Error for BackendError {}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{} ({})", self.msg, io::Error::from_raw_os_error(code)),
+            None => self.msg.fmt(f)
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// An error that can occur when sending MIDI messages.
 pub enum SendError {
     InvalidData(&'static str),
-    Other(&'static str)
+    Other(&'static str),
+    /// The backend reported an error, which can be inspected
+    /// to decide how to react (e.g. retry or reconnect).
+    Backend(BackendError)
 }
 
 impl Error for SendError {}
@@ -108,7 +195,8 @@ impl Error for SendError {}
 impl fmt::Display for SendError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            SendError::InvalidData(msg) | SendError::Other(msg) => msg.fmt(f)
+            SendError::InvalidData(msg) | SendError::Other(msg) => msg.fmt(f),
+            SendError::Backend(ref err) => err.fmt(f)
         }
     }
 }
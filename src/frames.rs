@@ -0,0 +1,104 @@
+#![deny(missing_docs)]
+
+const MICROS_PER_SEC: u128 = 1_000_000;
+
+/// Converts between *midir* timestamps (in microseconds) and the sample
+/// frame positions of an audio stream with a known sample rate.
+///
+/// The conversion is anchored at a pair of a timestamp and a frame position
+/// that refer to the same point in time, e.g. the input timestamp of a message
+/// and the frame position of the audio buffer that was being processed when it
+/// arrived. This assumes that the MIDI timestamps and the audio device run on
+/// equivalent clocks. In reality, the clock of an audio interface drifts a little
+/// against the system clock, so for long-running streams the anchor should be
+/// updated from time to time.
+///
+/// Results are rounded to the nearest frame or microsecond, respectively. The
+/// computation does not accumulate errors, so converting a position back and
+/// forth is exact up to this rounding. Positions that would lie before zero
+/// (i.e. before the start of the stream or the MIDI clock) are clamped to zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameClock {
+    sample_rate: u32,
+    anchor_timestamp: u64,
+    anchor_frame: u64
+}
+
+impl FrameClock {
+    /// Creates a new `FrameClock` for the given sample rate (in Hz), where
+    /// `anchor_timestamp` (in microseconds) corresponds to `anchor_frame`.
+    ///
+    /// # Panics
+    /// Panics if `sample_rate` is zero.
+    pub fn new(sample_rate: u32, anchor_timestamp: u64, anchor_frame: u64) -> FrameClock {
+        assert!(sample_rate > 0, "sample rate must not be zero");
+        FrameClock {
+            sample_rate,
+            anchor_timestamp,
+            anchor_frame
+        }
+    }
+
+    /// Get the sample rate (in Hz).
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Moves the anchor, so that `timestamp` corresponds to `frame`.
+    pub fn set_anchor(&mut self, timestamp: u64, frame: u64) {
+        self.anchor_timestamp = timestamp;
+        self.anchor_frame = frame;
+    }
+
+    /// Converts a timestamp (in microseconds) to a sample frame position.
+    pub fn timestamp_to_frame(&self, timestamp: u64) -> u64 {
+        let rate = self.sample_rate as u128;
+        if timestamp >= self.anchor_timestamp {
+            let delta = scale(timestamp - self.anchor_timestamp, rate, MICROS_PER_SEC);
+            self.anchor_frame.saturating_add(delta)
+        } else {
+            let delta = scale(self.anchor_timestamp - timestamp, rate, MICROS_PER_SEC);
+            self.anchor_frame.saturating_sub(delta)
+        }
+    }
+
+    /// Converts a sample frame position to a timestamp (in microseconds).
+    pub fn frame_to_timestamp(&self, frame: u64) -> u64 {
+        let rate = self.sample_rate as u128;
+        if frame >= self.anchor_frame {
+            let delta = scale(frame - self.anchor_frame, MICROS_PER_SEC, rate);
+            self.anchor_timestamp.saturating_add(delta)
+        } else {
+            let delta = scale(self.anchor_frame - frame, MICROS_PER_SEC, rate);
+            self.anchor_timestamp.saturating_sub(delta)
+        }
+    }
+}
+
+/// Computes `value * num / denom`, rounded to the nearest integer.
+fn scale(value: u64, num: u128, denom: u128) -> u64 {
+    let result = (value as u128 * num + denom / 2) / denom;
+    if result > u64::MAX as u128 { u64::MAX } else { result as u64 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_conversion() {
+        let clock = FrameClock::new(48_000, 1_000_000, 0);
+        assert_eq!(clock.timestamp_to_frame(1_000_000), 0);
+        assert_eq!(clock.timestamp_to_frame(1_500_000), 24_000);
+        assert_eq!(clock.timestamp_to_frame(500_000), 0); // clamped
+        assert_eq!(clock.frame_to_timestamp(24_000), 1_500_000);
+        assert_eq!(clock.frame_to_timestamp(1), 1_000_021); // 20.83 us per frame
+
+        let clock = FrameClock::new(44_100, 0, 441_000);
+        assert_eq!(clock.frame_to_timestamp(0), 0);
+        assert_eq!(clock.timestamp_to_frame(0), 441_000);
+        for &frame in &[441_000, 441_001, 500_000, 1_234_567] {
+            assert_eq!(clock.timestamp_to_frame(clock.frame_to_timestamp(frame)), frame);
+        }
+    }
+}
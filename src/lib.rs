@@ -2,6 +2,8 @@ extern crate memalloc;
 
 #[cfg(feature = "jack")]
 #[macro_use] extern crate bitflags;
+#[cfg(feature = "logging")]
+#[macro_use] extern crate log;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,6 +34,37 @@ impl Ignore {
     pub fn contains(self, other: Ignore) -> bool {
         self as u8 & other as u8 != 0 
     }
+
+    /// Converts the result of `flags as u8` back into flags (e.g. after storing them in an atomic).
+    #[allow(dead_code)] // not used by all backends
+    #[inline(always)]
+    pub(crate) fn from_bits(bits: u8) -> Ignore {
+        // this is safe because all combinations also exist as variants
+        unsafe { std::mem::transmute(bits & 0x07) }
+    }
+}
+
+/// A manufacturer ID, as found at the start of a System Exclusive message
+/// (directly following the `0xF0` status byte).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ManufacturerId {
+    /// A one-byte manufacturer ID (`0x01` to `0x7F`), which includes the
+    /// universal IDs `0x7E` (non-realtime) and `0x7F` (realtime).
+    Standard(u8),
+    /// An extended three-byte manufacturer ID, which consists of a zero
+    /// byte followed by the two bytes given here.
+    Extended(u8, u8)
+}
+
+impl ManufacturerId {
+    /// Returns whether the given System Exclusive message (including the
+    /// leading `0xF0`) starts with this manufacturer ID.
+    pub fn matches(self, sysex: &[u8]) -> bool {
+        match self {
+            ManufacturerId::Standard(id) => sysex.len() >= 2 && sysex[0] == 0xF0 && sysex[1] == id,
+            ManufacturerId::Extended(id1, id2) => sysex.len() >= 4 && sysex[0] == 0xF0 && sysex[1] == 0x00 && sysex[2] == id1 && sysex[3] == id2
+        }
+    }
 }
 
 /// A MIDI structure used internally by some backends to store incoming
@@ -62,4 +95,27 @@ pub use errors::*;
 mod common;
 pub use common::*;
 
-mod backend;
\ No newline at end of file
+mod duplex;
+pub use duplex::*;
+
+mod frames;
+pub use frames::*;
+
+mod registry;
+pub use registry::*;
+
+mod names;
+pub use names::*;
+
+pub mod realtime;
+
+pub mod smf;
+
+pub mod parse;
+
+mod backend;
+#[cfg(all(target_os = "linux", feature = "jack"))]
+pub use backend::Backend;
+
+#[cfg(all(feature = "network", not(target_arch = "wasm32")))]
+pub mod network;
\ No newline at end of file
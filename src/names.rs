@@ -0,0 +1,102 @@
+/// Convert a port name (as returned by `port_name`) into a canonical form that
+/// is comparable across backends, by removing the decorations that backends add
+/// to the name of the device. This is useful to find a device again by a name
+/// that has been saved on another platform, or to present consistent names.
+///
+/// The following rules are applied, regardless of the backend that is in use
+/// (so that names from all platforms can be normalized everywhere):
+///
+/// * ALSA: the client and port number at the end (e.g. `20:0`) are removed, as
+///   well as the client name at the start, which is separated from the port name
+///   by a colon (`Keystation 88:Keystation 88 MIDI 1 20:0` becomes `Keystation 88 MIDI 1`).
+/// * JACK: the client name at the start is removed like for ALSA (`system:midi_capture_1`
+///   becomes `midi_capture_1`).
+/// * WinMM: the number that Windows puts in front of the names of identical devices
+///   (`2- Keystation 88`) is removed, and so is the wrapper around the names of
+///   further ports of a device (`MIDIIN2 (Keystation 88)` becomes `Keystation 88`).
+///   WinMM truncates names to 31 characters, so long names may still differ.
+/// * The suffix ` MIDI 1` that ALSA and some drivers append to the first port of
+///   a USB device is removed (the suffixes of other ports are kept, so that the
+///   ports of one device can still be told apart).
+/// * Whitespace at the start and end is removed.
+///
+/// CoreMIDI, WinRT and Web MIDI names are used as they are, apart from the last
+/// two rules. A name that contains a colon itself is therefore shortened as well.
+pub fn normalize_port_name(name: &str) -> String {
+    let mut name = name.trim();
+
+    // ALSA: "client:port 20:0"
+    if let Some(pos) = name.rfind(' ') {
+        let mut numbers = name[pos + 1..].splitn(2, ':');
+        let is_number = |s: Option<&str>| s.is_some_and(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()));
+        if is_number(numbers.next()) && is_number(numbers.next()) {
+            name = name[..pos].trim_end();
+        }
+    }
+
+    // WinMM: "MIDIIN2 (device)" and "MIDIOUT2 (device)"
+    for prefix in &["MIDIIN", "MIDIOUT"] {
+        if name.starts_with(prefix) && name.ends_with(')') {
+            if let Some(open) = name.find(" (") {
+                if name[prefix.len()..open].bytes().all(|b| b.is_ascii_digit()) {
+                    name = &name[open + 2..name.len() - 1];
+                }
+            }
+        }
+    }
+
+    // WinMM: "2- device"
+    if let Some(pos) = name.find("- ") {
+        if pos > 0 && name[..pos].bytes().all(|b| b.is_ascii_digit()) {
+            name = &name[pos + 2..];
+        }
+    }
+
+    // ALSA and JACK: "client:port"
+    if let Some(pos) = name.find(':') {
+        name = &name[pos + 1..];
+    }
+
+    let name = name.trim();
+    match name.strip_suffix(" MIDI 1") {
+        Some(name) => name.trim_end().to_string(),
+        None => name.to_string()
+    }
+}
+
+/// The name by which the same device is recognized in different backends
+/// (see `MidiInput::ports_across_backends`). JACK ports that the `a2jmidid`
+/// bridge creates for ALSA ports (`a2j:Keystation 88 [20] (capture): Keystation 88 MIDI 1`)
+/// are reduced to the name of the ALSA port before normalizing.
+#[cfg(any(test, all(target_os = "linux", feature = "jack")))]
+pub(crate) fn device_key(name: &str) -> String {
+    match name.strip_prefix("a2j:").and_then(|bridged| bridged.rsplit_once(": ")) {
+        Some((_, port)) => normalize_port_name(port),
+        None => normalize_port_name(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_port_name("Keystation 88:Keystation 88 MIDI 1 20:0"), "Keystation 88");
+        assert_eq!(normalize_port_name("Keystation 88:Keystation 88 MIDI 2 20:1"), "Keystation 88 MIDI 2");
+        assert_eq!(normalize_port_name("system:midi_capture_1"), "midi_capture_1");
+        assert_eq!(normalize_port_name("2- Keystation 88"), "Keystation 88");
+        assert_eq!(normalize_port_name("MIDIIN2 (Keystation 88)"), "Keystation 88");
+        assert_eq!(normalize_port_name("MIDIOUT3 (Keystation 88)"), "Keystation 88");
+        assert_eq!(normalize_port_name(" Keystation 88 "), "Keystation 88");
+        assert_eq!(normalize_port_name("Synth (Port 2)"), "Synth (Port 2)");
+        assert_eq!(normalize_port_name("Model 2000"), "Model 2000");
+    }
+
+    #[test]
+    fn device() {
+        assert_eq!(device_key("a2j:Keystation 88 [20] (capture): Keystation 88 MIDI 1"), "Keystation 88");
+        assert_eq!(device_key("Keystation 88:Keystation 88 MIDI 1 20:0"), "Keystation 88");
+        assert_eq!(device_key("system:midi_capture_1"), "midi_capture_1");
+    }
+}
@@ -0,0 +1,188 @@
+//! Forwarding of MIDI messages over the network (enable the `network` feature).
+//!
+//! A `NetworkSender` is used as the user data of an input connection and sends
+//! each incoming message to a remote host, where a `NetworkReceiver` passes it on
+//! to an output connection.
+//!
+//! # Wire format
+//! Messages are transmitted via UDP, one MIDI message per datagram:
+//!
+//! | Offset | Length | Content                                                    |
+//! |--------|--------|------------------------------------------------------------|
+//! | 0      | 4      | Magic bytes `b"MIDR"`                                      |
+//! | 4      | 8      | Timestamp of the message in microseconds (big-endian)      |
+//! | 12     | *n*    | The bytes of the MIDI message                              |
+//!
+//! The timestamp is the one that was passed to the input callback on the sending
+//! side. Because UDP datagrams are limited in size, SysEx messages larger than
+//! about 64 KiB can not be forwarded. There is no retransmission of lost messages.
+//!
+//! # Timing
+//! The clocks of sender and receiver are not synchronized. Instead, the receiver
+//! anchors the timestamp of the first message it receives to its own clock and
+//! sends each following message at the same offset (relative to that first message)
+//! that it had on the sending side, delayed by a configurable latency which absorbs
+//! network jitter. Messages that arrive too late for that are sent immediately.
+
+use std::io;
+use std::net::{UdpSocket, ToSocketAddrs};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use ::{MidiInput, MidiInputPort, MidiInputConnection, MidiOutputConnection};
+use ::errors::*;
+
+const MAGIC: &[u8; 4] = b"MIDR";
+const HEADER_SIZE: usize = 12;
+const MAX_DATAGRAM_SIZE: usize = 65507;
+const DEFAULT_LATENCY_MS: u64 = 10;
+
+/// Appends the frame for a single MIDI message to `buffer`.
+pub fn encode_frame(timestamp: u64, message: &[u8], buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(MAGIC);
+    buffer.extend_from_slice(&timestamp.to_be_bytes());
+    buffer.extend_from_slice(message);
+}
+
+/// Decodes a frame into the timestamp and the bytes of the MIDI message.
+/// Returns `None` if the frame is not valid.
+pub fn decode_frame(frame: &[u8]) -> Option<(u64, &[u8])> {
+    if frame.len() < HEADER_SIZE || &frame[0..4] != MAGIC {
+        return None;
+    }
+    let mut stamp = [0; 8];
+    stamp.copy_from_slice(&frame[4..HEADER_SIZE]);
+    Some((u64::from_be_bytes(stamp), &frame[HEADER_SIZE..]))
+}
+
+/// Sends MIDI messages to a remote `NetworkReceiver`.
+pub struct NetworkSender {
+    socket: UdpSocket,
+    buffer: Vec<u8>
+}
+
+impl NetworkSender {
+    /// Creates a sender that sends all messages to the specified address.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<NetworkSender> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        Ok(NetworkSender {
+            socket,
+            buffer: Vec::with_capacity(HEADER_SIZE + 3)
+        })
+    }
+
+    /// Sends a single MIDI message with the given timestamp (in microseconds).
+    pub fn send(&mut self, timestamp: u64, message: &[u8]) -> io::Result<()> {
+        if HEADER_SIZE + message.len() > MAX_DATAGRAM_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "MIDI message is too large to be sent over the network"));
+        }
+        self.buffer.clear();
+        encode_frame(timestamp, message, &mut self.buffer);
+        self.socket.send(&self.buffer).map(|_| ())
+    }
+}
+
+/// Connects to the specified input port and forwards all incoming messages
+/// using the given `NetworkSender`. Messages that can not be sent are dropped.
+///
+/// The sender can be retrieved again by closing the returned connection.
+pub fn forward_input(
+    midi_in: MidiInput, port: &MidiInputPort, port_name: &str, sender: NetworkSender
+) -> Result<MidiInputConnection<NetworkSender>, ConnectError<MidiInput>> {
+    midi_in.connect(port, port_name, |stamp, message, sender| {
+        let _ = sender.send(stamp, message);
+    }, sender)
+}
+
+/// Receives MIDI messages from a remote `NetworkSender`.
+pub struct NetworkReceiver {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+    latency: Duration,
+    anchor: Option<(u64, Instant)>
+}
+
+impl NetworkReceiver {
+    /// Creates a receiver that listens on the specified address.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<NetworkReceiver> {
+        Ok(NetworkReceiver {
+            socket: UdpSocket::bind(addr)?,
+            buffer: vec![0; MAX_DATAGRAM_SIZE],
+            latency: Duration::from_millis(DEFAULT_LATENCY_MS),
+            anchor: None
+        })
+    }
+
+    /// Set the additional latency that is applied to received messages in
+    /// order to compensate for network jitter. The default is 10 ms.
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    /// Blocks until a valid message has been received and returns the
+    /// timestamp (as seen by the sender) and the bytes of the message.
+    /// Datagrams that are not valid frames are skipped.
+    pub fn receive(&mut self) -> io::Result<(u64, &[u8])> {
+        loop {
+            let len = self.socket.recv(&mut self.buffer)?;
+            // Only keep the timestamp, so that the borrow of the buffer ends before the next iteration
+            let timestamp = match decode_frame(&self.buffer[..len]) {
+                Some((timestamp, _)) => timestamp,
+                None => continue
+            };
+            return Ok((timestamp, &self.buffer[HEADER_SIZE..len]));
+        }
+    }
+
+    /// Translates a remote timestamp into the local point in time at which
+    /// the message should be sent.
+    fn deadline(&mut self, timestamp: u64) -> Instant {
+        let now = Instant::now();
+        match self.anchor {
+            // The sender has restarted (its timestamps went back), so re-anchor
+            Some((remote, _)) if timestamp < remote => {},
+            Some((remote, local)) => {
+                return local + self.latency + Duration::from_micros(timestamp - remote);
+            },
+            None => {}
+        }
+        self.anchor = Some((timestamp, now));
+        now + self.latency
+    }
+
+    /// Receives messages and sends them to the given output connection,
+    /// preserving their relative timing. This only returns when an error occurs.
+    pub fn forward_to(&mut self, conn_out: &mut MidiOutputConnection) -> io::Result<()> {
+        let mut message = Vec::new();
+        loop {
+            let timestamp = {
+                let (timestamp, bytes) = self.receive()?;
+                message.clear();
+                message.extend_from_slice(bytes);
+                timestamp
+            };
+            let deadline = self.deadline(timestamp);
+            let now = Instant::now();
+            if deadline > now {
+                sleep(deadline - now);
+            }
+            conn_out.send(&message).map_err(io::Error::other)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_roundtrip() {
+        let mut buffer = Vec::new();
+        encode_frame(123_456_789, &[0x90, 60, 100], &mut buffer);
+        assert_eq!(buffer.len(), HEADER_SIZE + 3);
+        assert_eq!(decode_frame(&buffer), Some((123_456_789, &[0x90, 60, 100][..])));
+        assert_eq!(decode_frame(&buffer[..HEADER_SIZE - 1]), None);
+        assert_eq!(decode_frame(b"XXXX12345678"), None);
+    }
+}
@@ -0,0 +1,1184 @@
+//! Functionality that is specific to the ALSA backend (Linux).
+//!
+//! # Timestamps
+//! Each input connection owns an ALSA queue that is started when the
+//! connection is opened (in `connect` or `create_virtual`). The timestamp
+//! passed to the input callback is the queue time at which the message was
+//! received, i.e. the number of microseconds since the connection was opened
+//! (or nanoseconds or ticks, see `MidiInputExt::set_nanosecond_timestamps`
+//! and `MidiInputExt::set_tick_timestamps`).
+//! The first message therefore carries the (nonzero) delay between opening
+//! the connection and its arrival, and timestamps of different connections
+//! are not directly comparable. When timestamping is disabled (with
+//! `MidiInputExt::set_timestamping` or the `avoid_timestamping` feature), no
+//! queue is used and all timestamps are zero.
+//!
+//! # Opening the sequencer
+//! `MidiInput::new` and `MidiOutput::new` open the `default` sequencer device.
+//! If that fails (e.g. in a container where the ALSA configuration has no
+//! default sound card, but `/dev/snd/seq` is available), the `hw` device is
+//! tried next, which is reported on stderr. `InitError` is only returned if
+//! neither of them can be opened, and then contains the error reported for
+//! the `hw` device (see `InitError::backend`).
+//!
+//! # JACK
+//! When the `jack` feature is enabled, the ALSA backend is only used if it is
+//! selected with `MidiInput::new_with_backend` or `MidiOutput::new_with_backend`.
+//! The extension traits and the functions that build on them are then not
+//! available, but raw MIDI devices can still be accessed.
+
+#[cfg(not(feature = "jack"))]
+extern crate libc;
+
+#[cfg(not(feature = "jack"))]
+use ::{MidiInput, MidiInputPort, MidiInputConnection, MidiOutput, MidiOutputPort, MidiOutputConnection};
+
+/// Direct access to raw MIDI devices (`hw:X,Y,Z`), bypassing the sequencer.
+///
+/// This gives exclusive access to the hardware with the lowest possible latency,
+/// and can help with devices that misbehave when used through the sequencer.
+/// Compared to `MidiInput`/`MidiOutput` there are some important differences:
+///
+/// * There is no subscription model: a device can usually only be opened by one
+///   application at a time, and fails to open while it is in use.
+/// * Only hardware devices are available, there are no software clients and it
+///   is not possible to create virtual ports.
+/// * Input timestamps are taken when the bytes are read from the device, instead
+///   of being provided by a sequencer queue.
+/// * Output bytes are written to the device unmodified.
+pub use ::backend::{RawMidiPort, RawMidiInputConnection, RawMidiOutputConnection, raw_input_ports, raw_output_ports};
+
+pub use ::backend::encode_decode_roundtrip;
+#[cfg(not(feature = "jack"))]
+use ::{ConnectError, PortInfoError, SendError, BackendError, ManufacturerId};
+#[cfg(not(feature = "jack"))]
+use ::smf::SmfTrack;
+
+#[cfg(not(feature = "jack"))]
+use std::cmp;
+#[cfg(not(feature = "jack"))]
+use std::sync::{Arc, Mutex};
+#[cfg(not(feature = "jack"))]
+use std::sync::mpsc::{channel, Receiver};
+#[cfg(not(feature = "jack"))]
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+#[cfg(not(feature = "jack"))]
+use std::num::{NonZeroU16, NonZeroU32};
+#[cfg(not(feature = "jack"))]
+use std::time::{Instant, SystemTime};
+
+/// The capabilities that an ALSA sequencer port advertises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortCapabilities {
+    /// Events can be read from the port.
+    pub read: bool,
+    /// Events can be written to the port.
+    pub write: bool,
+    /// Other clients can subscribe to the port to read from it.
+    pub subs_read: bool,
+    /// Other clients can subscribe to the port to write to it.
+    pub subs_write: bool,
+}
+
+/// Selects which ports are listed by `MidiInput::ports` and `MidiOutput::ports`
+/// (see `MidiInputExt::set_port_filter` and `MidiOutputExt::set_port_filter`).
+///
+/// Every ALSA sequencer port advertises a set of types. A port is listed if
+/// it has at least one of the enabled types and none of the disabled ones,
+/// so that e.g. disabling `synth` also hides a port of type `MIDI_GENERIC | SYNTH`.
+/// The default lists all ports that have one of these types, as before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortFilter {
+    /// List ports of the type `MIDI_GENERIC`.
+    pub midi_generic: bool,
+    /// List ports of the type `SYNTH` (e.g. wavetable synthesizers of a sound card).
+    pub synth: bool,
+    /// List ports of the type `APPLICATION`, i.e. ports of software clients.
+    pub application: bool,
+    /// Only list ports that belong to a hardware device (type `HARDWARE` or `PORT`).
+    pub hardware_only: bool,
+}
+
+impl Default for PortFilter {
+    fn default() -> Self {
+        PortFilter {
+            midi_generic: true,
+            synth: true,
+            application: true,
+            hardware_only: false,
+        }
+    }
+}
+
+/// Decides how an output connection delivers events to the destination port.
+///
+/// When connecting in `Auto` mode (the default), *midir* subscribes to the
+/// destination if it advertises the `subs_write` capability. If it does not,
+/// or if creating the subscription fails, but the port advertises `write`,
+/// events are instead addressed directly to the destination port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Choose between `Subscribe` and `Direct` based on the port capabilities.
+    #[default]
+    Auto,
+    /// Always subscribe to the destination port (fails if that is not possible).
+    Subscribe,
+    /// Never subscribe, but address each event to the destination port.
+    Direct,
+}
+
+/// Statistics about the time spent in the callback of an input connection.
+///
+/// The callback is considered to be falling behind when it takes longer on
+/// average than the average time between two incoming messages, because the
+/// handler thread can then not keep up with the rate at which messages arrive
+/// and they accumulate in the input buffer (which eventually overruns).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackStats {
+    /// The number of times the callback has been called.
+    pub calls: u64,
+    /// The average time that one invocation of the callback took.
+    pub average_duration: Duration,
+    /// The average time between two invocations of the callback, or `None` if
+    /// fewer than two messages have been received. This is measured with the
+    /// wall clock when the callback is called, not with the timestamps of the
+    /// messages, so it does not depend on their unit (microseconds, nanoseconds
+    /// or ticks, see `MidiInputExt::set_tick_timestamps`) and is also available
+    /// without timestamping.
+    pub average_interval: Option<Duration>,
+}
+
+impl CallbackStats {
+    /// Whether the callback is too slow for the rate at which messages arrive.
+    pub fn is_falling_behind(&self) -> bool {
+        self.average_interval.is_some_and(|interval| self.average_duration >= interval)
+    }
+}
+
+/// The configuration of the thread that handles the input of a connection
+/// (see `MidiInputExt::set_thread_config`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadConfig {
+    /// The name of the thread, or `None` to name it after the port (e.g.
+    /// `midir ALSA input handler (port 'name')`).
+    pub name: Option<String>,
+    /// The priority for running the thread with the `SCHED_FIFO` real-time
+    /// scheduling policy (from 1 to 99), or `None` to use the normal policy.
+    pub rt_priority: Option<i32>,
+}
+
+/// Information about the ALSA timer that drives the queue of an input connection.
+///
+/// The queue advances in steps of the timer's period, so input timestamps are
+/// only as accurate as `resolution`, even though they are given in microseconds.
+/// For example, the system timer of a kernel with `CONFIG_HZ=250` has a period of
+/// 4 ms, which makes messages that arrive within the same period share a timestamp.
+/// A high-resolution timer (`HR timer`, if available) usually has a period of 1 ms
+/// or less. Which timer is used for new queues is configured by the parameters of
+/// the `snd-seq` kernel module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueTimerInfo {
+    /// The name of the timer, as reported by the kernel (e.g. `system timer`).
+    pub name: String,
+    /// The period of the timer, i.e. the time between two updates of the queue time.
+    pub resolution: Duration,
+}
+
+/// The timestamps of a single incoming message, as recorded when
+/// `MidiInputExt::set_timestamp_log_capacity` is enabled. Both values are
+/// given in microseconds (or nanoseconds, see `MidiInputExt::set_nanosecond_timestamps`)
+/// since the connection was opened, but are taken from
+/// different clocks: comparing them shows how long it took from the arrival of
+/// the event in the kernel until the handler processed it (plus a small constant
+/// offset, because the two clocks were not started at exactly the same time).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampSample {
+    /// The event time, i.e. the timestamp that is passed to the callback
+    /// (the time of the input queue when the event arrived).
+    pub event_time: u64,
+    /// The time of the monotonic system clock when the handler read the event.
+    pub received: u64,
+}
+
+/// The position of a chunk of a System Exclusive message that is passed to
+/// the callback set with `MidiInputExt::set_sysex_chunk_callback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysexChunk {
+    /// The chunk contains the whole message (from `0xF0` to `0xF7`).
+    Complete,
+    /// The first chunk of a message, which starts with `0xF0`.
+    First,
+    /// A chunk from the middle of a message.
+    Continuation,
+    /// The last chunk of a message, which ends with `0xF7`.
+    Last,
+}
+
+/// A callback that receives SysEx messages in chunks (see
+/// `MidiInputExt::set_sysex_chunk_callback`).
+pub type SysexChunkCallback = Box<dyn FnMut(u64, &[u8], SysexChunk) + Send>;
+
+/// A change of the subscriptions of the port of an input connection (see
+/// `MidiInputExt::set_connection_callback`). The addresses are pairs of ALSA
+/// client and port numbers (like `MidiInputExt::port_address`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortEvent {
+    /// A sender has been connected to the port (e.g. by `connect`, or by another
+    /// application in case of a virtual port).
+    Connected { sender: (i32, i32), dest: (i32, i32) },
+    /// A sender has been disconnected from the port, e.g. because the device
+    /// has been unplugged or the sending application has exited.
+    Disconnected { sender: (i32, i32), dest: (i32, i32) },
+}
+
+/// How to handle incoming messages that are incomplete, i.e. that have fewer
+/// data bytes than their status byte requires (or no status byte at all).
+///
+/// The sequencer transports complete events, so this only happens if a driver
+/// or another client produces malformed events (e.g. because of a glitch on a
+/// flaky serial link). Buffering such messages until they are completed is not
+/// supported, because the missing bytes can never arrive in a later event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PartialMessagePolicy {
+    /// Pass incomplete messages to the callback like any other (the default),
+    /// or to the callback set with `MidiInputExt::set_partial_message_callback`,
+    /// so that they can be told apart from complete messages.
+    #[default]
+    Deliver,
+    /// Silently drop incomplete messages.
+    Drop,
+}
+
+/// The raw form of an incoming ALSA sequencer event, together with the
+/// MIDI bytes that it was decoded into (requires the `diagnostics` feature).
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEvent {
+    /// The name of the ALSA event type (e.g. `Noteon` or `Sysex`).
+    pub event_type: String,
+    /// The variable-length data of the event, which is only present for
+    /// some event types (most notably SysEx).
+    pub ext: Option<Vec<u8>>,
+    /// The bytes that the event was decoded into, which is empty if the
+    /// event was ignored or could not be decoded.
+    pub decoded: Vec<u8>,
+}
+
+/// A function that receives every incoming event in its raw form (see
+/// `MidiInputExt::set_raw_event_hook`).
+#[cfg(feature = "diagnostics")]
+pub type RawEventHook = Box<dyn FnMut(&RawEvent) + Send>;
+
+/// Decides how the input handler reacts when ALSA signals input, but then
+/// reports that no event is available (`EAGAIN`). This is harmless, but can
+/// happen repeatedly under some conditions. Each occurrence is counted (see
+/// `MidiInputConnectionExt::eagain_count`), regardless of the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EagainPolicy {
+    /// Only count the occurrence (the default).
+    #[default]
+    Silent,
+    /// Additionally write a message to `stderr` (or log a warning, if the `logging` feature is enabled).
+    Log,
+    /// Additionally wait for the given time before polling for input again.
+    Backoff(Duration),
+}
+
+/// Decides whether an output connection sends note-off messages when it is
+/// closed or dropped, to prevent hanging notes on the receiving device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoteOffPolicy {
+    /// Don't send anything (the default).
+    #[default]
+    None,
+    /// Send *All Notes Off* (CC 123) on all 16 channels. This is simple, but
+    /// not all devices respond to it.
+    AllNotesOff,
+    /// Keep track of the note-on messages that have been sent, and send a
+    /// matching note-off message for every note that is still on.
+    TrackNotes,
+}
+
+/// Trait that is implemented by `MidiInputBuilder` when using the ALSA backend.
+#[cfg(not(feature = "jack"))]
+pub trait MidiInputBuilderExt {
+    /// Set the tempo and resolution of the input queue (see `MidiInputExt::set_queue_tempo`).
+    fn queue_tempo(self, tempo: NonZeroU32, ppq: NonZeroU16) -> Self;
+
+    /// Set the name of the handler threads of connections (see `ThreadConfig::name`).
+    fn thread_name(self, name: &str) -> Self;
+}
+
+/// An additional callback of an input connection, which receives the timestamp
+/// and the bytes of a message (e.g. `MidiInputExt::set_system_common_callback`).
+pub type MessageCallback = Box<dyn FnMut(u64, &[u8]) + Send>;
+
+/// Trait that is implemented by `MidiInput` when using the ALSA backend.
+#[cfg(not(feature = "jack"))]
+pub trait MidiInputExt {
+    /// Get the capabilities of the specified input port.
+    fn port_capabilities(&self, port: &MidiInputPort) -> Result<PortCapabilities, PortInfoError>;
+
+    /// Get the ALSA address of the specified input port as a pair of client
+    /// and port number (e.g. `(20, 0)`, which `aconnect` shows as `20:0`).
+    /// Client numbers are assigned dynamically, so they are not suitable to
+    /// identify a device across restarts or after it has been plugged in again.
+    fn port_address(&self, port: &MidiInputPort) -> (i32, i32);
+
+    /// Get the name of the ALSA client of this `MidiInput` as it has been
+    /// stored by the sequencer, which truncates the name that was passed to
+    /// `MidiInput::new` to 63 bytes. This is the name that other applications
+    /// (and `port_name`) show for the ports of this client.
+    fn client_name(&self) -> Result<String, PortInfoError>;
+
+    /// Hold back each incoming message for the given `delay` before passing it
+    /// to the callback, so that messages can be delivered in the order of their
+    /// timestamps. This matters when several sources send to the same port (e.g.
+    /// a virtual port), because the kernel buffers each source separately.
+    ///
+    /// Messages are guaranteed to be in timestamp order as long as no message
+    /// arrives later than `delay` after its timestamp. A larger delay makes the
+    /// ordering more reliable, but adds latency. A delay of zero (the default)
+    /// disables reordering. Reordering is not available when timestamping is
+    /// disabled (see `set_timestamping`).
+    fn set_reorder_delay(&mut self, delay: Duration);
+
+    /// Limit the capacity (in bytes) of the buffer that incoming messages are
+    /// assembled in. The buffer grows to fit the largest message received, so
+    /// without a limit (the default) a single large SysEx dump keeps its memory
+    /// allocated for the lifetime of the connection. With a limit, the buffer is
+    /// shrunk after delivering a message that exceeded it, at the cost of having
+    /// to grow it again when the next large message arrives.
+    fn set_max_retained_capacity(&mut self, capacity: Option<usize>);
+
+    /// Enable measuring how long the callback takes, which can then be
+    /// retrieved using `MidiInputConnectionExt::callback_stats`. This is
+    /// disabled by default, because it reads the clock twice per message.
+    fn set_measure_callback_time(&mut self, enabled: bool);
+
+    /// Only deliver System Exclusive messages whose manufacturer ID is one of
+    /// the given `manufacturers`, and silently drop all others. Both one-byte
+    /// and extended three-byte IDs are supported (see `ManufacturerId`).
+    /// Messages other than SysEx are not affected by this filter. The default
+    /// is `None`, which delivers all SysEx messages (unless `Ignore::Sysex` is set).
+    fn set_sysex_filter(&mut self, manufacturers: Option<Vec<ManufacturerId>>);
+
+    /// Set how to react when no input event is available although input
+    /// was signalled (see `EagainPolicy`). The default is `EagainPolicy::Silent`.
+    fn set_eagain_policy(&mut self, policy: EagainPolicy);
+
+    /// Set a function that is called with the raw form of every event that is
+    /// received, before it is passed on to the callback. This allows to compare
+    /// what ALSA delivered to the bytes that the decoder produced, which helps
+    /// when debugging unexpected input. Pass `None` to remove it.
+    ///
+    /// This is only meant for diagnostic purposes and requires the
+    /// `diagnostics` feature, because it allocates for every event.
+    #[cfg(feature = "diagnostics")]
+    fn set_raw_event_hook(&mut self, hook: Option<RawEventHook>);
+
+    /// Don't start a handler thread for connections that are opened after this
+    /// call, so that their input can be processed by an external event loop
+    /// (e.g. based on `poll`, `epoll` or *mio*). The default is `false`.
+    ///
+    /// In this mode, the application is responsible for driving the connection:
+    ///
+    /// * Wait until one of the file descriptors returned by
+    ///   `MidiInputConnectionExt::poll_descriptors` becomes readable (`POLLIN`).
+    ///   The descriptors stay valid until the connection is closed.
+    /// * Then call `MidiInputConnectionExt::process_ready_events`, which invokes
+    ///   the callback for all received messages on the calling thread. It never
+    ///   blocks, so it may also be called when nothing is ready.
+    /// * If it returns a duration, call it again after that time at the latest,
+    ///   even if no descriptor becomes readable (this is needed for held back
+    ///   messages, see `set_reorder_delay`).
+    ///
+    /// Never read from the descriptors directly. Messages that arrive while
+    /// `process_ready_events` is not called are buffered by the kernel, and
+    /// may be lost if its buffer overruns.
+    fn set_external_polling(&mut self, enabled: bool);
+
+    /// Set a callback that receives the System Common messages *Song Position
+    /// Pointer* (`0xF2`), *Song Select* (`0xF3`) and *Tune Request* (`0xF6`)
+    /// instead of the regular callback, e.g. to handle transport synchronization
+    /// separately from the note data. It is called on the same thread as the
+    /// regular callback, with the timestamp and the bytes of the message.
+    ///
+    /// Other System Common messages are not affected: MIDI Time Code quarter
+    /// frames (`0xF1`) are treated as timing messages (see `Ignore::Time`), and
+    /// SysEx messages are always passed to the regular callback. The default is
+    /// `None`, which passes all messages to the regular callback.
+    fn set_system_common_callback(&mut self, callback: Option<MessageCallback>);
+
+    /// Set a callback that is notified when a sender is connected to or
+    /// disconnected from the port of a connection, e.g. so that a UI can show
+    /// that the device it listens to has gone away instead of silently
+    /// receiving nothing. It is called on the same thread as the regular
+    /// callback. The notification for the subscription that `connect` makes
+    /// itself may arrive before the first message. The default is `None`.
+    fn set_connection_callback(&mut self, callback: Option<Box<dyn FnMut(PortEvent) + Send>>);
+
+    /// Set the maximum size (in bytes) of a SysEx message that is assembled
+    /// from the chunks that ALSA delivers, 1 MiB by default. The rest of a
+    /// message that exceeds it is skipped, so that a device which never
+    /// terminates a message can not make the buffer grow indefinitely. This has
+    /// no effect on a `set_sysex_chunk_callback`, which does not assemble messages.
+    ///
+    /// A SysEx message that is interrupted by the start of a new one, or by the
+    /// sender disconnecting from the port, is discarded as well (and counted by
+    /// `MidiInputConnectionExt::partial_message_count`).
+    fn set_max_sysex_size(&mut self, size: usize);
+
+    /// Record the timestamps of each incoming message (see `TimestampSample`),
+    /// so that they can be retrieved with `MidiInputConnectionExt::take_timestamps`,
+    /// e.g. to build a latency histogram. Up to `capacity` samples are stored
+    /// until they are taken; further samples are dropped (and counted). The
+    /// memory for them is allocated when connecting, so that recording does not
+    /// allocate. A capacity of zero (the default) disables recording.
+    fn set_timestamp_log_capacity(&mut self, capacity: usize);
+
+    /// Set a callback that receives System Exclusive messages in chunks, as
+    /// they arrive, instead of passing complete messages to the regular callback.
+    /// This allows to process large dumps while they are being received (e.g.
+    /// write them to disk), without buffering them completely first.
+    ///
+    /// The callback is called with the timestamp of the chunk, its bytes and
+    /// its position in the message (see `SysexChunk`). Concatenating the chunks
+    /// from `First` to `Last` (or a single `Complete` one) yields the message
+    /// that would otherwise be passed to the regular callback. The size of the
+    /// chunks is determined by ALSA (usually up to 256 bytes). A message that
+    /// is interrupted by a flush (see `MidiInputConnectionExt::flush_input`)
+    /// never receives its `Last` chunk. Chunks are neither reordered (see
+    /// `set_reorder_delay`) nor filtered (see `set_sysex_filter`), but
+    /// `Ignore::Sysex` still applies. The default is `None`.
+    fn set_sysex_chunk_callback(&mut self, callback: Option<SysexChunkCallback>);
+
+    /// Pass SysEx messages that arrive in a single ALSA event to the callback
+    /// directly from the event buffer of ALSA, instead of copying them into the
+    /// message buffer of the connection first. This avoids holding large
+    /// messages (e.g. firmware transfers) in memory twice. As for every message,
+    /// the slice is only valid for the duration of the call, so the callback
+    /// has to copy what it wants to keep.
+    ///
+    /// Messages that ALSA delivers in several chunks are still assembled as
+    /// usual, as are all messages while a reorder delay is set (see
+    /// `set_reorder_delay`), which has to keep a copy of each message anyway.
+    /// This has no effect on a `set_sysex_chunk_callback`. The default is `false`.
+    fn set_zero_copy_sysex(&mut self, enabled: bool);
+
+    /// Set how to handle incomplete messages (see `PartialMessagePolicy`).
+    /// The default is `PartialMessagePolicy::Deliver`. Regardless of the policy,
+    /// such messages are counted (see `MidiInputConnectionExt::partial_message_count`).
+    fn set_partial_message_policy(&mut self, policy: PartialMessagePolicy);
+
+    /// Set a callback that receives incomplete messages instead of the regular
+    /// callback, when they are delivered (see `PartialMessagePolicy::Deliver`).
+    /// Every message that is passed to it is incomplete, so this marks each
+    /// of them individually, whereas `partial_message_count` only counts them.
+    /// It is called on the same thread as the regular callback, with the
+    /// timestamp and the bytes of the message. The default is `None`, which
+    /// passes incomplete messages to the regular callback.
+    fn set_partial_message_callback(&mut self, callback: Option<MessageCallback>);
+
+    /// Set whether an event that contains several messages (see
+    /// `MidiInputConnectionExt::merged_message_count`) is passed to the callback
+    /// one message at a time, all with the timestamp of the event, instead of
+    /// all at once. The split happens at the status bytes. The default is `false`.
+    fn set_split_merged_messages(&mut self, enabled: bool);
+
+    /// Subtract a constant `offset` from the timestamp of every incoming message,
+    /// to compensate for the known latency of a device (e.g. a USB interface
+    /// that always delivers messages a few milliseconds after they were played).
+    /// Timestamps can not become negative: those that would are clamped to zero.
+    /// The offset is applied before messages are reordered (see `set_reorder_delay`).
+    /// The default is zero.
+    fn set_timestamp_offset(&mut self, offset: Duration);
+
+    /// Pass timestamps to the callbacks in nanoseconds instead of microseconds
+    /// (this also applies to `TimestampSample`). The default is `false`.
+    ///
+    /// The kernel stamps each incoming event with the real time of the input
+    /// queue in nanoseconds, not in musical time, so the timestamps are never
+    /// quantized by the tempo or PPQ of the queue. By default, they are truncated
+    /// to whole microseconds, which this avoids. The actual precision is still
+    /// limited by the timer of the queue (see `MidiInputConnectionExt::queue_timer`).
+    fn set_nanosecond_timestamps(&mut self, enabled: bool);
+
+    /// Mark the ports that are created by this `MidiInput` afterwards as private
+    /// (using the `NO_EXPORT` capability of ALSA). The default is `false`.
+    ///
+    /// The kernel then refuses connections to these ports that are made by a
+    /// third client, such as `aconnect` or a patchbay, and bridges like
+    /// `a2jmidid` do not expose them, so that the MIDI routing of e.g. a plugin
+    /// host stays internal. This is not a security boundary: the ports are still
+    /// listed by other clients (e.g. in `aconnect -l` or `/proc/asound/seq/clients`),
+    /// and another application can still connect its own ports to them directly.
+    /// ALSA has no such flags for a client as a whole, so this applies per port.
+    fn set_private_ports(&mut self, enabled: bool);
+
+    /// Select which ports are listed by `ports` and counted by `port_count`
+    /// (see `PortFilter`). The default lists all MIDI ports, including those of
+    /// software clients.
+    fn set_port_filter(&mut self, filter: PortFilter);
+
+    /// Pass timestamps to the callbacks in ticks of the input queue instead of
+    /// microseconds, e.g. to record a Standard MIDI File. With `Some(ppq)`, the
+    /// queue runs at a tempo of 120 BPM (500000 microseconds per quarter note,
+    /// the default tempo of a MIDI file, unless it is changed with
+    /// `set_queue_tempo`) with a resolution of `ppq` ticks per
+    /// quarter note, and the port is set up so that the kernel stamps incoming
+    /// events with the tick time instead of the real time. The default is `None`.
+    ///
+    /// To write a MIDI file, use the same `ppq` as its division and write the
+    /// difference between the timestamps of consecutive messages as delta times
+    /// (the first message relative to zero, i.e. the opening of the connection).
+    /// No tempo event is needed. Ticks are integral, so timestamps are quantized
+    /// to `500000 / ppq` microseconds: choose a high resolution (e.g. 960) for
+    /// accurate recordings. This setting takes precedence over
+    /// `set_nanosecond_timestamps`, and all other durations (such as the reorder
+    /// delay and the timestamp offset) are converted into ticks.
+    fn set_tick_timestamps(&mut self, ppq: Option<NonZeroU16>);
+
+    /// Set whether connections that are opened afterwards use an input queue to
+    /// timestamp incoming messages. Without timestamping, no queue is allocated
+    /// and all timestamps are zero, which is useful if the application
+    /// timestamps messages itself or the number of queues is limited. The
+    /// default is to use timestamping, unless the `avoid_timestamping` feature
+    /// is enabled.
+    fn set_timestamping(&mut self, enabled: bool);
+
+    /// Set the tempo (in microseconds per quarter note) and the resolution (in
+    /// ticks per quarter note) of the input queue of connections that are opened
+    /// after this call. By default, the queue runs at 100 BPM (600000) with a
+    /// resolution of 240. Timestamps in microseconds or nanoseconds do not depend on
+    /// this, but tick timestamps do (see `set_tick_timestamps`, whose resolution
+    /// takes precedence over `ppq`), and so does the tick time of the queue
+    /// that other clients may observe.
+    fn set_queue_tempo(&mut self, tempo: NonZeroU32, ppq: NonZeroU16);
+
+    /// Set the size (in bytes) of the buffer in the kernel that holds incoming
+    /// events until the input handler reads them, for connections that are made
+    /// after this call. If it fills up, e.g. because a dense controller stream
+    /// or a SysEx dump arrives faster than the callback processes it, events are
+    /// lost (see `MidiInputConnectionExt::overrun_count`).
+    ///
+    /// The buffer consists of cells of 28 bytes (the size of an ALSA sequencer
+    /// event, SysEx data takes several), so the size is rounded down to a
+    /// multiple of that. By default, it holds 200 events (5600 bytes), and the
+    /// kernel allows up to 2000 (56000 bytes). If `bytes` is outside of this
+    /// range or smaller than one event, an error (with the code `EINVAL`) is
+    /// returned and the previous size is kept.
+    fn set_input_buffer_size(&mut self, bytes: usize) -> Result<(), BackendError>;
+
+    /// Choose whether dropping a connection that is made by this `MidiInput`
+    /// returns immediately instead of waiting for the handler thread to stop.
+    /// With `true`, joining the thread and releasing the resources of the
+    /// connection (unsubscribing, which can take a while, and deleting the
+    /// port and the queue) happens in a background thread, as does dropping
+    /// the user data. This is meant for contexts where `Drop` must not block,
+    /// such as async runtimes, at the cost of delayed cleanup: the port can
+    /// still exist (and the callback may still be running) for a short time
+    /// after the connection has been dropped, so e.g. a new port with the same
+    /// name may briefly coexist with it. `close` always blocks, and connections
+    /// with external polling are always released immediately. The default is `false`.
+    fn set_detach_on_drop(&mut self, enabled: bool);
+
+    /// Set the name and scheduling of the handler threads of connections that
+    /// are made by this `MidiInput` (see `ThreadConfig`). A real-time priority
+    /// is applied right after the thread has been spawned. This usually requires
+    /// the `CAP_SYS_NICE` capability or an `rtprio` limit (e.g. in
+    /// `/etc/security/limits.conf`); if it can not be applied, a warning is
+    /// reported and the thread keeps running at normal priority. The setting
+    /// has no effect when using external polling, where there is no handler thread.
+    fn set_thread_config(&mut self, config: ThreadConfig);
+
+    /// Limit the time that closing (or dropping) a connection made by this
+    /// `MidiInput` waits for the subscription to the source port to be removed.
+    /// Removing it blocks until the kernel has finished, so if that is slow for
+    /// the source port, `close` blocks as well.
+    ///
+    /// With a timeout, the subscription is removed through a separate, short-lived
+    /// sequencer client on a helper thread. If that takes longer than `timeout`,
+    /// closing continues (reporting a warning). If the helper has already started
+    /// to remove the subscription, it finishes in the background, so the source
+    /// port may still briefly appear connected after `close` has returned.
+    /// Otherwise, the subscription is removed when the port of the connection is
+    /// deleted. Opening the extra client takes a little time as well, so very
+    /// short timeouts are not useful. With `None` (the default), closing waits
+    /// until the subscription has been removed.
+    fn set_unsubscribe_timeout(&mut self, timeout: Option<Duration>);
+
+    /// Create a virtual input port (like `VirtualInput::create_virtual`) that
+    /// is already connected to the given source `port` when it appears, so that
+    /// there is no time window in which it exists but is not connected. Other
+    /// applications can connect to the virtual port as well, unless it is
+    /// private (see `set_private_ports`): then only applications that connect
+    /// their own ports to it directly can, but not patchbays or `aconnect`.
+    ///
+    /// If the connection to `port` can not be made, an error is returned and
+    /// the virtual port is removed again. To connect to a port by its index,
+    /// pass the respective element of `ports`.
+    fn create_virtual_connected<F, T: Send>(
+        self, port_name: &str, port: &MidiInputPort, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static, Self: Sized;
+
+    /// Like `create_virtual_connected`, but connects to the first port whose
+    /// name (as returned by `port_name`) is `target`, e.g. for a fixed routing
+    /// that is stored in a configuration file. Fails with `InvalidPort` if no
+    /// such port exists.
+    fn create_virtual_connected_by_name<F, T: Send>(
+        self, port_name: &str, target: &str, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+        where F: FnMut(u64, &[u8], &mut T) + Send + 'static, Self: Sized;
+}
+
+/// Trait that is implemented by `MidiInputConnection` when using the ALSA backend.
+#[cfg(not(feature = "jack"))]
+pub trait MidiInputConnectionExt {
+    /// Discard all events that are currently buffered for this connection,
+    /// without passing them to the callback. This is useful when resuming
+    /// after a pause, to avoid receiving a burst of stale messages.
+    /// A partially received SysEx message is discarded as well.
+    ///
+    /// The flush is performed asynchronously by the input handler thread,
+    /// shortly after this method returns (or by the next call of
+    /// `process_ready_events` when using external polling).
+    ///
+    /// Timestamps are not reset by a flush: the input queue keeps running,
+    /// so messages received afterwards are timestamped relative to the same
+    /// point in time as before.
+    fn flush_input(&self);
+
+    /// Stop the clock of the input queue, e.g. while the transport of a
+    /// recording application is stopped. Messages are still received and
+    /// delivered as usual, but their timestamps freeze at the time of the
+    /// pause. Messages that are held back for reordering (see
+    /// `MidiInputExt::set_reorder_delay`) are delivered without delay while the
+    /// queue is paused. Like `flush_input`, this is performed asynchronously by
+    /// the input handler. It has no effect if timestamping is disabled
+    /// (see `MidiInputExt::set_timestamping`), and a paused queue is freed as usual when the connection is closed.
+    fn pause_queue(&self);
+
+    /// Let the clock of the input queue continue after `pause_queue`. Timestamps
+    /// then continue from where they were frozen, i.e. the time during which the
+    /// queue was paused is not counted.
+    fn resume_queue(&self);
+
+    /// Get statistics about the time spent in the callback. These are only
+    /// collected if `MidiInputExt::set_measure_callback_time` was enabled
+    /// before connecting, otherwise no calls are counted.
+    fn callback_stats(&self) -> CallbackStats;
+
+    /// Get the number of times that no input event was available although
+    /// input was signalled (see `EagainPolicy`).
+    fn eagain_count(&self) -> u64;
+
+    /// Get the number of incomplete messages that have been received
+    /// (see `PartialMessagePolicy`), including those that have been dropped.
+    fn partial_message_count(&self) -> u64;
+
+    /// Get the number of times that the input buffer of the connection has
+    /// overrun, i.e. that incoming events have been lost because they were
+    /// not read fast enough. This counter only ever increases.
+    fn overrun_count(&self) -> u64;
+
+    /// Get the number of times that the bytes passed to the callback contained
+    /// more than one MIDI message. This happens when ALSA delivers several
+    /// messages as a single event (e.g. the four controller changes of an NRPN
+    /// change), which are then passed on together instead of one by one.
+    ///
+    /// Such messages are detected by counting the status bytes in the decoded
+    /// bytes of each event (the decoder never uses running status, so each
+    /// message starts with its status byte). Such events are counted even if
+    /// they are split (see `MidiInputExt::set_split_merged_messages`), which
+    /// consumers that require exactly one message per callback should enable.
+    /// System Exclusive messages are never merged.
+    fn merged_message_count(&self) -> u64;
+
+    /// Check whether the input buffer has overrun since the flag was last
+    /// cleared (see `clear_overrun`). Unlike `overrun_count`, this flag is
+    /// latched: it stays set until it is cleared explicitly, which makes it
+    /// easy to mark e.g. a recording as possibly incomplete.
+    fn has_overrun(&self) -> bool;
+
+    /// Clear the overrun flag (see `has_overrun`). Returns whether it was set,
+    /// so that checking and clearing can be done at once without missing an
+    /// overrun that happens in between.
+    fn clear_overrun(&self) -> bool;
+
+    /// Get the name of the port that this connection receives from, in the
+    /// same format as `MidiInput::port_name`. The name is resolved when the
+    /// connection is made, and again when the source subscribes to the port of
+    /// the connection (e.g. after it has been renamed and reconnected). For a
+    /// virtual port, this is the port that subscribed to it most recently, as
+    /// long as it stays subscribed. Returns `None` if there is no such port or
+    /// the name could not be determined.
+    fn source_name(&self) -> Option<String>;
+
+    /// Guess whether the timestamps of this connection reflect the timing with
+    /// which the source produced the messages. Timestamps are always taken when
+    /// an event arrives at the port of the connection, which is accurate for
+    /// hardware devices, but an application may send several messages in a burst
+    /// regardless of when they were meant to be played, making the differences
+    /// between their timestamps meaningless.
+    ///
+    /// This is a heuristic based on the source port at the time the connection
+    /// was made: it returns `Some(true)` if the port belongs to hardware (its
+    /// type contains `HARDWARE` or `PORT`) or has timestamping enabled itself,
+    /// and `Some(false)` otherwise, or if timestamping is disabled for the
+    /// connection. Returns `None` for virtual ports, which have no fixed source.
+    fn source_has_timing(&self) -> Option<bool>;
+
+    /// Check whether the source port is still connected to this connection.
+    /// This returns `Some(false)` once the subscription has been removed, e.g.
+    /// because the device has been unplugged or another application (like
+    /// `aconnect -d`) has disconnected it, and `Some(true)` again if the same
+    /// source port is connected to the port of this connection later on.
+    /// Returns `None` for virtual ports, which have no fixed source (unless
+    /// one has been connected using `rewire`).
+    ///
+    /// The connection itself stays open while the source is gone, but no more
+    /// messages arrive. To be notified when this happens, set a callback with
+    /// `MidiInputExt::set_connection_callback`, which receives a
+    /// `PortEvent::Disconnected` with the address of the source.
+    fn is_source_connected(&self) -> Option<bool>;
+
+    /// Get the address (client id and port number) of the port that midir
+    /// created for this connection, e.g. to display it to the user or to pass
+    /// it to `aconnect`. For virtual ports, this is the port that other
+    /// applications connect to.
+    fn virtual_port_addr(&self) -> (i32, i32);
+
+    /// Connect the port of this connection to another source `port`, e.g. when
+    /// the user switches to another device, while the port, the input queue
+    /// and the handler thread are kept (and the callback keeps its state).
+    /// The previous source is disconnected only after the new one has been
+    /// connected, so if this fails, the connection stays as it was. This also
+    /// works for virtual ports, which then receive the messages of `port` in
+    /// addition to those of other applications that have connected to them.
+    ///
+    /// This blocks until the input handler has performed the change, which
+    /// waits for the callback to return if it is currently running.
+    fn rewire(&mut self, port: &MidiInputPort) -> Result<(), ConnectError<()>>;
+
+    /// Move the recorded timestamps (see `MidiInputExt::set_timestamp_log_capacity`)
+    /// to the end of `buffer`, in the order in which the messages were received.
+    /// Returns the number of samples that have been dropped since the last call,
+    /// because the log was full.
+    fn take_timestamps(&self, buffer: &mut Vec<TimestampSample>) -> u64;
+
+    /// Get information about the timer that drives the queue of this connection
+    /// (see `QueueTimerInfo`), as reported by the kernel in `/proc/asound/seq/timer`.
+    /// Returns `None` if the information is not available (e.g. because `/proc`
+    /// is not mounted, or timestamping is disabled for the connection).
+    fn queue_timer(&self) -> Option<QueueTimerInfo>;
+
+    /// Get the id of the ALSA queue that timestamps the messages of this
+    /// connection, e.g. to read or schedule events on it with the ALSA API.
+    /// Returns `None` if timestamping is disabled for the connection.
+    fn queue_id(&self) -> Option<i32>;
+
+    /// Get the point in time (of the monotonic system clock) at which the time
+    /// of the input queue was zero, i.e. the time of a message with timestamp
+    /// `t` is `epoch + Duration::from_micros(t)` (before the timestamp offset is
+    /// subtracted), or `epoch + Duration::from_nanos(t)` with nanosecond
+    /// timestamps (see `MidiInputExt::set_nanosecond_timestamps`). With tick
+    /// timestamps (see `MidiInputExt::set_tick_timestamps`), tick zero is at the
+    /// epoch as well, and tick `t` is `t * tempo / ppq` microseconds after it,
+    /// as long as the tempo of the queue is not changed. Returns `None` if the
+    /// queue can not be read, or timestamping is disabled for the connection.
+    ///
+    /// The queue time is read in a single system call that is bracketed by two
+    /// readings of the monotonic clock, so the epoch is accurate to half the
+    /// duration of that call (usually a few microseconds), plus the resolution
+    /// of the queue timer (see `queue_timer`). The queue timer may drift against
+    /// the monotonic clock, so take new epochs periodically during long recordings.
+    /// To align several connections, use `timestamp_epochs`.
+    fn timestamp_epoch(&self) -> Option<Instant>;
+
+    /// Get the file descriptors that an external event loop needs to wait on
+    /// (see `MidiInputExt::set_external_polling`). The first one is the trigger
+    /// pipe of the connection (which becomes readable e.g. after `flush_input`),
+    /// the others belong to the ALSA sequencer. The list is empty if the
+    /// connection is served by a handler thread.
+    fn poll_descriptors(&self) -> Vec<RawFd>;
+
+    /// Process all events that are ready, invoking the callback for each
+    /// message, without blocking (see `MidiInputExt::set_external_polling`).
+    /// Returns the time after which this needs to be called again at the latest,
+    /// or `None` if it only needs to be called when a descriptor becomes readable.
+    /// This does nothing if the connection is served by a handler thread.
+    fn process_ready_events(&mut self) -> Option<Duration>;
+}
+
+/// Trait that is implemented by `MidiOutput` when using the ALSA backend.
+#[cfg(not(feature = "jack"))]
+pub trait MidiOutputExt {
+
+    /// Get the capabilities of the specified output port.
+    fn port_capabilities(&self, port: &MidiOutputPort) -> Result<PortCapabilities, PortInfoError>;
+
+    /// Get the ALSA address of the specified output port as a pair of client
+    /// and port number (see `MidiInputExt::port_address`).
+    fn port_address(&self, port: &MidiOutputPort) -> (i32, i32);
+
+    /// Get the name of the ALSA client of this `MidiOutput` as it has been
+    /// stored by the sequencer (see `MidiInputExt::client_name`).
+    fn client_name(&self) -> Result<String, PortInfoError>;
+
+    /// Set how connections created by this `MidiOutput` deliver their events
+    /// (see `OutputMode` for details). The default is `OutputMode::Auto`.
+    fn set_output_mode(&mut self, mode: OutputMode);
+
+    /// Choose whether connections created by this `MidiOutput` wait when the
+    /// output pool of the client in the kernel is full. By default, the
+    /// sequencer is opened in non-blocking mode, so that sending never stalls
+    /// (e.g. an audio thread), and `send` fails with a `SendError::Backend`
+    /// carrying `EAGAIN` instead, while the message remains in the output
+    /// buffer. With `true`, `send` waits until the message has been handed to
+    /// the kernel. This only applies to the sequencer output; input connections
+    /// always read without blocking.
+    fn set_blocking(&mut self, enabled: bool) -> Result<(), BackendError>;
+
+    /// Mark the ports that are created by this `MidiOutput` afterwards as private
+    /// (see `MidiInputExt::set_private_ports`). The default is `false`.
+    fn set_private_ports(&mut self, enabled: bool);
+
+    /// Select which ports are listed by `ports` and counted by `port_count`
+    /// (see `MidiInputExt::set_port_filter`).
+    fn set_port_filter(&mut self, filter: PortFilter);
+
+    /// Create a virtual output port (like `VirtualOutput::create_virtual`)
+    /// that is already connected to the given destination `port` when it
+    /// appears, so that there is no time window in which it exists but is not
+    /// connected. Other applications can connect to the virtual port as well
+    /// (unless it is private, see `MidiInputExt::create_virtual_connected`),
+    /// and receive the same messages as `port`. The output mode is ignored,
+    /// since this always requires a subscription.
+    ///
+    /// If the connection to `port` can not be made, an error is returned and
+    /// the virtual port is removed again.
+    fn create_virtual_connected(
+        self, port_name: &str, port: &MidiOutputPort
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> where Self: Sized;
+
+    /// Like `create_virtual_connected`, but connects to the first port whose
+    /// name (as returned by `port_name`) is `target`. Fails with `InvalidPort`
+    /// if no such port exists.
+    fn create_virtual_connected_by_name(
+        self, port_name: &str, target: &str
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> where Self: Sized;
+}
+
+/// Trait that is implemented by `MidiOutputConnection` when using the ALSA backend.
+///
+/// # Scheduled output
+/// Messages can be scheduled for future delivery using an ALSA queue that
+/// is owned by the connection. The queue is allocated and started the first
+/// time `send_scheduled` or `queue_time` is called, and timestamps are given
+/// in microseconds of queue time (i.e. since the queue was started). The
+/// queue is freed when the connection is closed, which discards all messages
+/// that have not yet been delivered.
+#[cfg(not(feature = "jack"))]
+pub trait MidiOutputConnectionExt {
+    /// Get the mode that was selected when the connection was made. This is
+    /// never `OutputMode::Auto`, which is always resolved during `connect`.
+    /// Virtual ports always report `OutputMode::Subscribe`.
+    fn output_mode(&self) -> OutputMode;
+
+    /// Get the address (client id and port number) of the port that midir
+    /// created for this connection (see `MidiInputConnectionExt::virtual_port_addr`).
+    fn virtual_port_addr(&self) -> (i32, i32);
+
+    /// Get the current time of the output queue (in microseconds).
+    fn queue_time(&mut self) -> Result<u64, SendError>;
+
+    /// Schedule a message to be sent when the output queue reaches the given
+    /// `timestamp` (in microseconds). Timestamps in the past cause the message
+    /// to be sent immediately.
+    fn send_scheduled(&mut self, timestamp: u64, message: &[u8]) -> Result<(), SendError>;
+
+    /// Schedule a message to be sent at the given wall-clock `time`, e.g. for
+    /// cue-driven playback. The time is translated to the timebase of the output
+    /// queue using the offset between the system clock and the queue clock at
+    /// the time of the call, and the message is then scheduled like with
+    /// `send_scheduled` (so the schedule transform applies as well). Times in
+    /// the past cause the message to be sent immediately.
+    ///
+    /// The precision is limited by the resolution of the queue timer and the
+    /// time between reading the two clocks (usually a few microseconds). The
+    /// queue does not follow the system clock afterwards: if the system clock
+    /// is adjusted (e.g. by NTP) before the message is sent, it is still sent
+    /// at the originally computed point in time.
+    fn send_at_system_time(&mut self, time: SystemTime, message: &[u8]) -> Result<(), SendError>;
+
+    /// Send several messages at once (e.g. the notes of a chord, or a bank of
+    /// controller values). All messages are put into the output buffer first,
+    /// which is then drained only once, saving system calls compared to sending
+    /// them one by one.
+    ///
+    /// If a message can not be sent, the error is returned together with the
+    /// index of that message. The messages before it have already been sent in
+    /// that case, the ones after it are not sent.
+    fn send_batch(&mut self, messages: &[&[u8]]) -> Result<(), (usize, SendError)>;
+
+    /// Send the complete messages at the start of a stream of bytes (e.g. read
+    /// from a file or a socket), which may end with an incomplete message, and
+    /// return the number of bytes that have been sent. The remaining bytes
+    /// belong to an incomplete message, which the caller should pass again
+    /// once more bytes are available. Running status is supported within the
+    /// stream, but a message that continues after the returned position must
+    /// start with its status byte. System real-time messages must not be
+    /// interleaved with the bytes of an incomplete message at the end.
+    ///
+    /// If a message can not be sent, the error is returned together with the
+    /// number of bytes that have been sent before it (like `send_batch`), so
+    /// that the caller can continue after them without sending any message twice.
+    fn send_stream(&mut self, bytes: &[u8]) -> Result<usize, (usize, SendError)>;
+
+    /// Put a message into the output buffer without draining it, so that many
+    /// messages (e.g. while replaying a dense MIDI file) can be delivered with
+    /// a single system call by calling `flush` afterwards. Messages stay in the
+    /// buffer until then, except that ALSA drains it by itself when it is full.
+    /// If a rate limit is set (see `MidiOutputConnection::set_max_byte_rate`),
+    /// this behaves like `send`.
+    fn send_buffered(&mut self, message: &[u8]) -> Result<(), SendError>;
+
+    /// Deliver all messages that have been put into the output buffer by
+    /// `send_buffered`.
+    fn flush(&mut self) -> Result<(), SendError>;
+
+    /// Schedule messages on the queue of the given input connection, instead
+    /// of an own output queue (which is freed, discarding the messages that are
+    /// scheduled on it). Afterwards, `queue_time` and the timestamps passed to
+    /// `send_scheduled` use the same timebase as the timestamps of `input`:
+    /// a message received at time `t` can be sent at exactly `t + delay`, and
+    /// the round-trip time of a request can be measured without any offset.
+    ///
+    /// The queue stays owned by the input connection. When it is closed, the
+    /// queue is freed and scheduling fails until this is called again with
+    /// another input connection. This fails if timestamping is disabled for
+    /// the input connection, because it has no queue then.
+    fn use_queue_of<T>(&mut self, input: &MidiInputConnection<T>) -> Result<(), SendError>;
+
+    /// Set a function that maps the intended time of each scheduled message to
+    /// the time at which it is actually sent, e.g. to apply swing or to humanize
+    /// a sequence. It is called once per message by `send_scheduled`, but does
+    /// not affect messages sent directly using `send`. Pass `None` to remove it.
+    fn set_schedule_transform(&mut self, transform: Option<Box<dyn FnMut(u64) -> u64 + Send>>);
+
+    /// Block until all messages scheduled using `send_scheduled` have been
+    /// delivered by the output queue, or until the `timeout` has elapsed.
+    /// Returns `true` if the queue is empty and `false` on timeout.
+    ///
+    /// `send` already drains the output buffer, which only hands the events
+    /// over to the sequencer. Scheduled events are then held by the queue until
+    /// their time has come, so this should be called before closing a connection
+    /// (which discards them) at the end of a playback.
+    fn wait_for_drain(&mut self, timeout: Duration) -> Result<bool, SendError>;
+
+    /// Set whether note-off messages are sent when the connection is closed or
+    /// dropped (see `NoteOffPolicy`). The default is `NoteOffPolicy::None`.
+    /// For `NoteOffPolicy::TrackNotes`, only notes sent after this has been
+    /// set are tracked.
+    fn set_note_off_policy(&mut self, policy: NoteOffPolicy);
+
+    /// Set whether messages that are sent immediately (using `send` or
+    /// `send_batch`) go through the output queue, instead of bypassing it.
+    /// This only has an effect once the connection has a queue (i.e. after
+    /// `send_scheduled` or `queue_time` have been used, or after `use_queue_of`).
+    /// The default is `false`.
+    ///
+    /// Bypassing the queue is slightly faster, but a message that is sent now can
+    /// then overtake scheduled messages that are due at the same time. Through
+    /// the queue, all messages form a single stream that is ordered by time:
+    /// an immediate message is delivered after all scheduled messages that are
+    /// due up to now (and after earlier messages with the same time), and before
+    /// all messages that are scheduled for later.
+    fn set_send_through_queue(&mut self, enabled: bool);
+
+    /// Get the size of the largest message (in bytes) that can be sent in one
+    /// piece, so that larger SysEx dumps can be split up beforehand. Sending a
+    /// larger message fails.
+    ///
+    /// The size of the encoding buffer is not a limit, because it grows as
+    /// needed. Instead, the value is derived from the default sizes of the ALSA
+    /// output buffer (16 KiB in `libasound`, which must hold the event together
+    /// with its header) and of the output pool of the client in the kernel (which
+    /// holds scheduled events, in cells of 28 bytes). midir does not change these,
+    /// but the value would change if they were resized. Other clients may impose
+    /// lower limits when receiving large messages.
+    fn max_message_size(&self) -> usize;
+
+    /// Release the memory of the encoding buffer, which grows to the size of
+    /// the largest message that has been sent and keeps that size afterwards.
+    /// Call this after sending a large SysEx dump to return to the initial
+    /// size in a long-running process. The buffer grows again as needed.
+    fn shrink_buffer(&mut self);
+}
+
+
+/// Connects to the specified input port and forwards all incoming messages to
+/// `conn_out`, reproducing their original timing. Each message is scheduled on
+/// the output queue (see `MidiOutputConnectionExt::send_scheduled`) at the same
+/// position relative to the previous messages that it had on the input, shifted
+/// by the given `delay`. This can be used as a software MIDI thru with exact
+/// timing, or with a larger delay as a MIDI delay/echo.
+///
+/// The input and output queues run on the same clock, but are started at
+/// different times. To align them, the input timestamp of the first message is
+/// mapped to the output queue time at which it is forwarded. The total latency
+/// of each message is thus `delay`, plus the time the first message took from
+/// arriving until being handled by the callback. Messages whose scheduled time
+/// has already passed (e.g. because `delay` is zero) are sent immediately.
+///
+/// This requires input timestamps, so it is not useful when timestamping
+/// is disabled for `midi_in`. Output is scheduled in microseconds, so
+/// nanosecond and tick timestamps are disabled on `midi_in`. The output
+/// connection can be retrieved again by closing the returned connection.
+#[cfg(not(feature = "jack"))]
+pub fn connect_timed_thru(
+    mut midi_in: MidiInput, port: &MidiInputPort, port_name: &str, conn_out: MidiOutputConnection, delay: Duration
+) -> Result<MidiInputConnection<MidiOutputConnection>, ConnectError<MidiInput>> {
+    midi_in.set_nanosecond_timestamps(false);
+    midi_in.set_tick_timestamps(None);
+    let delay = delay.as_secs() * 1_000_000 + delay.subsec_micros() as u64;
+    // Difference between output queue time and input timestamps
+    let mut offset: Option<i64> = None;
+    midi_in.connect(port, port_name, move |stamp, message, conn_out| {
+        let offset = match offset {
+            Some(offset) => offset,
+            None => match conn_out.queue_time() {
+                Ok(now) => *offset.get_or_insert(now as i64 - stamp as i64),
+                Err(_) => return
+            }
+        };
+        let target = cmp::max(stamp as i64 + offset, 0) as u64 + delay;
+        let _ = conn_out.send_scheduled(target, message);
+    }, conn_out)
+}
+
+/// Get the epochs (see `MidiInputConnectionExt::timestamp_epoch`) of several
+/// input connections at once, in the same order. Each connection has its own
+/// queue, so the timestamps of different connections are not comparable
+/// directly. This reads the times of all queues back to back and relates them
+/// to a single sample of the monotonic clock, which is taken halfway between
+/// the first and the last read, so the epochs are aligned with each other to
+/// within half the duration of all reads (a few microseconds per connection),
+/// plus the resolution of the queue timers. The timestamps of connection `b`
+/// are then aligned with those of connection `a` by adding `epoch_a - epoch_b`.
+#[cfg(not(feature = "jack"))]
+pub fn timestamp_epochs(connections: &[&dyn MidiInputConnectionExt]) -> Vec<Option<Instant>> {
+    let queue_ids: Vec<Option<i32>> = connections.iter().map(|conn| conn.queue_id()).collect();
+    ::backend::timestamp_epochs(&queue_ids)
+}
+
+/// The track that a connection made by `connect_smf_recorder` records into.
+#[cfg(not(feature = "jack"))]
+pub type SmfRecorder = Arc<Mutex<SmfTrack>>;
+
+/// Connect to the given input port and record all messages into a Standard
+/// MIDI File track, using tick timestamps with the given resolution (see
+/// `MidiInputExt::set_tick_timestamps`, which this enables). The track starts
+/// when the connection is opened and can be taken from the returned recorder
+/// at any time (e.g. `recorder.lock().unwrap().finish(0)`) and written into a
+/// format 0 file with `smf::smf_file(ppq.get(), &chunk)`. See `smf::SmfTrack` for
+/// which messages can be stored. The track starts with a tempo event that
+/// contains the tempo of the input queue (see `MidiInputExt::set_queue_tempo`),
+/// so that the ticks of the file have the same duration as those of the queue.
+#[cfg(not(feature = "jack"))]
+pub fn connect_smf_recorder(
+    mut midi_in: MidiInput, port: &MidiInputPort, port_name: &str, ppq: NonZeroU16
+) -> Result<(MidiInputConnection<()>, SmfRecorder), ConnectError<MidiInput>> {
+    midi_in.set_tick_timestamps(Some(ppq));
+    let mut track = SmfTrack::new();
+    track.push_tempo(0, midi_in.tick_tempo());
+    let recorder = Arc::new(Mutex::new(track));
+    let handler_recorder = recorder.clone();
+    let conn = midi_in.connect(port, port_name, move |stamp, message, _| {
+        if let Ok(mut track) = handler_recorder.lock() {
+            track.push(stamp, message);
+        }
+    }, ())?;
+    Ok((conn, recorder))
+}
+
+/// An input connection without a handler thread, whose messages are retrieved
+/// by calling `poll` (see `connect_nonblocking`).
+#[cfg(not(feature = "jack"))]
+pub struct MidiInputPoller {
+    conn: MidiInputConnection<()>,
+    receiver: Receiver<(u64, Vec<u8>)>
+}
+
+#[cfg(not(feature = "jack"))]
+impl MidiInputPoller {
+    /// Get the next message together with its timestamp, waiting at most for
+    /// `timeout` until one arrives. A timeout of zero never blocks, which is
+    /// useful when this is called from an existing event loop (e.g. once per
+    /// frame, or after one of `poll_descriptors` has become readable).
+    /// Returns `None` if no message has arrived in time.
+    pub fn poll(&mut self, timeout: Duration) -> Option<(u64, Vec<u8>)> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(message) = self.receiver.try_recv() {
+                return Some(message);
+            }
+            let retained = self.conn.process_ready_events();
+            if let Ok(message) = self.receiver.try_recv() {
+                return Some(message);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            let wait = match retained {
+                Some(retained) => cmp::min(deadline - now, retained),
+                None => deadline - now
+            };
+            let mut fds: Vec<_> = self.conn.poll_descriptors().into_iter().map(|fd| {
+                libc::pollfd { fd, events: libc::POLLIN, revents: 0 }
+            }).collect();
+            // Round up, so that waiting for less than a millisecond does not spin
+            let millis = (wait.as_secs() * 1000 + (wait.subsec_nanos() as u64).div_ceil(1_000_000)) as libc::c_int;
+            unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, millis) };
+        }
+    }
+
+    /// Get the file descriptors that become readable when `poll` may have
+    /// something to return, so that they can be added to an existing event loop.
+    pub fn poll_descriptors(&self) -> Vec<RawFd> {
+        self.conn.poll_descriptors()
+    }
+
+    /// Close the connection and get back the `MidiInput` (with external polling
+    /// disabled again). Messages that have not been retrieved are discarded.
+    pub fn close(self) -> MidiInput {
+        let (mut midi_in, _) = self.conn.close();
+        midi_in.set_external_polling(false);
+        midi_in
+    }
+}
+
+/// Connect to the given input port without starting a handler thread, so that
+/// the application can retrieve the messages by polling the returned
+/// `MidiInputPoller`, e.g. from the event loop of a game engine. This uses
+/// external polling (see `MidiInputExt::set_external_polling`), which is enabled
+/// for the given `MidiInput`, and delivers the messages in the same order and
+/// with the same timestamps as the callback of `connect` would. Messages are
+/// only processed while `poll` is called: if it is not called for a long time,
+/// the kernel buffer may overrun.
+#[cfg(not(feature = "jack"))]
+pub fn connect_nonblocking(
+    mut midi_in: MidiInput, port: &MidiInputPort, port_name: &str
+) -> Result<MidiInputPoller, ConnectError<MidiInput>> {
+    midi_in.set_external_polling(true);
+    let (sender, receiver) = channel();
+    let conn = midi_in.connect(port, port_name, move |stamp, message, _| {
+        let _ = sender.send((stamp, message.to_vec()));
+    }, ())?;
+    Ok(MidiInputPoller { conn, receiver })
+}
@@ -0,0 +1,30 @@
+//! Functionality that is specific to the CoreMIDI backend (macOS and iOS).
+
+use ::ConnectError;
+use ::{MidiInputConnection, MidiOutputConnection};
+
+/// Trait that is implemented by `MidiInput` when using the CoreMIDI backend.
+#[cfg(not(feature = "jack"))]
+pub trait MidiInputExt where Self: Sized {
+    /// Creates a virtual input port like `VirtualInput::create_virtual`, but
+    /// gives its endpoint the given `kMIDIPropertyUniqueID`. Other applications
+    /// (such as DAWs) identify ports by this id, so reusing the same id when
+    /// the application is started again lets them restore their connections.
+    /// Fails if the id is already used by another source or destination.
+    fn create_virtual_with_id<F, T: Send + 'static>(
+        self, port_name: &str, unique_id: i32, callback: F, data: T
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where F: FnMut(u64, &[u8], &mut T) + Send + 'static;
+}
+
+/// Trait that is implemented by `MidiOutput` when using the CoreMIDI backend.
+#[cfg(not(feature = "jack"))]
+pub trait MidiOutputExt where Self: Sized {
+    /// Creates a virtual output port like `VirtualOutput::create_virtual`, but
+    /// gives its endpoint the given `kMIDIPropertyUniqueID` (see
+    /// `MidiInputExt::create_virtual_with_id`). Fails if the id is already used
+    /// by another source or destination.
+    fn create_virtual_with_id(
+        self, port_name: &str, unique_id: i32
+    ) -> Result<MidiOutputConnection, ConnectError<Self>>;
+}
@@ -1 +1,3 @@
-#[cfg(unix)] pub mod unix;
\ No newline at end of file
+#[cfg(unix)] pub mod unix;
+#[cfg(target_os = "linux")] pub mod linux;
+#[cfg(any(target_os = "macos", target_os = "ios"))] pub mod macos;
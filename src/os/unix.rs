@@ -1,5 +1,8 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use ::ConnectError;
-use ::{MidiInputConnection, MidiOutputConnection};
+use ::{MidiInput, MidiInputConnection, MidiOutputConnection};
 
 // TODO: maybe move to module `virtual` instead of `os::unix`?
 
@@ -24,4 +27,43 @@ pub trait VirtualOutput where Self: Sized {
     fn create_virtual(
         self, port_name: &str
     ) -> Result<MidiOutputConnection, ConnectError<Self>>;
-}
\ No newline at end of file
+}
+
+/// Counts the data that has been received by a dry virtual port (see `create_dry_virtual`).
+#[derive(Debug, Default)]
+pub struct DryPortCounter {
+    messages: AtomicU64,
+    bytes: AtomicU64
+}
+
+impl DryPortCounter {
+    /// Get the number of messages that have been received.
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// Get the total number of bytes of all messages that have been received.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Creates a virtual input port that other applications can connect to,
+/// but which discards all messages it receives, only counting them. This is
+/// meant for testing and diagnostics, e.g. to verify that another application
+/// actually sends MIDI, or to check its routing, without writing a callback.
+///
+/// The returned counter can be read while the port exists (it is updated
+/// from the input handler thread). The port is removed when the returned
+/// connection is closed or dropped.
+pub fn create_dry_virtual(
+    midi_in: MidiInput, port_name: &str
+) -> Result<(MidiInputConnection<()>, Arc<DryPortCounter>), ConnectError<MidiInput>> {
+    let counter = Arc::new(DryPortCounter::default());
+    let handler_counter = counter.clone();
+    let conn = midi_in.create_virtual(port_name, move |_, message, _| {
+        handler_counter.messages.fetch_add(1, Ordering::Relaxed);
+        handler_counter.bytes.fetch_add(message.len() as u64, Ordering::Relaxed);
+    }, ())?;
+    Ok((conn, counter))
+}
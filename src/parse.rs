@@ -0,0 +1,193 @@
+//! A typed representation of the messages that are passed to input callbacks.
+//!
+//! Callbacks receive the raw bytes of each message, which stays the primary
+//! API. This module offers an optional layer on top, for applications that
+//! would otherwise have to decode status bytes themselves:
+//!
+//! ```
+//! use midir::parse::LiveEvent;
+//!
+//! match LiveEvent::from_bytes(&[0x91, 60, 100]) {
+//!     Ok(LiveEvent::NoteOn { channel, key, velocity }) => {
+//!         assert_eq!((channel, key, velocity), (1, 60, 100));
+//!     },
+//!     _ => unreachable!()
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+/// A single MIDI message, as received by an input callback.
+///
+/// Channels are numbered from 0 to 15 (i.e. channel 1 in the terminology of
+/// most devices is 0). A *Note On* message with a velocity of zero is reported
+/// as `NoteOff` (with a velocity of 64), because that is what it means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiveEvent<'a> {
+    /// *Note Off* (`0x8n`).
+    NoteOff { channel: u8, key: u8, velocity: u8 },
+    /// *Note On* (`0x9n`) with a nonzero velocity.
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    /// *Polyphonic Key Pressure* (`0xAn`).
+    PolyPressure { channel: u8, key: u8, pressure: u8 },
+    /// *Control Change* (`0xBn`), including Channel Mode messages (controllers 120 to 127).
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    /// *Program Change* (`0xCn`).
+    ProgramChange { channel: u8, program: u8 },
+    /// *Channel Pressure* (`0xDn`).
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// *Pitch Bend Change* (`0xEn`), relative to the center (from -8192 to 8191).
+    PitchBend { channel: u8, value: i16 },
+    /// A System Exclusive message, including the leading `0xF0` and the trailing `0xF7`.
+    SysEx(&'a [u8]),
+    /// *MIDI Time Code Quarter Frame* (`0xF1`).
+    TimeCodeQuarterFrame(u8),
+    /// *Song Position Pointer* (`0xF2`), in MIDI beats (sixteenth notes).
+    SongPosition(u16),
+    /// *Song Select* (`0xF3`).
+    SongSelect(u8),
+    /// *Tune Request* (`0xF6`).
+    TuneRequest,
+    /// *Timing Clock* (`0xF8`).
+    Clock,
+    /// *Start* (`0xFA`).
+    Start,
+    /// *Continue* (`0xFB`).
+    Continue,
+    /// *Stop* (`0xFC`).
+    Stop,
+    /// *Active Sensing* (`0xFE`).
+    ActiveSensing,
+    /// *System Reset* (`0xFF`).
+    Reset,
+}
+
+impl<'a> LiveEvent<'a> {
+    /// Parses the bytes of a single, complete message.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<LiveEvent<'a>, ParseError> {
+        let status = match bytes.first() {
+            Some(&status) if status & 0x80 != 0 => status,
+            Some(_) => return Err(ParseError::MissingStatus),
+            None => return Err(ParseError::Empty)
+        };
+
+        if status == 0xF0 {
+            if bytes.last() != Some(&0xF7) || bytes.len() < 2 {
+                return Err(ParseError::Incomplete);
+            }
+            if bytes[1..bytes.len() - 1].iter().any(|&b| b & 0x80 != 0) {
+                return Err(ParseError::InvalidData);
+            }
+            return Ok(LiveEvent::SysEx(bytes));
+        }
+
+        let len = match status {
+            0x80..=0xBF | 0xE0..=0xEF | 0xF2 => 3,
+            0xC0..=0xDF | 0xF1 | 0xF3 => 2,
+            0xF4 | 0xF5 | 0xF7 | 0xF9 | 0xFD => return Err(ParseError::UndefinedStatus(status)),
+            _ => 1
+        };
+        if bytes.len() < len {
+            return Err(ParseError::Incomplete);
+        }
+        if bytes.len() > len {
+            return Err(ParseError::TooLong);
+        }
+        if bytes[1..].iter().any(|&b| b & 0x80 != 0) {
+            return Err(ParseError::InvalidData);
+        }
+
+        let channel = status & 0x0F;
+        Ok(match status {
+            0x80..=0x8F => LiveEvent::NoteOff { channel, key: bytes[1], velocity: bytes[2] },
+            0x90..=0x9F if bytes[2] == 0 => LiveEvent::NoteOff { channel, key: bytes[1], velocity: 64 },
+            0x90..=0x9F => LiveEvent::NoteOn { channel, key: bytes[1], velocity: bytes[2] },
+            0xA0..=0xAF => LiveEvent::PolyPressure { channel, key: bytes[1], pressure: bytes[2] },
+            0xB0..=0xBF => LiveEvent::ControlChange { channel, controller: bytes[1], value: bytes[2] },
+            0xC0..=0xCF => LiveEvent::ProgramChange { channel, program: bytes[1] },
+            0xD0..=0xDF => LiveEvent::ChannelPressure { channel, pressure: bytes[1] },
+            0xE0..=0xEF => LiveEvent::PitchBend { channel, value: ((bytes[2] as i16) << 7 | bytes[1] as i16) - 0x2000 },
+            0xF1 => LiveEvent::TimeCodeQuarterFrame(bytes[1]),
+            0xF2 => LiveEvent::SongPosition((bytes[2] as u16) << 7 | bytes[1] as u16),
+            0xF3 => LiveEvent::SongSelect(bytes[1]),
+            0xF6 => LiveEvent::TuneRequest,
+            0xF8 => LiveEvent::Clock,
+            0xFA => LiveEvent::Start,
+            0xFB => LiveEvent::Continue,
+            0xFC => LiveEvent::Stop,
+            0xFE => LiveEvent::ActiveSensing,
+            _ => LiveEvent::Reset
+        })
+    }
+}
+
+/// An error that can occur when parsing a message with `LiveEvent::from_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// There are no bytes at all.
+    Empty,
+    /// The first byte is not a status byte.
+    MissingStatus,
+    /// The status byte is undefined (or an *End of Exclusive* without a preceding SysEx).
+    UndefinedStatus(u8),
+    /// There are fewer data bytes than the status byte requires, or a SysEx
+    /// message is not terminated.
+    Incomplete,
+    /// There are more bytes than the status byte requires (e.g. because
+    /// several messages have been passed at once).
+    TooLong,
+    /// A data byte has its most significant bit set.
+    InvalidData,
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Empty => "message is empty".fmt(f),
+            ParseError::MissingStatus => "message does not start with a status byte".fmt(f),
+            ParseError::UndefinedStatus(status) => write!(f, "undefined status byte 0x{:02X}", status),
+            ParseError::Incomplete => "message is incomplete".fmt(f),
+            ParseError::TooLong => "message is longer than its status byte requires".fmt(f),
+            ParseError::InvalidData => "message contains an invalid data byte".fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_messages() {
+        assert_eq!(LiveEvent::from_bytes(&[0x90, 60, 100]), Ok(LiveEvent::NoteOn { channel: 0, key: 60, velocity: 100 }));
+        assert_eq!(LiveEvent::from_bytes(&[0x9F, 60, 0]), Ok(LiveEvent::NoteOff { channel: 15, key: 60, velocity: 64 }));
+        assert_eq!(LiveEvent::from_bytes(&[0xB2, 7, 127]), Ok(LiveEvent::ControlChange { channel: 2, controller: 7, value: 127 }));
+        assert_eq!(LiveEvent::from_bytes(&[0xC0, 5]), Ok(LiveEvent::ProgramChange { channel: 0, program: 5 }));
+        assert_eq!(LiveEvent::from_bytes(&[0xE0, 0x00, 0x40]), Ok(LiveEvent::PitchBend { channel: 0, value: 0 }));
+        assert_eq!(LiveEvent::from_bytes(&[0xE0, 0x00, 0x00]), Ok(LiveEvent::PitchBend { channel: 0, value: -8192 }));
+        assert_eq!(LiveEvent::from_bytes(&[0xE0, 0x7F, 0x7F]), Ok(LiveEvent::PitchBend { channel: 0, value: 8191 }));
+    }
+
+    #[test]
+    fn system_messages() {
+        let sysex = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+        assert_eq!(LiveEvent::from_bytes(&sysex), Ok(LiveEvent::SysEx(&sysex)));
+        assert_eq!(LiveEvent::from_bytes(&[0xF2, 0x01, 0x01]), Ok(LiveEvent::SongPosition(129)));
+        assert_eq!(LiveEvent::from_bytes(&[0xF8]), Ok(LiveEvent::Clock));
+        assert_eq!(LiveEvent::from_bytes(&[0xFF]), Ok(LiveEvent::Reset));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(LiveEvent::from_bytes(&[]), Err(ParseError::Empty));
+        assert_eq!(LiveEvent::from_bytes(&[60, 100]), Err(ParseError::MissingStatus));
+        assert_eq!(LiveEvent::from_bytes(&[0xF4]), Err(ParseError::UndefinedStatus(0xF4)));
+        assert_eq!(LiveEvent::from_bytes(&[0x90, 60]), Err(ParseError::Incomplete));
+        assert_eq!(LiveEvent::from_bytes(&[0xF0, 0x7E]), Err(ParseError::Incomplete));
+        assert_eq!(LiveEvent::from_bytes(&[0x90, 60, 100, 0x80]), Err(ParseError::TooLong));
+        assert_eq!(LiveEvent::from_bytes(&[0x90, 60, 0x80]), Err(ParseError::InvalidData));
+    }
+}
@@ -0,0 +1,86 @@
+//! Support for callbacks that must not allocate memory, e.g. because they
+//! run on (or hand data to) a real-time audio thread, where an allocation
+//! could block and cause priority inversion.
+//!
+//! Allocations are detected by `CheckedAllocator`, which has to be installed
+//! as the global allocator of the application (a library can not do that):
+//!
+//! ```ignore
+//! use std::alloc::System;
+//! use midir::realtime::CheckedAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CheckedAllocator<System> = CheckedAllocator(System);
+//! ```
+//!
+//! Then, any allocation (or deallocation) in a callback that was passed to
+//! `MidiInput::connect_realtime` aborts the process with an error message.
+//! This is only enforced in debug builds: in release builds, `CheckedAllocator`
+//! directly forwards to the wrapped allocator and `connect_realtime` behaves
+//! exactly like `connect`, so there is no overhead.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+use std::io::{stderr, Write};
+use std::process;
+
+thread_local! {
+    static FORBID_ALLOCATIONS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A global allocator that wraps another one (usually `std::alloc::System`)
+/// and aborts when memory is allocated inside a real-time callback (in debug builds).
+pub struct CheckedAllocator<A>(pub A);
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CheckedAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        check_allocation();
+        self.0.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        check_allocation();
+        self.0.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        check_allocation();
+        self.0.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        check_allocation();
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
+
+fn check_allocation() {
+    if !cfg!(debug_assertions) { return; }
+    // This fails while the thread is being destroyed, but then no callback is running anyway.
+    // The flag is reset first, so that writing the error message may allocate.
+    if FORBID_ALLOCATIONS.try_with(|f| f.replace(false)).unwrap_or(false) {
+        let _ = writeln!(stderr(), "midir: memory was allocated in a real-time callback");
+        // Unwinding out of an allocator is not allowed, so panicking is not an option
+        process::abort();
+    }
+}
+
+/// Resets the flag when the callback returns or panics.
+struct ForbidGuard;
+
+impl Drop for ForbidGuard {
+    fn drop(&mut self) {
+        let _ = FORBID_ALLOCATIONS.try_with(|f| f.set(false));
+    }
+}
+
+/// Calls `f`, treating all allocations that happen in the meantime as errors
+/// (only in debug builds, and only if `CheckedAllocator` is installed).
+pub(crate) fn forbid_allocations<F, R>(f: F) -> R where F: FnOnce() -> R {
+    if !cfg!(debug_assertions) {
+        return f();
+    }
+    FORBID_ALLOCATIONS.with(|flag| flag.set(true));
+    let _guard = ForbidGuard;
+    f()
+}
@@ -0,0 +1,135 @@
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The direction of an open connection (see `ConnectionInfo`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionDirection {
+    /// A `MidiInputConnection`.
+    Input,
+    /// A `MidiOutputConnection`.
+    Output
+}
+
+/// Information about a connection that is currently open, as returned by `open_connections`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// A number that identifies the connection within the process.
+    /// Numbers are assigned in the order in which connections are opened.
+    pub id: u64,
+    /// Whether this is an input or an output connection.
+    pub direction: ConnectionDirection,
+    /// The name of the port that the connection was made to (as returned by
+    /// `port_name`), or `None` for virtual ports that are not connected to one.
+    pub port: Option<String>,
+    /// Whether the connection is a virtual port that other applications can
+    /// connect to (created by `create_virtual` or `create_virtual_connected`).
+    pub is_virtual: bool,
+    /// The name that was assigned to the connection itself (the `port_name`
+    /// parameter of `connect` or `create_virtual`).
+    pub name: String,
+    /// The address (client id and port number) of the port that *midir* has
+    /// created for the connection, which other applications see. This is only
+    /// available with the ALSA backend, and `None` otherwise.
+    pub address: Option<(i32, i32)>,
+    /// The label that has been attached to the connection using `set_label`.
+    pub label: Option<String>
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static CONNECTIONS: Mutex<Vec<ConnectionInfo>> = Mutex::new(Vec::new());
+
+fn connections() -> MutexGuard<'static, Vec<ConnectionInfo>> {
+    // The list stays consistent even if a thread panicked while holding the lock
+    CONNECTIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Get a list of all connections that have been opened by this process (using
+/// any `MidiInput` or `MidiOutput`) and have not yet been closed or dropped,
+/// in the order in which they were opened. This can be used to find connections
+/// that are unintentionally kept alive, e.g. in long-running hosts.
+///
+/// The list is maintained for every connection. It is protected by a lock that
+/// is taken when a connection is opened, closed or labeled, and when calling
+/// this function, so there is no overhead when sending or receiving messages.
+/// This function can be called from any thread.
+pub fn open_connections() -> Vec<ConnectionInfo> {
+    connections().clone()
+}
+
+/// Get a list of the virtual ports that have been created by this process
+/// (using `create_virtual` or `create_virtual_connected`) and are still open,
+/// i.e. the subset of `open_connections` whose `is_virtual` is set. This allows to
+/// present the ports that an application exposes separately from those of
+/// other devices and applications.
+pub fn virtual_ports() -> Vec<ConnectionInfo> {
+    connections().iter().filter(|info| info.is_virtual).cloned().collect()
+}
+
+/// Keeps a connection in the list of open connections until it is dropped.
+pub(crate) struct Registration {
+    id: u64
+}
+
+impl Registration {
+    pub(crate) fn new(direction: ConnectionDirection, port: Option<String>, name: &str, address: Option<(i32, i32)>, is_virtual: bool) -> Registration {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        connections().push(ConnectionInfo {
+            id,
+            direction,
+            port,
+            is_virtual,
+            name: name.to_string(),
+            address,
+            label: None
+        });
+        Registration { id }
+    }
+
+    /// Updates the port of a connection that has been moved to another source.
+    #[allow(dead_code)] // only used by the ALSA backend
+    pub(crate) fn set_source(&self, port: String) {
+        if let Some(info) = connections().iter_mut().find(|info| info.id == self.id) {
+            info.port = Some(port);
+        }
+    }
+
+    pub(crate) fn set_label(&self, label: Option<String>) {
+        if let Some(info) = connections().iter_mut().find(|info| info.id == self.id) {
+            info.label = label;
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let id = self.id;
+        connections().retain(|info| info.id != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registration() {
+        let input = Registration::new(ConnectionDirection::Input, Some("Device".to_string()), "in", None, false);
+        let output = Registration::new(ConnectionDirection::Output, None, "out", Some((128, 0)), true);
+        output.set_label(Some("label".to_string()));
+
+        let find = |id| open_connections().into_iter().find(|info| info.id == id);
+        assert_eq!(find(input.id).map(|info| info.port), Some(Some("Device".to_string())));
+        assert_eq!(find(output.id).map(|info| info.label), Some(Some("label".to_string())));
+
+        let id = input.id;
+        drop(input);
+        assert_eq!(find(id), None);
+        assert!(find(output.id).is_some());
+        assert!(virtual_ports().iter().any(|info| info.id == output.id && info.address == Some((128, 0))));
+
+        // A virtual port that is connected to a target is still listed
+        let connected = Registration::new(ConnectionDirection::Output, Some("Synth".to_string()), "thru", None, true);
+        connected.set_source("Other synth".to_string());
+        assert!(virtual_ports().iter().any(|info| info.id == connected.id && info.port == Some("Other synth".to_string())));
+    }
+}
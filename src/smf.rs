@@ -0,0 +1,189 @@
+//! Recording of MIDI messages into Standard MIDI File (SMF) tracks.
+
+use std::cmp;
+
+/// Collects MIDI messages with timestamps in ticks and encodes them as the
+/// track of a Standard MIDI File, e.g. to record incoming messages (see
+/// `os::linux::connect_smf_recorder` for a recorder that uses ALSA tick timestamps).
+///
+/// Channel messages are written using running status, and System Exclusive
+/// messages as `F0` events. System real-time and system common messages
+/// (e.g. MIDI clock) can not be stored in a MIDI file and are skipped, and so
+/// are incomplete messages. The only meta events are tempo changes (see
+/// `push_tempo`) and the End of Track event that `finish` appends, so without a
+/// tempo event the track relies on the default tempo of 120 BPM. A track can be
+/// used on its own in a format 0 file (see `smf_file`).
+#[derive(Debug, Clone, Default)]
+pub struct SmfTrack {
+    events: Vec<u8>,
+    last_tick: u64,
+    running_status: Option<u8>
+}
+
+impl SmfTrack {
+    /// Creates an empty track, whose time starts at tick zero.
+    pub fn new() -> SmfTrack {
+        SmfTrack::default()
+    }
+
+    /// Returns whether no events have been recorded since the track has been created or finished.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Append the given bytes, which may contain several messages, at the time
+    /// `tick` (in ticks since the start of the track). The delta time of each
+    /// event is computed from the tick of the previous one. Timestamps that go
+    /// back in time are treated as if they were equal to the previous one.
+    pub fn push(&mut self, tick: u64, bytes: &[u8]) {
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let status = bytes[pos];
+            let len = match status {
+                0xF0 => bytes.len() - pos, // a SysEx message extends to the end
+                0x80..=0xBF | 0xE0..=0xEF => 3,
+                0xC0..=0xDF => 2,
+                0xF1 | 0xF3 => 2,
+                0xF2 => 3,
+                0xF4..=0xFF => 1,
+                _ => 1 // a data byte without status, which is skipped
+            };
+            let message = &bytes[pos..cmp::min(pos + len, bytes.len())];
+            pos += len;
+            if message.len() == len {
+                self.push_message(tick, message);
+            }
+        }
+    }
+
+    /// Append a Set Tempo meta event at the time `tick`, which sets the tempo
+    /// (in microseconds per quarter note) from then on. Only the lower 24 bits
+    /// of `tempo` can be stored.
+    pub fn push_tempo(&mut self, tick: u64, tempo: u32) {
+        self.push_delta(tick);
+        self.events.extend_from_slice(&[0xFF, 0x51, 0x03]);
+        self.events.extend_from_slice(&tempo.to_be_bytes()[1..]);
+        self.running_status = None;
+    }
+
+    fn push_message(&mut self, tick: u64, message: &[u8]) {
+        match message[0] {
+            0x80..=0xEF => {
+                self.push_delta(tick);
+                if self.running_status != Some(message[0]) {
+                    self.events.push(message[0]);
+                    self.running_status = Some(message[0]);
+                }
+                self.events.extend_from_slice(&message[1..]);
+            },
+            0xF0 => {
+                self.push_delta(tick);
+                self.events.push(0xF0);
+                write_variable_length(&mut self.events, (message.len() - 1) as u64);
+                self.events.extend_from_slice(&message[1..]);
+                self.running_status = None;
+            },
+            _ => {} // can not be stored in a MIDI file
+        }
+    }
+
+    fn push_delta(&mut self, tick: u64) {
+        let tick = if tick > self.last_tick { tick } else { self.last_tick };
+        write_variable_length(&mut self.events, tick - self.last_tick);
+        self.last_tick = tick;
+    }
+
+    /// Take the recorded events as a complete track chunk (`MTrk`), ending
+    /// at the time `end_tick` (or at the last event, if that is later). The
+    /// track is then empty again, and the next event is recorded relative to
+    /// `end_tick`, so that the chunks of consecutive calls can be played one
+    /// after another. Pass `0` to end the track right after its last event.
+    pub fn finish(&mut self, end_tick: u64) -> Vec<u8> {
+        self.push_delta(end_tick);
+        self.events.extend_from_slice(&[0xFF, 0x2F, 0x00]); // End of Track
+        self.running_status = None;
+
+        let mut chunk = Vec::with_capacity(self.events.len() + 8);
+        chunk.extend_from_slice(b"MTrk");
+        chunk.extend_from_slice(&(self.events.len() as u32).to_be_bytes());
+        chunk.append(&mut self.events);
+        chunk
+    }
+}
+
+/// Create a complete format 0 Standard MIDI File with the given resolution
+/// (in ticks per quarter note) from a single track chunk (see `SmfTrack::finish`).
+pub fn smf_file(ppq: u16, track: &[u8]) -> Vec<u8> {
+    let mut file = Vec::with_capacity(track.len() + 14);
+    file.extend_from_slice(b"MThd");
+    file.extend_from_slice(&6u32.to_be_bytes());
+    file.extend_from_slice(&0u16.to_be_bytes()); // format 0
+    file.extend_from_slice(&1u16.to_be_bytes()); // one track
+    file.extend_from_slice(&(ppq & 0x7FFF).to_be_bytes());
+    file.extend_from_slice(track);
+    file
+}
+
+/// Writes a variable-length quantity, as used for delta times and lengths in MIDI files.
+fn write_variable_length(buffer: &mut Vec<u8>, value: u64) {
+    let value = if value > 0x0FFF_FFFF { 0x0FFF_FFFF } else { value }; // at most four bytes
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        buffer.push(0x80 | (value >> shift) as u8 & 0x7F);
+        shift -= 7;
+    }
+    buffer.push(value as u8 & 0x7F);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variable_length() {
+        let encode = |value| { let mut buffer = Vec::new(); write_variable_length(&mut buffer, value); buffer };
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(0x7F), vec![0x7F]);
+        assert_eq!(encode(0x80), vec![0x81, 0x00]);
+        assert_eq!(encode(0x3FFF), vec![0xFF, 0x7F]);
+        assert_eq!(encode(0x0FFF_FFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn track() {
+        let mut track = SmfTrack::new();
+        track.push(0, &[0x90, 60, 100]);
+        track.push(10, &[0x90, 64, 100, 0x90, 67, 100]); // running status
+        track.push(20, &[0xF8]); // skipped
+        track.push(240, &[0xF0, 0x7E, 0x7F, 0xF7]);
+        track.push(200, &[0x80, 60, 0]); // back in time
+        assert_eq!(track.finish(300), vec![
+            b'M', b'T', b'r', b'k', 0, 0, 0, 25,
+            0x00, 0x90, 60, 100,
+            0x0A, 64, 100,
+            0x00, 67, 100,
+            0x81, 0x66, 0xF0, 0x03, 0x7E, 0x7F, 0xF7,
+            0x00, 0x80, 60, 0,
+            0x3C, 0xFF, 0x2F, 0x00
+        ]);
+        assert!(track.is_empty());
+    }
+
+    #[test]
+    fn tempo() {
+        let mut track = SmfTrack::new();
+        track.push(0, &[0x90, 60, 100]);
+        track.push_tempo(0, 600_000);
+        track.push(0, &[0x90, 64, 100]); // the tempo event cancels running status
+        assert_eq!(track.finish(0), vec![
+            b'M', b'T', b'r', b'k', 0, 0, 0, 19,
+            0x00, 0x90, 60, 100,
+            0x00, 0xFF, 0x51, 0x03, 0x09, 0x27, 0xC0,
+            0x00, 0x90, 64, 100,
+            0x00, 0xFF, 0x2F, 0x00
+        ]);
+    }
+}